@@ -0,0 +1,101 @@
+use redis::{AsyncConnectionConfig, PushInfo, PushKind, RedisResult, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Local copy of the queue hashes read by `get_queue_attributes`. It relies on RESP3 client side caching (CLIENT
+/// TRACKING), so Redis notifies the connection when any of the cached keys is modified and the entry is dropped.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct AttributesCache(Arc<Mutex<CacheState>>);
+
+/// The numeric fields of a queue hash, and its maxrc and dlq fields
+pub(crate) type CachedAttributes = (Vec<Option<i64>>, (Option<String>, Option<String>));
+
+#[derive(Debug, Default)]
+struct CacheState {
+    // Incremented on every invalidation, so a read that raced with one doesn't store stale values
+    generation: u64,
+    entries: HashMap<String, CachedAttributes>,
+}
+
+impl AttributesCache {
+    pub fn generation(&self) -> u64 {
+        self.0.lock().map(|state| state.generation).unwrap_or(0)
+    }
+
+    pub fn get(&self, key: &str) -> Option<CachedAttributes> {
+        self.0
+            .lock()
+            .ok()
+            .and_then(|state| state.entries.get(key).cloned())
+    }
+
+    /// Stores the value only if there wasn't any invalidation since `generation` was read
+    pub fn insert(&self, key: String, value: CachedAttributes, generation: u64) {
+        if let Ok(mut state) = self.0.lock() {
+            if state.generation == generation {
+                state.entries.insert(key, value);
+            }
+        }
+    }
+
+    fn invalidate(&self, info: PushInfo) {
+        let mut state = match self.0.lock() {
+            Ok(state) => state,
+            Err(_) => return,
+        };
+
+        match info.kind {
+            PushKind::Invalidate => {
+                state.generation += 1;
+
+                for value in info.data {
+                    match value {
+                        Value::Array(keys) => {
+                            for key in keys {
+                                if let Ok(key) = redis::from_redis_value::<String>(&key) {
+                                    state.entries.remove(&key);
+                                }
+                            }
+                        }
+                        // A null invalidation means that Redis flushed the whole tracking table
+                        _ => state.entries.clear(),
+                    }
+                }
+            }
+            // Invalidations can be lost while the connection is down, so nothing cached can be trusted anymore
+            PushKind::Disconnection => {
+                state.generation += 1;
+                state.entries.clear();
+            }
+            _ => {}
+        }
+    }
+
+    /// Connection config that sends the tracking invalidations to this cache
+    fn connection_config(&self) -> AsyncConnectionConfig {
+        let cache = self.clone();
+
+        AsyncConnectionConfig::new().set_push_sender(move |info: PushInfo| {
+            cache.invalidate(info);
+            Ok::<(), ()>(())
+        })
+    }
+
+    /// Opens a new connection with the tracking enabled and the invalidations sent to this cache
+    pub async fn tracked_connection(
+        &self,
+        client: &redis::Client,
+    ) -> RedisResult<redis::aio::MultiplexedConnection> {
+        let mut connection = client
+            .get_multiplexed_async_connection_with_config(&self.connection_config())
+            .await?;
+
+        redis::cmd("CLIENT")
+            .arg("TRACKING")
+            .arg("ON")
+            .query_async::<()>(&mut connection)
+            .await?;
+
+        Ok(connection)
+    }
+}
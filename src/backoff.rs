@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+/// Poll interval for consumer loops. The interval grows (multiplied by `multiplier`, up to `max`) on every empty
+/// receive and goes back to `min` when a message arrives, so idle consumers don't hammer Redis but pick up new work
+/// quickly.
+///
+/// ```rust,ignore
+/// let mut backoff = PollBackoff::default();
+///
+/// loop {
+///     match rsmq.receive_message::<String>("myqueue", None).await? {
+///         Some(message) => {
+///             backoff.reset();
+///             // process the message
+///         }
+///         None => tokio::time::sleep(backoff.next_interval()).await,
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct PollBackoff {
+    min: Duration,
+    max: Duration,
+    multiplier: f64,
+    current: Duration,
+}
+
+impl PollBackoff {
+    /// Creates a new backoff. `max` is raised to `min` if it is smaller and a `multiplier` lower than 1 (or not a
+    /// number) is treated as 1 (fixed interval).
+    pub fn new(min: Duration, max: Duration, multiplier: f64) -> PollBackoff {
+        let multiplier = if multiplier.is_finite() && multiplier >= 1.0 {
+            multiplier
+        } else {
+            1.0
+        };
+
+        PollBackoff {
+            min,
+            max: max.max(min),
+            multiplier,
+            current: min,
+        }
+    }
+
+    /// Returns how long to wait after an empty receive and increases the interval for the next one
+    pub fn next_interval(&mut self) -> Duration {
+        let interval = self.current;
+
+        self.current = Duration::try_from_secs_f64(self.current.as_secs_f64() * self.multiplier)
+            .unwrap_or(self.max)
+            .min(self.max);
+
+        interval
+    }
+
+    /// Goes back to the minimum interval. Call it when a message is received
+    pub fn reset(&mut self) {
+        self.current = self.min;
+    }
+
+    /// Interval that the next call to "next_interval" will return
+    pub fn current(&self) -> Duration {
+        self.current
+    }
+}
+
+impl Default for PollBackoff {
+    /// From 100 milliseconds up to 5 seconds, doubling on each empty receive
+    fn default() -> PollBackoff {
+        PollBackoff::new(Duration::from_millis(100), Duration::from_secs(5), 2.0)
+    }
+}
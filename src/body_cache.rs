@@ -0,0 +1,79 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+/// Local copy of the last message bodies received, by message id, so a message received again (after its visibility
+/// timeout expired, for example) doesn't need its body to be sent again by Redis. Bodies never change once the
+/// message is sent, so the entries only need to be dropped when the message is deleted. The least recently used
+/// entry is dropped when the cache is full.
+#[derive(Debug, Clone)]
+pub(crate) struct BodyCache(Arc<Mutex<CacheState>>);
+
+#[derive(Debug)]
+struct CacheState {
+    capacity: usize,
+    // Body of each message, with the last time it was used
+    bodies: HashMap<String, (u64, Vec<u8>)>,
+    // Message ids by the last time they were used, least recently used first
+    order: BTreeMap<u64, String>,
+    // Increased on every use, so it works as a clock for the order of use
+    clock: u64,
+}
+
+impl BodyCache {
+    pub fn new(capacity: usize) -> BodyCache {
+        BodyCache(Arc::new(Mutex::new(CacheState {
+            capacity,
+            bodies: HashMap::new(),
+            order: BTreeMap::new(),
+            clock: 0,
+        })))
+    }
+
+    pub fn get(&self, id: &str) -> Option<Vec<u8>> {
+        let mut state = self.0.lock().ok()?;
+        let state = &mut *state;
+
+        let (used, body) = state.bodies.get_mut(id)?;
+
+        state.order.remove(used);
+        state.clock += 1;
+        *used = state.clock;
+        state.order.insert(state.clock, id.to_string());
+
+        Some(body.clone())
+    }
+
+    pub fn insert(&self, id: &str, body: &[u8]) {
+        let mut state = match self.0.lock() {
+            Ok(state) => state,
+            Err(_) => return,
+        };
+
+        if state.capacity == 0 {
+            return;
+        }
+
+        state.clock += 1;
+        let clock = state.clock;
+
+        if let Some((used, _)) = state.bodies.insert(id.to_string(), (clock, body.to_vec())) {
+            state.order.remove(&used);
+        }
+
+        state.order.insert(clock, id.to_string());
+
+        while state.order.len() > state.capacity {
+            if let Some((_, evicted)) = state.order.pop_first() {
+                state.bodies.remove(&evicted);
+            }
+        }
+    }
+
+    pub fn remove(&self, id: &str) {
+        if let Ok(mut state) = self.0.lock() {
+            if let Some((used, _)) = state.bodies.remove(id) {
+                state.order.remove(&used);
+            }
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use std::borrow::Cow;
+use std::io::{Read, Write};
+
+/// Prefix of the compressed messages. Messages without it are returned as they were stored
+const HEADER: &[u8] = b"\0rsmq-deflate\0";
+
+/// Compresses the message if it is bigger than `threshold`. If compressing doesn't make it smaller, it is stored raw
+pub(crate) fn compress(body: &[u8], threshold: usize) -> Cow<'_, [u8]> {
+    if body.len() <= threshold {
+        return Cow::Borrowed(body);
+    }
+
+    let mut encoder = DeflateEncoder::new(HEADER.to_vec(), Compression::default());
+
+    match encoder.write_all(body).and_then(|_| encoder.finish()) {
+        Ok(compressed) if compressed.len() < body.len() => Cow::Owned(compressed),
+        _ => Cow::Borrowed(body),
+    }
+}
+
+/// Decompresses the message if it has the compression header. Returns the original bytes if it cannot be decompressed
+pub(crate) fn decompress(body: Vec<u8>) -> Result<Vec<u8>, Vec<u8>> {
+    if !body.starts_with(HEADER) {
+        return Ok(body);
+    }
+
+    let mut decompressed = Vec::new();
+
+    match DeflateDecoder::new(&body[HEADER.len()..]).read_to_end(&mut decompressed) {
+        Ok(_) => Ok(decompressed),
+        Err(_) => Err(body),
+    }
+}
@@ -1,5 +1,5 @@
 use bb8::RunError;
-use redis::RedisError;
+use redis::{ErrorKind, RedisError};
 use thiserror::Error as ThisError;
 
 /// This is an alias of `Result<T, RsmqError>` for simplicity
@@ -12,7 +12,9 @@ pub enum RsmqError {
     #[error("Pool run error: `{0:?}`")]
     RunError(#[from] RunError<RedisError>),
     #[error("Redis error: `{0:?}`")]
-    RedisError(#[from] RedisError),
+    RedisError(RedisError),
+    #[error("Redis is a read-only replica (probably during a failover), the operation can be retried: `{0:?}`")]
+    ReadOnlyReplica(RedisError),
     #[error("No connection acquired`")]
     NoConnectionAcquired,
     #[error("No attribute was supplied")]
@@ -21,14 +23,25 @@ pub enum RsmqError {
     MissingParameter(String),
     #[error("Invalid `{0:?} format`")]
     InvalidFormat(String),
-    #[error("{0:?} must be between {0:?} and {0:?}")]
-    InvalidValue(String, String, String),
+    #[error("`{name}` must be between {min} and {max}, but it is {value}")]
+    InvalidParameter {
+        name: &'static str,
+        value: String,
+        min: String,
+        max: String,
+    },
+    #[error(
+        "maxsize must be between 1024 and {max} bytes, or -1 for unlimited, but it is {value}"
+    )]
+    InvalidMaxSize { value: u32, max: i32 },
     #[error("Message not string")]
     MessageNotString,
     #[error("Message too long")]
     MessageTooLong,
     #[error("Queue not found")]
     QueueNotFound,
+    #[error("Queue `{0}` not found")]
+    MissingQueue(String),
     #[error("Queue already exists")]
     QueueExists,
     #[error("Error when trying to create random value. This is a bug and realted with the rust random generator")]
@@ -41,10 +54,77 @@ pub enum RsmqError {
     CannotParseMaxsize,
     #[error("The message received from Redis cannot be decoded into the expected type. Try to use Vec<u8> instead.")]
     CannotDecodeMessage(Vec<u8>),
+    #[error("The message received from Redis cannot be decoded into the expected type: {0}")]
+    CannotDecodeMessageWithContext(String),
+    #[error("The message cannot be serialized as JSON: {0}")]
+    CannotEncodeJson(String),
+    #[error("The message received from Redis is not valid JSON for the expected type: {1}")]
+    CannotDecodeJson(Vec<u8>, String),
+    #[error("Batch of {requested} elements is bigger than the maximum allowed of {max}")]
+    BatchTooLarge { requested: usize, max: usize },
+    #[error("A message with the same deduplication id is already in the queue")]
+    DuplicatedMessage,
+    #[error("Inconsistent state: {0}")]
+    InconsistentState(String),
+    #[error("A new Redis connection is needed, but this instance was created from an existing connection")]
+    NoClient,
+    #[error("TLS was requested but rsmq_async was compiled without any of its TLS features")]
+    TlsNotEnabled,
+    #[error("Client side caching needs the RESP3 protocol")]
+    ClientSideCachingNeedsResp3,
+    #[error(
+        "Sentinels were configured but rsmq_async was compiled without the `sentinel` feature"
+    )]
+    SentinelNotEnabled,
+    #[error("The send buffer stopped before sending the message")]
+    SendBufferClosed,
+    #[error("The batch of the send buffer failed: {0}")]
+    SendBufferFailed(String),
     #[error("Cannot start tokio runtime for sync facade")]
     TokioStart(Different<std::io::Error>),
 }
 
+impl From<RedisError> for RsmqError {
+    fn from(error: RedisError) -> Self {
+        // Writes sent to a replica fail until the client reaches the new master, so they are worth retrying
+        if error.kind() == ErrorKind::ReadOnly {
+            RsmqError::ReadOnlyReplica(error)
+        } else {
+            RsmqError::RedisError(error)
+        }
+    }
+}
+
+/// Error of the `TryFrom<RedisBytes>` implementations used for decoding received messages. It tells how the
+/// failure is reported as `RsmqError`.
+///
+/// `Vec<u8>` (the original bytes) becomes `RsmqError::CannotDecodeMessage` and `String` becomes
+/// `RsmqError::CannotDecodeMessageWithContext`. You can implement it for your own error type in order to keep the
+/// details of what failed:
+///
+/// ```rust,ignore
+/// impl RsmqDecodeError for MyDecodeError {
+///     fn into_rsmq_error(self) -> RsmqError {
+///         RsmqError::CannotDecodeMessageWithContext(format!("field `{}` is invalid", self.field))
+///     }
+/// }
+/// ```
+pub trait RsmqDecodeError {
+    fn into_rsmq_error(self) -> RsmqError;
+}
+
+impl RsmqDecodeError for Vec<u8> {
+    fn into_rsmq_error(self) -> RsmqError {
+        RsmqError::CannotDecodeMessage(self)
+    }
+}
+
+impl RsmqDecodeError for String {
+    fn into_rsmq_error(self) -> RsmqError {
+        RsmqError::CannotDecodeMessageWithContext(self)
+    }
+}
+
 #[derive(Debug)]
 pub struct Different<T>(pub T);
 
@@ -1,30 +1,202 @@
+use crate::attributes_cache::AttributesCache;
+use crate::body_cache::BodyCache;
+use crate::observer::{RsmqMetrics, RsmqObserver};
+use crate::time::TimeSource;
 use crate::types::RedisBytes;
+#[cfg(feature = "guard")]
+use crate::types::RsmqUnackedPolicy;
 use crate::{
-    types::{QueueDescriptor, RsmqMessage, RsmqQueueAttributes},
-    RsmqError, RsmqResult,
+    types::{
+        MaxSize, QueueDescriptor, RsmqAuditOperation, RsmqAuditRecord, RsmqBatchMode,
+        RsmqConfigSummary, RsmqConnectionInfo, RsmqMessage, RsmqMessageMeta, RsmqOptions,
+        RsmqQueueAttributes, RsmqQueueConfig, RsmqRealtimePayload,
+    },
+    PollBackoff, RsmqDecodeError, RsmqError, RsmqResult,
 };
 use core::convert::TryFrom;
 use radix_fmt::radix_36;
-use rand::seq::IteratorRandom;
-use redis::{aio::ConnectionLike, pipe};
+use rand::seq::SliceRandom;
+use redis::{aio::ConnectionLike, pipe, ErrorKind, ProtocolVersion};
+#[cfg(feature = "compression")]
+use std::borrow::Cow;
+#[cfg(feature = "otel")]
+use std::collections::HashMap;
 use std::convert::TryInto;
-use std::time::Duration;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+/// Maximum of every duration (delay, hidden, lifetime): the 9_999_999 seconds allowed by the JS version, in
+/// milliseconds like the durations stored in Redis
 const JS_COMPAT_MAX_TIME_MILLIS: u64 = 9_999_999_000;
 
+/// Queue settings used by "create_queue" when they are not specified
+const DEFAULT_HIDDEN: Duration = Duration::from_secs(30);
+const DEFAULT_DELAY: Duration = Duration::ZERO;
+const DEFAULT_MAXSIZE: MaxSize = MaxSize::Bytes(65536);
+/// Random part of the message ids, as generated by the JS library
+pub(crate) const DEFAULT_ID_LENGTH: usize = 22;
+pub(crate) const DEFAULT_ID_ALPHABET: &str =
+    "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+/// First wait between the receives of "receive_message_blocking"
+const MIN_BLOCKING_POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// Longest wait between the receives of "receive_message_blocking"
+const MAX_BLOCKING_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Highest maxsize accepted by the JS library
+pub(crate) const JS_COMPAT_MAX_MAXSIZE: i32 = 65536;
+
+/// Suffix of the queue hash field where the W3C trace context of a message is stored
+const TRACEPARENT_FIELD: &str = "tp";
+/// Suffix of the queue hash field where the ordering key of a message is stored
+const ORDERING_KEY_FIELD: &str = "ok";
+/// Suffix of the queue hash field where the group id of a message is stored
+const GROUP_FIELD: &str = "gr";
+/// Suffix of the queue hash field where the deduplication id of a message is stored
+const DEDUPLICATION_FIELD: &str = "dd";
+/// Suffix of the queue hash field where the queue to reply to is stored
+const REPLY_TO_FIELD: &str = "rp";
+/// Suffix of the queue hash field where the deadline of a message (timestamp in milliseconds) is stored
+const EXPIRES_AT_FIELD: &str = "ex";
+/// Suffix of the queue hash field where the attributes of a message (see "encode_attributes") are stored
+const ATTRIBUTES_FIELD: &str = "at";
+/// Suffixes of the queue hash fields stored alongside the message body (as "{id}:{suffix}"). The scripts get them as
+/// the `messageFields` table (see "with_prelude")
+const MESSAGE_FIELDS: [&str; 9] = [
+    "rc",
+    "fr",
+    TRACEPARENT_FIELD,
+    ORDERING_KEY_FIELD,
+    GROUP_FIELD,
+    DEDUPLICATION_FIELD,
+    REPLY_TO_FIELD,
+    EXPIRES_AT_FIELD,
+    ATTRIBUTES_FIELD,
+];
+/// Message fields kept when a message is moved to another queue (by "move_messages" or to a dead-letter queue), the
+/// `carriedFields` table of the scripts. The ordering key, group and deduplication id belong to the source queue
+const CARRIED_FIELDS: [&str; 6] = [
+    "rc",
+    "fr",
+    TRACEPARENT_FIELD,
+    REPLY_TO_FIELD,
+    EXPIRES_AT_FIELD,
+    ATTRIBUTES_FIELD,
+];
+#[cfg(feature = "otel")]
+const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// Values returned by receiveMessage.lua: found, id, body (None if it's in the body cache), rc, fr, expired,
+/// totalrecv
+type ReceiveMessageResult = (bool, String, Option<Vec<u8>>, u64, u64, bool, u64);
+/// Values returned for each message by receiveMessageBatch.lua: id, body, rc, fr, expired
+type ReceiveBatchEntry = (String, Vec<u8>, u64, u64, bool);
+/// Values returned for each message by receiveMessageMulti.lua: queue position (from 1), id, body, rc, fr, expired
+type ReceiveMultiEntry = (usize, String, Vec<u8>, u64, u64, bool);
+/// Message (with the total received count of the queue) and queue attributes returned by
+/// "receive_message_matching"
+type ReceivedMatching<E> = (Option<(RsmqMessage<E>, u64)>, Option<RsmqQueueAttributes>);
+/// Values returned by peekMessage.lua: found, id, rc, fr, size, body, expired
+type PeekMessageResult = (bool, String, u64, u64, u64, Vec<u8>, bool);
+/// Values read by the commands added by "add_queue_attributes_commands": the queue hash fields, msgs, hiddenmsgs and
+/// the dead-letter settings (maxrc and dlq)
+type QueueAttributesResult<F> = (Vec<Option<F>>, u64, u64, DeadLetterFields);
+/// The maxrc and dlq fields of the queue hash
+type DeadLetterFields = (Option<String>, Option<String>);
+/// Body, rc, fr and deadline of a message as read by "for_each_message" and "get_message". They are missing if the
+/// message was deleted
+type StoredMessage = (Option<Vec<u8>>, Option<u64>, Option<u64>, Option<u64>);
+
 #[cfg(feature = "break-js-comp")]
 const TIME_MULTIPLIER: u64 = 1000;
 #[cfg(not(feature = "break-js-comp"))]
 const TIME_MULTIPLIER: u64 = 1;
 
+/// How many messages "for_each_message" reads from Redis at once
+const FOR_EACH_PAGE_SIZE: usize = 100;
+
+/// When a message sent by "send_message_with_fields" becomes visible
+enum SendAt {
+    /// After the delay, or the queue delay if None
+    Delay(Option<Duration>),
+    /// At the score (milliseconds since epoch) given by the caller
+    Score(u64),
+}
+
+/// Optional parts of a message sent by "send_message_with_fields"
+#[derive(Debug, Default, Clone, Copy)]
+struct SendExtras<'a> {
+    /// Extra fields of the message, as (suffix, value)
+    fields: &'a [(&'a str, String)],
+    /// The send fails with `RsmqError::DuplicatedMessage` if a message with this deduplication id is in the queue
+    deduplication_id: Option<&'a str>,
+    /// Lifetime of the message in milliseconds. Its deadline is stored relative to the send time
+    lifetime: Option<u64>,
+}
+
 /// The main object of this library. Creates/Handles the redis connection and contains all the methods
-#[derive(Clone)]
 pub struct RsmqFunctions<T: ConnectionLike> {
     pub(crate) ns: String,
     pub(crate) realtime: bool,
+    pub(crate) realtime_payload: RsmqRealtimePayload,
+    pub(crate) max_batch_size: usize,
+    pub(crate) protocol: ProtocolVersion,
+    pub(crate) audit_log_max_len: Option<usize>,
+    pub(crate) missing_queue_as_empty: bool,
+    pub(crate) unchecked_send: bool,
+    pub(crate) max_scan: Option<usize>,
+    pub(crate) trim_queue_names: bool,
+    pub(crate) track_max_depth: bool,
+    pub(crate) maxsize_ceiling: i32,
+    pub(crate) id_length: usize,
+    pub(crate) id_alphabet: Vec<char>,
+    #[cfg(feature = "guard")]
+    pub(crate) unacked_policy: RsmqUnackedPolicy,
+    pub(crate) max_global_redeliveries: Option<u64>,
+    pub(crate) observer: Option<Arc<dyn RsmqObserver>>,
+    pub(crate) metrics: Option<Arc<dyn RsmqMetrics>>,
+    pub(crate) connection_info: RsmqConnectionInfo,
+    #[cfg(feature = "compression")]
+    pub(crate) compress_threshold: Option<usize>,
+    pub(crate) attributes_cache: Option<AttributesCache>,
+    pub(crate) body_cache: Option<BodyCache>,
+    pub(crate) time_source: Option<Arc<dyn TimeSource>>,
     pub(crate) conn: std::marker::PhantomData<T>,
 }
 
+// Implemented by hand because deriving it would require the connection to be `Clone`
+impl<T: ConnectionLike> Clone for RsmqFunctions<T> {
+    fn clone(&self) -> Self {
+        RsmqFunctions {
+            ns: self.ns.clone(),
+            realtime: self.realtime,
+            realtime_payload: self.realtime_payload,
+            max_batch_size: self.max_batch_size,
+            protocol: self.protocol,
+            audit_log_max_len: self.audit_log_max_len,
+            missing_queue_as_empty: self.missing_queue_as_empty,
+            unchecked_send: self.unchecked_send,
+            max_scan: self.max_scan,
+            trim_queue_names: self.trim_queue_names,
+            track_max_depth: self.track_max_depth,
+            maxsize_ceiling: self.maxsize_ceiling,
+            id_length: self.id_length,
+            id_alphabet: self.id_alphabet.clone(),
+            #[cfg(feature = "guard")]
+            unacked_policy: self.unacked_policy,
+            max_global_redeliveries: self.max_global_redeliveries,
+            observer: self.observer.clone(),
+            metrics: self.metrics.clone(),
+            connection_info: self.connection_info.clone(),
+            #[cfg(feature = "compression")]
+            compress_threshold: self.compress_threshold,
+            attributes_cache: self.attributes_cache.clone(),
+            body_cache: self.body_cache.clone(),
+            time_source: self.time_source.clone(),
+            conn: std::marker::PhantomData,
+        }
+    }
+}
+
 impl<T: ConnectionLike> std::fmt::Debug for RsmqFunctions<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "RsmqFunctions")
@@ -34,24 +206,88 @@ impl<T: ConnectionLike> std::fmt::Debug for RsmqFunctions<T> {
 #[derive(Debug, Clone)]
 pub struct CachedScript {
     change_message_visibility_sha1: String,
+    count_group_sha1: String,
+    delete_message_by_handle_sha1: String,
+    delete_message_if_unchanged_sha1: String,
+    delete_queue_if_empty_sha1: String,
+    move_messages_sha1: String,
+    peek_message_sha1: String,
+    purge_queue_sha1: String,
     receive_message_sha1: String,
+    receive_message_batch_sha1: String,
+    receive_message_multi_sha1: String,
+    release_deduplication_sha1: String,
+    rename_queue_sha1: String,
+    send_message_sha1: String,
+    swap_message_order_sha1: String,
+    update_max_depth_sha1: String,
 }
 
 impl CachedScript {
     async fn init<T: ConnectionLike>(conn: &mut T) -> RsmqResult<Self> {
-        let change_message_visibility_sha1: String = redis::cmd("SCRIPT")
-            .arg("LOAD")
-            .arg(include_str!("./redis-scripts/changeMessageVisibility.lua"))
-            .query_async(conn)
-            .await?;
-        let receive_message_sha1: String = redis::cmd("SCRIPT")
-            .arg("LOAD")
-            .arg(include_str!("./redis-scripts/receiveMessage.lua"))
-            .query_async(conn)
-            .await?;
         Ok(Self {
-            change_message_visibility_sha1,
-            receive_message_sha1,
+            change_message_visibility_sha1: load_script(
+                conn,
+                include_str!("./redis-scripts/changeMessageVisibility.lua"),
+            )
+            .await?,
+            count_group_sha1: load_script(conn, include_str!("./redis-scripts/countGroup.lua"))
+                .await?,
+            delete_message_by_handle_sha1: load_script(
+                conn,
+                include_str!("./redis-scripts/deleteMessageByHandle.lua"),
+            )
+            .await?,
+            delete_message_if_unchanged_sha1: load_script(
+                conn,
+                include_str!("./redis-scripts/deleteMessageIfUnchanged.lua"),
+            )
+            .await?,
+            delete_queue_if_empty_sha1: load_script(
+                conn,
+                include_str!("./redis-scripts/deleteQueueIfEmpty.lua"),
+            )
+            .await?,
+            move_messages_sha1: load_script(conn, include_str!("./redis-scripts/moveMessages.lua"))
+                .await?,
+            peek_message_sha1: load_script(conn, include_str!("./redis-scripts/peekMessage.lua"))
+                .await?,
+            purge_queue_sha1: load_script(conn, include_str!("./redis-scripts/purgeQueue.lua"))
+                .await?,
+            receive_message_sha1: load_script(
+                conn,
+                include_str!("./redis-scripts/receiveMessage.lua"),
+            )
+            .await?,
+            receive_message_batch_sha1: load_script(
+                conn,
+                include_str!("./redis-scripts/receiveMessageBatch.lua"),
+            )
+            .await?,
+            receive_message_multi_sha1: load_script(
+                conn,
+                include_str!("./redis-scripts/receiveMessageMulti.lua"),
+            )
+            .await?,
+            release_deduplication_sha1: load_script(
+                conn,
+                include_str!("./redis-scripts/releaseDeduplication.lua"),
+            )
+            .await?,
+            rename_queue_sha1: load_script(conn, include_str!("./redis-scripts/renameQueue.lua"))
+                .await?,
+            send_message_sha1: load_script(conn, include_str!("./redis-scripts/sendMessage.lua"))
+                .await?,
+            swap_message_order_sha1: load_script(
+                conn,
+                include_str!("./redis-scripts/swapMessageOrder.lua"),
+            )
+            .await?,
+            update_max_depth_sha1: load_script(
+                conn,
+                include_str!("./redis-scripts/updateMaxDepth.lua"),
+            )
+            .await?,
         })
     }
 
@@ -61,413 +297,3403 @@ impl CachedScript {
         key1: String,
         key2: String,
         key3: String,
+        receive_count: Option<u64>,
     ) -> RsmqResult<R>
     where
         R: redis::FromRedisValue,
     {
-        redis::cmd("EVALSHA")
+        #[cfg(feature = "tracing")]
+        crate::instrument::script_invoked("changeMessageVisibility");
+
+        let mut command = redis::cmd("EVALSHA");
+
+        command
             .arg(&self.change_message_visibility_sha1)
             .arg(3)
             .arg(key1)
             .arg(key2)
             .arg(key3)
-            .query_async(conn)
-            .await
-            .map_err(Into::into)
+            .arg(receive_count);
+
+        query_script(conn, &command).await
     }
 
-    async fn invoke_receive_message<R, T: ConnectionLike>(
+    async fn invoke_count_group<R, T: ConnectionLike>(
         &self,
         conn: &mut T,
         key1: String,
         key2: String,
-        key3: String,
-        should_delete: String,
     ) -> RsmqResult<R>
     where
         R: redis::FromRedisValue,
     {
-        redis::cmd("EVALSHA")
-            .arg(&self.receive_message_sha1)
-            .arg(3)
+        #[cfg(feature = "tracing")]
+        crate::instrument::script_invoked("countGroup");
+
+        let mut command = redis::cmd("EVALSHA");
+
+        command
+            .arg(&self.count_group_sha1)
+            .arg(2)
             .arg(key1)
-            .arg(key2)
-            .arg(key3)
-            .arg(should_delete)
-            .query_async(conn)
-            .await
-            .map_err(Into::into)
+            .arg(key2);
+
+        query_script(conn, &command).await
     }
-}
 
-impl<T: ConnectionLike> RsmqFunctions<T> {
-    /// Change the hidden time of a already sent message.
-    pub async fn change_message_visibility(
+    async fn invoke_delete_message_by_handle<R, T: ConnectionLike>(
         &self,
         conn: &mut T,
-        qname: &str,
-        message_id: &str,
-        hidden: Duration,
-        cached_script: &CachedScript,
-    ) -> RsmqResult<()> {
-        let hidden = get_redis_duration(Some(hidden), &Duration::from_secs(30));
-
-        let queue = self.get_queue(conn, qname, false).await?;
-
-        number_in_range(hidden, 0, JS_COMPAT_MAX_TIME_MILLIS)?;
+        key1: String,
+        key2: String,
+        receive_count: u64,
+        fields: &[String],
+    ) -> RsmqResult<R>
+    where
+        R: redis::FromRedisValue,
+    {
+        #[cfg(feature = "tracing")]
+        crate::instrument::script_invoked("deleteMessageByHandle");
 
-        cached_script
-            .invoke_change_message_visibility::<(), T>(
-                conn,
-                format!("{}:{}", self.ns, qname),
-                message_id.to_string(),
-                (queue.ts + hidden).to_string(),
-            )
-            .await?;
+        let mut command = redis::cmd("EVALSHA");
 
-        Ok(())
-    }
+        command
+            .arg(&self.delete_message_by_handle_sha1)
+            .arg(2)
+            .arg(key1)
+            .arg(key2)
+            .arg(receive_count)
+            .arg(fields);
 
-    pub async fn load_scripts(&self, conn: &mut T) -> RsmqResult<CachedScript> {
-        CachedScript::init(conn).await
+        query_script(conn, &command).await
     }
 
-    /// Creates a new queue. Attributes can be later modified with "set_queue_attributes" method
-    ///
-    /// hidden: Time the messages will be hidden when they are received with the "receive_message" method.
-    ///
-    /// delay: Time the messages will be delayed before being delivered
-    ///
-    /// maxsize: Maximum size in bytes of each message in the queue. Needs to be between 1024 or 65536 or -1 (unlimited size)
-    pub async fn create_queue(
+    async fn invoke_delete_message_if_unchanged<R, T: ConnectionLike>(
         &self,
         conn: &mut T,
-        qname: &str,
-        hidden: Option<Duration>,
-        delay: Option<Duration>,
-        maxsize: Option<i32>,
-    ) -> RsmqResult<()> {
-        valid_name_format(qname)?;
-
-        let key = format!("{}:{}:Q", self.ns, qname);
-        let hidden = get_redis_duration(hidden, &Duration::from_secs(30));
-        let delay = get_redis_duration(delay, &Duration::ZERO);
-        let maxsize = maxsize.unwrap_or(65536);
-
-        number_in_range(hidden, 0, JS_COMPAT_MAX_TIME_MILLIS)?;
-        number_in_range(delay, 0, JS_COMPAT_MAX_TIME_MILLIS)?;
-        if let Err(error) = number_in_range(maxsize, 1024, 65536) {
-            if maxsize != -1 {
-                // TODO: Create another error in order to explain that -1 is allowed
-                return Err(error);
-            }
-        }
-
-        let time: (u64, u64) = redis::cmd("TIME").query_async(conn).await?;
-
-        let results: Vec<bool> = pipe()
-            .atomic()
-            .cmd("HSETNX")
-            .arg(&key)
-            .arg("vt")
-            .arg(hidden)
-            .cmd("HSETNX")
-            .arg(&key)
-            .arg("delay")
-            .arg(delay)
-            .cmd("HSETNX")
-            .arg(&key)
-            .arg("maxsize")
-            .arg(maxsize)
-            .cmd("HSETNX")
-            .arg(&key)
-            .arg("created")
-            .arg(time.0)
-            .cmd("HSETNX")
-            .arg(&key)
-            .arg("modified")
-            .arg(time.0)
-            .cmd("HSETNX")
-            .arg(&key)
-            .arg("totalrecv")
-            .arg(0_i32)
-            .cmd("HSETNX")
-            .arg(&key)
-            .arg("totalsent")
-            .arg(0_i32)
-            .query_async(conn)
-            .await?;
+        key1: String,
+        key2: String,
+        expected: &[u8],
+        fields: &[String],
+    ) -> RsmqResult<R>
+    where
+        R: redis::FromRedisValue,
+    {
+        #[cfg(feature = "tracing")]
+        crate::instrument::script_invoked("deleteMessageIfUnchanged");
 
-        if !results[0] {
-            return Err(RsmqError::QueueExists);
-        }
+        let mut command = redis::cmd("EVALSHA");
 
-        redis::cmd("SADD")
-            .arg(format!("{}:QUEUES", self.ns))
-            .arg(qname)
-            .query_async::<()>(conn)
-            .await?;
+        command
+            .arg(&self.delete_message_if_unchanged_sha1)
+            .arg(2)
+            .arg(key1)
+            .arg(key2)
+            .arg(expected)
+            .arg(fields);
 
-        Ok(())
+        query_script(conn, &command).await
     }
 
-    /// Deletes a message from the queue.
-    ///
-    /// Important to use when you are using receive_message.
-    pub async fn delete_message(&self, conn: &mut T, qname: &str, id: &str) -> RsmqResult<bool> {
-        let key = format!("{}:{}", self.ns, qname);
+    async fn invoke_delete_queue_if_empty<R, T: ConnectionLike>(
+        &self,
+        conn: &mut T,
+        key1: String,
+        key2: String,
+        key3: String,
+    ) -> RsmqResult<R>
+    where
+        R: redis::FromRedisValue,
+    {
+        #[cfg(feature = "tracing")]
+        crate::instrument::script_invoked("deleteQueueIfEmpty");
 
-        let results: (u16, u16) = pipe()
-            .atomic()
-            .cmd("ZREM")
-            .arg(&key)
-            .arg(id)
-            .cmd("HDEL")
-            .arg(format!("{}:Q", &key))
-            .arg(id)
-            .arg(format!("{}:rc", id))
-            .arg(format!("{}:fr", id))
-            .query_async(conn)
-            .await?;
+        let mut command = redis::cmd("EVALSHA");
 
-        if results.0 == 1 && results.1 > 0 {
-            return Ok(true);
-        }
+        command
+            .arg(&self.delete_queue_if_empty_sha1)
+            .arg(3)
+            .arg(key1)
+            .arg(key2)
+            .arg(key3);
 
-        Ok(false)
+        query_script(conn, &command).await
     }
 
-    /// Deletes the queue and all the messages on it
-    pub async fn delete_queue(&self, conn: &mut T, qname: &str) -> RsmqResult<()> {
-        let key = format!("{}:{}", self.ns, qname);
+    async fn invoke_move_messages<R, T: ConnectionLike>(
+        &self,
+        conn: &mut T,
+        keys: &[String],
+        reset_receives: bool,
+        ids: &[String],
+    ) -> RsmqResult<R>
+    where
+        R: redis::FromRedisValue,
+    {
+        #[cfg(feature = "tracing")]
+        crate::instrument::script_invoked("moveMessages");
 
-        let results: (u16, u16) = pipe()
-            .atomic()
-            .cmd("DEL")
-            .arg(format!("{}:Q", &key))
-            .arg(key)
-            .cmd("SREM")
-            .arg(format!("{}:QUEUES", self.ns))
-            .arg(qname)
-            .query_async(conn)
-            .await?;
+        let mut command = redis::cmd("EVALSHA");
 
-        if results.0 == 0 {
-            return Err(RsmqError::QueueNotFound);
-        }
+        command
+            .arg(&self.move_messages_sha1)
+            .arg(keys.len())
+            .arg(keys)
+            .arg(if reset_receives { "true" } else { "false" })
+            .arg(ids);
 
-        Ok(())
+        query_script(conn, &command).await
     }
 
-    /// Returns the queue attributes and statistics
-    pub async fn get_queue_attributes(
+    async fn invoke_peek_message<R, T: ConnectionLike>(
         &self,
         conn: &mut T,
-        qname: &str,
-    ) -> RsmqResult<RsmqQueueAttributes> {
-        let key = format!("{}:{}", self.ns, qname);
+        key1: String,
+        key2: String,
+        include_body: bool,
+    ) -> RsmqResult<R>
+    where
+        R: redis::FromRedisValue,
+    {
+        #[cfg(feature = "tracing")]
+        crate::instrument::script_invoked("peekMessage");
 
-        let time: (u64, u64) = redis::cmd("TIME").query_async(conn).await?;
+        let mut command = redis::cmd("EVALSHA");
 
-        let result: (Vec<Option<i64>>, u64, u64) = pipe()
-            .atomic()
-            .cmd("HMGET")
-            .arg(format!("{}:Q", key))
-            .arg("vt")
-            .arg("delay")
-            .arg("maxsize")
-            .arg("totalrecv")
-            .arg("totalsent")
-            .arg("created")
-            .arg("modified")
-            .cmd("ZCARD")
-            .arg(&key)
-            .cmd("ZCOUNT")
-            .arg(&key)
-            .arg(time.0 * TIME_MULTIPLIER)
-            .arg("+inf")
-            .query_async(conn)
-            .await?;
+        command
+            .arg(&self.peek_message_sha1)
+            .arg(2)
+            .arg(key1)
+            .arg(key2)
+            .arg(include_body.to_string());
 
-        let is_empty = result.0.contains(&None);
+        query_script(conn, &command).await
+    }
 
-        if is_empty {
-            return Err(RsmqError::QueueNotFound);
-        }
+    async fn invoke_purge_queue<R, T: ConnectionLike>(
+        &self,
+        conn: &mut T,
+        key1: String,
+    ) -> RsmqResult<R>
+    where
+        R: redis::FromRedisValue,
+    {
+        #[cfg(feature = "tracing")]
+        crate::instrument::script_invoked("purgeQueue");
 
-        Ok(RsmqQueueAttributes {
-            vt: result
-                .0
-                .first()
-                .and_then(Option::as_ref)
-                .map(|dur| Duration::from_millis((*dur).try_into().unwrap_or(0)))
-                .unwrap_or(Duration::ZERO),
-            delay: result
-                .0
-                .get(1)
-                .and_then(Option::as_ref)
-                .map(|dur| Duration::from_millis((*dur).try_into().unwrap_or(0)))
-                .unwrap_or(Duration::ZERO),
-            maxsize: result.0.get(2).unwrap_or(&Some(0)).unwrap_or(0),
-            totalrecv: u64::try_from(result.0.get(3).unwrap_or(&Some(0)).unwrap_or(0)).unwrap_or(0),
-            totalsent: u64::try_from(result.0.get(4).unwrap_or(&Some(0)).unwrap_or(0)).unwrap_or(0),
-            created: u64::try_from(result.0.get(5).unwrap_or(&Some(0)).unwrap_or(0)).unwrap_or(0),
-            modified: u64::try_from(result.0.get(6).unwrap_or(&Some(0)).unwrap_or(0)).unwrap_or(0),
-            msgs: result.1,
-            hiddenmsgs: result.2,
-        })
-    }
+        let mut command = redis::cmd("EVALSHA");
 
-    /// Returns a list of queues in the namespace
-    pub async fn list_queues(&self, conn: &mut T) -> RsmqResult<Vec<String>> {
-        let queues = redis::cmd("SMEMBERS")
-            .arg(format!("{}:QUEUES", self.ns))
-            .query_async(conn)
-            .await?;
+        command.arg(&self.purge_queue_sha1).arg(1).arg(key1);
 
-        Ok(queues)
+        query_script(conn, &command).await
     }
 
-    /// Deletes and returns a message. Be aware that using this you may end with deleted & unprocessed messages.
-    pub async fn pop_message<E: TryFrom<RedisBytes, Error = Vec<u8>>>(
+    async fn invoke_receive_message<R, T: ConnectionLike>(
         &self,
         conn: &mut T,
-        qname: &str,
-        cached_script: &CachedScript,
-    ) -> RsmqResult<Option<RsmqMessage<E>>> {
-        let queue = self.get_queue(conn, qname, false).await?;
+        keys: &[String],
+        should_delete: String,
+        omit_body: bool,
+    ) -> RsmqResult<R>
+    where
+        R: redis::FromRedisValue,
+    {
+        #[cfg(feature = "tracing")]
+        crate::instrument::script_invoked("receiveMessage");
 
-        let result: (bool, String, Vec<u8>, u64, u64) = cached_script
-            .invoke_receive_message(
-                conn,
-                format!("{}:{}", self.ns, qname),
-                queue.ts.to_string(),
-                queue.ts.to_string(),
-                "true".to_string(),
-            )
-            .await?;
+        let mut command = redis::cmd("EVALSHA");
 
-        if !result.0 {
-            return Ok(None);
+        command
+            .arg(&self.receive_message_sha1)
+            .arg(keys.len())
+            .arg(keys)
+            .arg(should_delete)
+            .arg(omit_body.to_string());
+
+        query_script(conn, &command).await
+    }
+
+    async fn invoke_receive_message_batch<R, T: ConnectionLike>(
+        &self,
+        conn: &mut T,
+        keys: &[String],
+        should_delete: String,
+        count: usize,
+    ) -> RsmqResult<R>
+    where
+        R: redis::FromRedisValue,
+    {
+        #[cfg(feature = "tracing")]
+        crate::instrument::script_invoked("receiveMessageBatch");
+
+        let mut command = redis::cmd("EVALSHA");
+
+        command
+            .arg(&self.receive_message_batch_sha1)
+            .arg(keys.len())
+            .arg(keys)
+            .arg(should_delete)
+            .arg(count);
+
+        query_script(conn, &command).await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn invoke_receive_message_multi<R, T: ConnectionLike>(
+        &self,
+        conn: &mut T,
+        keys: Vec<String>,
+        now: u64,
+        max_total: usize,
+        hidden: Option<u64>,
+        prefix: String,
+        skip_missing: bool,
+    ) -> RsmqResult<R>
+    where
+        R: redis::FromRedisValue,
+    {
+        #[cfg(feature = "tracing")]
+        crate::instrument::script_invoked("receiveMessageMulti");
+
+        let mut command = redis::cmd("EVALSHA");
+
+        command
+            .arg(&self.receive_message_multi_sha1)
+            .arg(keys.len())
+            .arg(keys)
+            .arg(now)
+            .arg(max_total)
+            .arg(hidden.map(|hidden| hidden.to_string()).unwrap_or_default())
+            .arg(prefix)
+            .arg(skip_missing.to_string());
+
+        query_script(conn, &command).await
+    }
+
+    async fn invoke_release_deduplication<R, T: ConnectionLike>(
+        &self,
+        conn: &mut T,
+        key1: String,
+        key2: String,
+        key3: String,
+    ) -> RsmqResult<R>
+    where
+        R: redis::FromRedisValue,
+    {
+        #[cfg(feature = "tracing")]
+        crate::instrument::script_invoked("releaseDeduplication");
+
+        let mut command = redis::cmd("EVALSHA");
+
+        command
+            .arg(&self.release_deduplication_sha1)
+            .arg(3)
+            .arg(key1)
+            .arg(key2)
+            .arg(key3);
+
+        query_script(conn, &command).await
+    }
+
+    async fn invoke_rename_queue<R, T: ConnectionLike>(
+        &self,
+        conn: &mut T,
+        keys: [String; 5],
+    ) -> RsmqResult<R>
+    where
+        R: redis::FromRedisValue,
+    {
+        #[cfg(feature = "tracing")]
+        crate::instrument::script_invoked("renameQueue");
+
+        let mut command = redis::cmd("EVALSHA");
+
+        command.arg(&self.rename_queue_sha1).arg(5).arg(&keys);
+
+        query_script(conn, &command).await
+    }
+
+    async fn invoke_swap_message_order<R, T: ConnectionLike>(
+        &self,
+        conn: &mut T,
+        key1: String,
+        key2: String,
+        key3: String,
+        key4: String,
+    ) -> RsmqResult<R>
+    where
+        R: redis::FromRedisValue,
+    {
+        #[cfg(feature = "tracing")]
+        crate::instrument::script_invoked("swapMessageOrder");
+
+        let mut command = redis::cmd("EVALSHA");
+
+        command
+            .arg(&self.swap_message_order_sha1)
+            .arg(4)
+            .arg(key1)
+            .arg(key2)
+            .arg(key3)
+            .arg(key4);
+
+        query_script(conn, &command).await
+    }
+
+    async fn invoke_update_max_depth<R, T: ConnectionLike>(
+        &self,
+        conn: &mut T,
+        key1: String,
+    ) -> RsmqResult<R>
+    where
+        R: redis::FromRedisValue,
+    {
+        #[cfg(feature = "tracing")]
+        crate::instrument::script_invoked("updateMaxDepth");
+
+        let mut command = redis::cmd("EVALSHA");
+
+        command.arg(&self.update_max_depth_sha1).arg(1).arg(key1);
+
+        query_script(conn, &command).await
+    }
+}
+
+async fn load_script<T: ConnectionLike>(conn: &mut T, script: &str) -> RsmqResult<String> {
+    redis::cmd("SCRIPT")
+        .arg("LOAD")
+        .arg(with_prelude(script))
+        .query_async(conn)
+        .await
+        .map_err(Into::into)
+}
+
+/// Puts in front of a script the `messageFields` and `carriedFields` tables, built from "MESSAGE_FIELDS" and
+/// "CARRIED_FIELDS", and the functions shared by the scripts (prelude.lua)
+fn with_prelude(script: &str) -> String {
+    let table = |fields: &[&str]| {
+        fields
+            .iter()
+            .map(|field| format!("\"{}\"", field))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    format!(
+        "local messageFields = {{ {} }}\nlocal carriedFields = {{ {} }}\n{}\n{}",
+        table(&MESSAGE_FIELDS),
+        table(&CARRIED_FIELDS),
+        include_str!("./redis-scripts/prelude.lua"),
+        script
+    )
+}
+
+/// Sends a command calling a script with EVALSHA. If Redis doesn't know the script anymore (it was restarted, or
+/// a failover promoted a Redis that never loaded it), the scripts are loaded again and the command is sent once more.
+/// The hashes only depend on the scripts, so the ones in `CachedScript` stay valid.
+async fn query_script<R, T>(conn: &mut T, command: &redis::Cmd) -> RsmqResult<R>
+where
+    R: redis::FromRedisValue,
+    T: ConnectionLike,
+{
+    match command.query_async(conn).await {
+        Err(error) if error.kind() == ErrorKind::NoScriptError => {
+            CachedScript::init(conn).await?;
+            command.query_async(conn).await.map_err(Into::into)
         }
+        result => result.map_err(Into::into),
+    }
+}
 
-        let message = E::try_from(RedisBytes(result.2)).map_err(RsmqError::CannotDecodeMessage)?;
+/// Same as "query_script" for a pipeline with EVALSHA commands. The pipelines using it only add commands that can
+/// be repeated, as the ones after the failed EVALSHA already ran when it is sent again
+async fn query_script_pipeline<R, T>(conn: &mut T, pipeline: &redis::Pipeline) -> RsmqResult<R>
+where
+    R: redis::FromRedisValue,
+    T: ConnectionLike,
+{
+    match pipeline.query_async(conn).await {
+        Err(error) if error.kind() == ErrorKind::NoScriptError => {
+            CachedScript::init(conn).await?;
+            pipeline.query_async(conn).await.map_err(Into::into)
+        }
+        result => result.map_err(Into::into),
+    }
+}
 
-        Ok(Some(RsmqMessage {
-            id: result.1.clone(),
-            message,
-            rc: result.3,
-            fr: result.4,
-            sent: u64::from_str_radix(&result.1[0..10], 36).unwrap_or(0),
-        }))
+impl<T: ConnectionLike> RsmqFunctions<T> {
+    pub(crate) fn new(options: &RsmqOptions) -> Self {
+        RsmqFunctions {
+            ns: options.ns.clone(),
+            realtime: options.realtime,
+            realtime_payload: options.realtime_payload,
+            max_batch_size: options.max_batch_size,
+            protocol: options.protocol,
+            audit_log_max_len: options.audit_log_max_len,
+            missing_queue_as_empty: options.missing_queue_as_empty,
+            unchecked_send: options.unchecked_send,
+            max_scan: options.max_scan,
+            trim_queue_names: options.trim_queue_names,
+            track_max_depth: options.track_max_depth,
+            maxsize_ceiling: options.maxsize_ceiling,
+            id_length: options.id_length,
+            id_alphabet: options.id_alphabet.chars().collect(),
+            #[cfg(feature = "guard")]
+            unacked_policy: options.unacked_policy,
+            max_global_redeliveries: options.max_global_redeliveries,
+            observer: options.observer.clone(),
+            metrics: options.metrics.clone(),
+            connection_info: RsmqConnectionInfo::default(),
+            #[cfg(feature = "compression")]
+            compress_threshold: options.compress_threshold,
+            attributes_cache: None,
+            body_cache: options.body_cache_size.map(BodyCache::new),
+            #[cfg(feature = "test-util")]
+            time_source: options.time_source.clone(),
+            #[cfg(not(feature = "test-util"))]
+            time_source: None,
+            conn: std::marker::PhantomData,
+        }
     }
 
-    /// Returns a message. The message stays hidden for some time (defined by "hidden"
-    /// argument or the queue settings). After that time, the message will be redelivered.
-    /// In order to avoid the redelivery, you need to use the "delete_message" after this function.
-    pub async fn receive_message<E: TryFrom<RedisBytes, Error = Vec<u8>>>(
+    /// Change the hidden time of a already sent message.
+    pub async fn change_message_visibility(
         &self,
         conn: &mut T,
         qname: &str,
-        hidden: Option<Duration>,
+        message_id: &str,
+        hidden: Duration,
         cached_script: &CachedScript,
-    ) -> RsmqResult<Option<RsmqMessage<E>>> {
-        let queue = self.get_queue(conn, qname, false).await?;
+    ) -> RsmqResult<()> {
+        let qname = self.queue_name(qname);
 
-        let hidden = get_redis_duration(hidden, &queue.vt);
-        number_in_range(hidden, 0, JS_COMPAT_MAX_TIME_MILLIS)?;
+        traced(
+            "change_message_visibility",
+            qname,
+            Some(message_id),
+            async {
+                let hidden = get_redis_duration(Some(hidden), &Duration::from_secs(30));
 
-        let result: (bool, String, Vec<u8>, u64, u64) = cached_script
-            .invoke_receive_message(
+                let queue = self.get_queue(conn, qname, false).await?;
+
+                duration_in_range("hidden", hidden)?;
+
+                cached_script
+                    .invoke_change_message_visibility::<(), T>(
+                        conn,
+                        format!("{}:{}", self.ns, qname),
+                        message_id.to_string(),
+                        score_after(queue.ts, hidden)?.to_string(),
+                        None,
+                    )
+                    .await?;
+
+                Ok(())
+            },
+        )
+        .await
+    }
+
+    /// Creates a new queue with the same "vt", "delay" and "maxsize" as an existing one. The new queue starts empty
+    /// and with fresh statistics.
+    pub async fn clone_queue_config(&self, conn: &mut T, src: &str, dest: &str) -> RsmqResult<()> {
+        let src = self.queue_name(src);
+        let dest = self.queue_name(dest);
+
+        let attributes = self.get_queue_attributes(conn, src).await?;
+
+        self.create_queue(
+            conn,
+            dest,
+            Some(attributes.vt),
+            Some(attributes.delay),
+            Some(attributes.maxsize),
+        )
+        .await
+    }
+
+    pub async fn load_scripts(&self, conn: &mut T) -> RsmqResult<CachedScript> {
+        CachedScript::init(conn).await
+    }
+
+    /// Returns the settings this instance is effectively using
+    pub fn config_summary(&self) -> RsmqConfigSummary {
+        RsmqConfigSummary {
+            ns: self.ns.clone(),
+            realtime: self.realtime,
+            realtime_payload: self.realtime_payload,
+            protocol: self.protocol,
+            max_batch_size: self.max_batch_size,
+            client_side_caching: self.attributes_cache.is_some(),
+            custom_time_source: self.time_source.is_some(),
+            break_js_comp: cfg!(feature = "break-js-comp"),
+            default_vt: DEFAULT_HIDDEN,
+            default_delay: DEFAULT_DELAY,
+            default_maxsize: DEFAULT_MAXSIZE,
+            maxsize_ceiling: self.maxsize_ceiling,
+            #[cfg(feature = "guard")]
+            unacked_policy: self.unacked_policy,
+        }
+    }
+
+    /// Returns how many messages of the group are still in the queue
+    pub async fn count_group(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        group_id: &str,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<u64> {
+        let qname = self.queue_name(qname);
+
+        self.get_queue(conn, qname, false).await?;
+
+        cached_script
+            .invoke_count_group::<u64, T>(
                 conn,
                 format!("{}:{}", self.ns, qname),
-                queue.ts.to_string(),
-                (queue.ts + hidden).to_string(),
-                "false".to_string(),
+                group_id.to_string(),
             )
+            .await
+    }
+
+    /// Returns the number of messages that can be received now: the ones not hidden and not delayed
+    pub async fn count_visible_messages(&self, conn: &mut T, qname: &str) -> RsmqResult<u64> {
+        let qname = self.queue_name(qname);
+
+        let key = format!("{}:{}", self.ns, qname);
+
+        let time = self.redis_time(conn).await?;
+        let now = time.0 * 1000 + time.1 / 1000;
+
+        let (vt, visible): (Option<String>, u64) = pipe()
+            .atomic()
+            .cmd("HGET")
+            .arg(format!("{}:Q", key))
+            .arg("vt")
+            .cmd("ZCOUNT")
+            .arg(&key)
+            .arg("-inf")
+            .arg(now)
+            .query_async(conn)
             .await?;
 
-        if !result.0 {
-            return Ok(None);
+        if vt.is_none() {
+            return Err(RsmqError::QueueNotFound);
         }
 
-        let message = E::try_from(RedisBytes(result.2)).map_err(RsmqError::CannotDecodeMessage)?;
-
-        Ok(Some(RsmqMessage {
-            id: result.1.clone(),
-            message,
-            rc: result.3,
-            fr: result.4,
-            sent: u64::from_str_radix(&result.1[0..10], 36).unwrap_or(0),
-        }))
+        Ok(visible)
     }
 
-    /// Sends a message to the queue. The message will be delayed some time (controlled by the "delayed" argument or the queue settings) before being delivered to a client.
-    pub async fn send_message<E: Into<RedisBytes>>(
+    /// Creates a new queue. Attributes can be later modified with "set_queue_attributes" method
+    ///
+    /// hidden: Time the messages will be hidden when they are received with the "receive_message" method.
+    ///
+    /// delay: Time the messages will be delayed before being delivered
+    ///
+    /// maxsize: Maximum size of each message in the queue. `MaxSize::Bytes` needs to be between 1024 and `RsmqOptions::maxsize_ceiling` (65536 by default)
+    ///
+    /// A queue left half initialized by a failed create is completed with the given settings. If its hash doesn't
+    /// even have the creation time, it fails with `RsmqError::InconsistentState` and nothing is changed.
+    pub async fn create_queue(
         &self,
         conn: &mut T,
         qname: &str,
-        message: E,
+        hidden: Option<Duration>,
         delay: Option<Duration>,
-    ) -> RsmqResult<String> {
-        let queue = self.get_queue(conn, qname, true).await?;
-
-        let delay = get_redis_duration(delay, &queue.delay);
-        let key = format!("{}:{}", self.ns, qname);
-
-        number_in_range(delay, 0, JS_COMPAT_MAX_TIME_MILLIS)?;
+        maxsize: Option<MaxSize>,
+    ) -> RsmqResult<()> {
+        let qname = self.queue_name(qname);
 
-        let message: RedisBytes = message.into();
+        valid_name_format(qname)?;
 
-        let msg_len: i64 = message
-            .0
-            .len()
-            .try_into()
-            .map_err(|_| RsmqError::MessageTooLong)?;
+        let key = format!("{}:{}:Q", self.ns, qname);
+        let hidden = get_redis_duration(hidden, &DEFAULT_HIDDEN);
+        let delay = get_redis_duration(delay, &DEFAULT_DELAY);
+        let maxsize = maxsize.unwrap_or(DEFAULT_MAXSIZE);
 
-        if queue.maxsize != -1 && msg_len > queue.maxsize {
-            return Err(RsmqError::MessageTooLong);
-        }
+        duration_in_range("hidden", hidden)?;
+        duration_in_range("delay", delay)?;
+        self.check_maxsize(maxsize)?;
+        let maxsize = i64::from(maxsize);
 
-        let queue_uid = match queue.uid {
-            Some(uid) => uid,
-            None => return Err(RsmqError::QueueNotFound),
-        };
+        let time = self.redis_time(conn).await?;
 
-        let queue_key = format!("{}:Q", key);
+        let (fields, has_created): (u64, bool) = pipe()
+            .atomic()
+            .cmd("HLEN")
+            .arg(&key)
+            .cmd("HEXISTS")
+            .arg(&key)
+            .arg("created")
+            .query_async(conn)
+            .await?;
 
-        let mut piping = pipe();
+        // Only hashes that got as far as the creation time are known to be queues
+        if fields > 0 && !has_created {
+            return Err(RsmqError::InconsistentState(format!(
+                "{} has queue fields but no creation time",
+                key
+            )));
+        }
 
-        let mut commands = piping
+        let results: Vec<bool> = pipe()
             .atomic()
-            .cmd("ZADD")
+            .cmd("HSETNX")
+            .arg(&key)
+            .arg("vt")
+            .arg(hidden)
+            .cmd("HSETNX")
+            .arg(&key)
+            .arg("delay")
+            .arg(delay)
+            .cmd("HSETNX")
+            .arg(&key)
+            .arg("maxsize")
+            .arg(maxsize)
+            .cmd("HSETNX")
+            .arg(&key)
+            .arg("created")
+            .arg(time.0)
+            .cmd("HSETNX")
+            .arg(&key)
+            .arg("modified")
+            .arg(time.0)
+            .cmd("HSETNX")
+            .arg(&key)
+            .arg("totalrecv")
+            .arg(0_i32)
+            .cmd("HSETNX")
             .arg(&key)
-            .arg(queue.ts + delay)
-            .arg(&queue_uid)
-            .cmd("HSET")
-            .arg(&queue_key)
-            .arg(&queue_uid)
-            .arg(message.0)
-            .cmd("HINCRBY")
-            .arg(&queue_key)
             .arg("totalsent")
-            .arg(1_u64);
+            .arg(0_i32)
+            .cmd("SADD")
+            .arg(format!("{}:QUEUES", self.ns))
+            .arg(qname)
+            .ignore()
+            .query_async(conn)
+            .await?;
 
-        if self.realtime {
-            commands = commands.cmd("ZCARD").arg(&key);
+        if results.iter().all(|created| !created) {
+            return Err(RsmqError::QueueExists);
         }
 
-        let result: Vec<i64> = commands.query_async(conn).await?;
-
-        if self.realtime {
-            redis::cmd("PUBLISH")
-                .arg(format!("{}:rt:{}", self.ns, qname))
-                .arg(result[3])
+        // Only some fields were missing, so the queue was left half initialized. The missing fields were just set,
+        // and the settings are overwritten so the queue ends up as requested.
+        if results.iter().any(|created| !created) {
+            pipe()
+                .atomic()
+                .cmd("HSET")
+                .arg(&key)
+                .arg("vt")
+                .arg(hidden)
+                .arg("delay")
+                .arg(delay)
+                .arg("maxsize")
+                .arg(maxsize)
                 .query_async::<()>(conn)
                 .await?;
         }
 
-        Ok(queue_uid)
+        Ok(())
+    }
+
+    /// Same as "create_queue" but it returns false instead of failing when the queue already exists
+    pub async fn create_queue_if_not_exists(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        hidden: Option<Duration>,
+        delay: Option<Duration>,
+        maxsize: Option<MaxSize>,
+    ) -> RsmqResult<bool> {
+        match self.create_queue(conn, qname, hidden, delay, maxsize).await {
+            Ok(()) => Ok(true),
+            Err(RsmqError::QueueExists) => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Same as "create_queue" but with the settings of `config`. If `config.max_receives` is set, the messages
+    /// received that many times are moved by "receive_message" (and "receive_messages" and "receive_multi") to the dead-letter queue
+    /// instead of being delivered again. The dead-letter queue is created with the default settings if it doesn't
+    /// exist
+    pub async fn create_queue_with_config(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        config: &RsmqQueueConfig,
+    ) -> RsmqResult<()> {
+        let qname = self.queue_name(qname);
+
+        let dead_letter = match config.max_receives {
+            Some(max_receives) => {
+                number_in_range("max_receives", max_receives, 1, u64::MAX)?;
+
+                let dead_letter_queue = config
+                    .dead_letter_queue
+                    .as_deref()
+                    .map(|dead_letter_queue| self.queue_name(dead_letter_queue).to_string())
+                    .unwrap_or_else(|| dlq_name(qname));
+
+                valid_name_format(&dead_letter_queue)?;
+
+                // Moving the messages to the same queue would delete them
+                if dead_letter_queue == qname {
+                    return Err(RsmqError::InvalidFormat(dead_letter_queue));
+                }
+
+                Some((max_receives, dead_letter_queue))
+            }
+            None => None,
+        };
+
+        self.create_queue(conn, qname, config.hidden, config.delay, config.maxsize)
+            .await?;
+
+        if let Some((max_receives, dead_letter_queue)) = dead_letter {
+            match self
+                .create_queue(conn, &dead_letter_queue, None, None, None)
+                .await
+            {
+                Ok(()) | Err(RsmqError::QueueExists) => {}
+                Err(error) => return Err(error),
+            }
+
+            redis::cmd("HSET")
+                .arg(format!("{}:{}:Q", self.ns, qname))
+                .arg("maxrc")
+                .arg(max_receives)
+                .arg("dlq")
+                .arg(dead_letter_queue)
+                .query_async::<()>(conn)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Same as "delete_message" but with the handle returned by "RsmqMessage::receipt_handle". The message is only
+    /// deleted if it wasn't received again since then
+    pub async fn delete_by_handle(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        handle: &str,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<bool> {
+        let qname = self.queue_name(qname);
+
+        let (id, receive_count) = parse_handle(handle)?;
+
+        let key = format!("{}:{}", self.ns, qname);
+
+        let (deleted, deduplication_id): (bool, Option<String>) = cached_script
+            .invoke_delete_message_by_handle(
+                conn,
+                key.clone(),
+                id.to_string(),
+                receive_count,
+                &message_fields(id),
+            )
+            .await?;
+
+        if deleted {
+            self.forget_body(id);
+        }
+
+        if let Some(deduplication_id) = deduplication_id {
+            cached_script
+                .invoke_release_deduplication::<(), T>(conn, key, deduplication_id, id.to_string())
+                .await?;
+        }
+
+        if deleted {
+            self.audit(conn, qname, RsmqAuditOperation::Delete, &[id])
+                .await?;
+        }
+
+        if let Some(metrics) = &self.metrics {
+            metrics.on_delete(qname, deleted);
+        }
+
+        Ok(deleted)
+    }
+
+    /// Deletes a message from the queue.
+    ///
+    /// Important to use when you are using receive_message.
+    pub async fn delete_message(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        id: &str,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<bool> {
+        let qname = self.queue_name(qname);
+
+        let result = traced("delete_message", qname, Some(id), async {
+            let key = format!("{}:{}", self.ns, qname);
+
+            let results: (u16, Option<String>, u16) = pipe()
+                .atomic()
+                .cmd("ZREM")
+                .arg(&key)
+                .arg(id)
+                .cmd("HGET")
+                .arg(format!("{}:Q", &key))
+                .arg(format!("{}:{}", id, DEDUPLICATION_FIELD))
+                .cmd("HDEL")
+                .arg(format!("{}:Q", &key))
+                .arg(id)
+                .arg(message_fields(id))
+                .query_async(conn)
+                .await?;
+
+            self.forget_body(id);
+
+            if let Some(deduplication_id) = results.1 {
+                cached_script
+                    .invoke_release_deduplication::<(), T>(
+                        conn,
+                        key,
+                        deduplication_id,
+                        id.to_string(),
+                    )
+                    .await?;
+            }
+
+            if results.0 == 1 && results.2 > 0 {
+                self.audit(conn, qname, RsmqAuditOperation::Delete, &[id])
+                    .await?;
+
+                return Ok(true);
+            }
+
+            Ok(false)
+        })
+        .await?;
+
+        if let Some(metrics) = &self.metrics {
+            metrics.on_delete(qname, result);
+        }
+
+        Ok(result)
+    }
+
+    /// Same as "delete_message" but the message is deleted only if its body is still `expected`
+    pub async fn delete_message_if_unchanged(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        id: &str,
+        expected: &[u8],
+        cached_script: &CachedScript,
+    ) -> RsmqResult<bool> {
+        let qname = self.queue_name(qname);
+
+        let key = format!("{}:{}", self.ns, qname);
+
+        // The stored body is compressed the same way it was when sent
+        #[cfg(feature = "compression")]
+        let compressed = match self.compress_threshold {
+            Some(threshold) => crate::compression::compress(expected, threshold),
+            None => Cow::Borrowed(expected),
+        };
+        #[cfg(feature = "compression")]
+        let expected: &[u8] = &compressed;
+
+        let (deleted, deduplication_id): (bool, Option<String>) = cached_script
+            .invoke_delete_message_if_unchanged(
+                conn,
+                key.clone(),
+                id.to_string(),
+                expected,
+                &message_fields(id),
+            )
+            .await?;
+
+        if deleted {
+            self.forget_body(id);
+        }
+
+        if let Some(deduplication_id) = deduplication_id {
+            cached_script
+                .invoke_release_deduplication::<(), T>(conn, key, deduplication_id, id.to_string())
+                .await?;
+        }
+
+        if deleted {
+            self.audit(conn, qname, RsmqAuditOperation::Delete, &[id])
+                .await?;
+        }
+
+        if let Some(metrics) = &self.metrics {
+            metrics.on_delete(qname, deleted);
+        }
+
+        Ok(deleted)
+    }
+
+    /// Same as "delete_message" but it fails with `RsmqError::InconsistentState` if the message was only partially
+    /// stored (the id in the queue without its body, or the other way around). The leftovers are removed anyway.
+    pub async fn delete_message_strict(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        id: &str,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<bool> {
+        let qname = self.queue_name(qname);
+
+        let key = format!("{}:{}", self.ns, qname);
+
+        let (removed, body, deduplication_id, fields): (u16, u16, Option<String>, u16) = pipe()
+            .atomic()
+            .cmd("ZREM")
+            .arg(&key)
+            .arg(id)
+            .cmd("HDEL")
+            .arg(format!("{}:Q", &key))
+            .arg(id)
+            .cmd("HGET")
+            .arg(format!("{}:Q", &key))
+            .arg(format!("{}:{}", id, DEDUPLICATION_FIELD))
+            .cmd("HDEL")
+            .arg(format!("{}:Q", &key))
+            .arg(message_fields(id))
+            .query_async(conn)
+            .await?;
+
+        self.forget_body(id);
+
+        if let Some(deduplication_id) = deduplication_id {
+            cached_script
+                .invoke_release_deduplication::<(), T>(conn, key, deduplication_id, id.to_string())
+                .await?;
+        }
+
+        let results = (removed, body, fields);
+
+        match results {
+            (1, 1, _) => {
+                self.audit(conn, qname, RsmqAuditOperation::Delete, &[id])
+                    .await?;
+
+                if let Some(metrics) = &self.metrics {
+                    metrics.on_delete(qname, true);
+                }
+
+                Ok(true)
+            }
+            (0, 0, 0) => {
+                if let Some(metrics) = &self.metrics {
+                    metrics.on_delete(qname, false);
+                }
+
+                Ok(false)
+            }
+            (1, 0, _) => Err(RsmqError::InconsistentState(format!(
+                "message {} was in the queue {} but its body was missing",
+                id, qname
+            ))),
+            (_, _, fields) => Err(RsmqError::InconsistentState(format!(
+                "message {} was not in the queue {} but {} of its fields were stored (body included: {})",
+                id,
+                qname,
+                fields + results.1,
+                results.1 == 1
+            ))),
+        }
+    }
+
+    /// Same as "delete_message" for several messages of the queue, in a single transaction. Returns, in the same order
+    /// as `ids`, if each message existed and was deleted
+    pub async fn delete_messages(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        ids: &[&str],
+        cached_script: &CachedScript,
+    ) -> RsmqResult<Vec<bool>> {
+        let qname = self.queue_name(qname);
+
+        let deleted = traced("delete_messages", qname, None, async {
+            self.check_batch_size(ids.len())?;
+
+            if ids.is_empty() {
+                return Ok(Vec::new());
+            }
+
+            let key = format!("{}:{}", self.ns, qname);
+
+            let mut pipeline = pipe();
+            pipeline.atomic();
+
+            for id in ids {
+                pipeline
+                    .cmd("ZREM")
+                    .arg(&key)
+                    .arg(id)
+                    .cmd("HGET")
+                    .arg(format!("{}:Q", &key))
+                    .arg(format!("{}:{}", id, DEDUPLICATION_FIELD))
+                    .cmd("HDEL")
+                    .arg(format!("{}:Q", &key))
+                    .arg(id)
+                    .arg(message_fields(id));
+            }
+
+            let results: Vec<(u16, Option<String>, u16)> = pipeline.query_async(conn).await?;
+
+            let mut deleted = Vec::with_capacity(ids.len());
+
+            for (id, (removed, deduplication_id, fields)) in ids.iter().zip(results) {
+                self.forget_body(id);
+
+                // Only the messages sent with a deduplication id need another round trip
+                if let Some(deduplication_id) = deduplication_id {
+                    cached_script
+                        .invoke_release_deduplication::<(), T>(
+                            conn,
+                            key.clone(),
+                            deduplication_id,
+                            id.to_string(),
+                        )
+                        .await?;
+                }
+
+                deleted.push(removed == 1 && fields > 0);
+            }
+
+            let deleted_ids = ids
+                .iter()
+                .zip(&deleted)
+                .filter(|(_, deleted)| **deleted)
+                .map(|(id, _)| *id)
+                .collect::<Vec<_>>();
+
+            self.audit(conn, qname, RsmqAuditOperation::Delete, &deleted_ids)
+                .await?;
+
+            Ok(deleted)
+        })
+        .await?;
+
+        if let Some(metrics) = &self.metrics {
+            for deleted in &deleted {
+                metrics.on_delete(qname, *deleted);
+            }
+        }
+
+        Ok(deleted)
+    }
+
+    /// Deletes the queue and all the messages on it
+    pub async fn delete_queue(&self, conn: &mut T, qname: &str) -> RsmqResult<()> {
+        let qname = self.queue_name(qname);
+
+        let results: (u16, u16) = self
+            .add_delete_queue_commands(conn, pipe().atomic(), qname)
+            .await?
+            .query_async(conn)
+            .await?;
+
+        if results.0 == 0 {
+            return Err(RsmqError::QueueNotFound);
+        }
+
+        Ok(())
+    }
+
+    /// Deletes the queue only if it has no messages. Returns false if the queue still has messages
+    pub async fn delete_queue_if_empty(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<bool> {
+        let qname = self.queue_name(qname);
+
+        let result = cached_script
+            .invoke_delete_queue_if_empty::<i8, T>(
+                conn,
+                format!("{}:{}", self.ns, qname),
+                format!("{}:QUEUES", self.ns),
+                qname.to_string(),
+            )
+            .await?;
+
+        match result {
+            -1 => Err(RsmqError::QueueNotFound),
+            deleted => Ok(deleted == 1),
+        }
+    }
+
+    /// Same as "delete_queue" but it returns the attributes the queue had, read in the same transaction that deletes
+    /// it
+    pub async fn delete_queue_returning_attributes(
+        &self,
+        conn: &mut T,
+        qname: &str,
+    ) -> RsmqResult<RsmqQueueAttributes> {
+        let qname = self.queue_name(qname);
+
+        let key = format!("{}:{}", self.ns, qname);
+
+        let time = self.redis_time(conn).await?;
+
+        // The message scores are always in milliseconds
+        let now = time.0 * 1000 + time.1 / 1000;
+
+        let mut commands = pipe();
+        add_queue_attributes_commands(commands.atomic(), &key, now);
+
+        let (fields, msgs, hiddenmsgs, dead_letter, deleted, _): (
+            Vec<Option<i64>>,
+            u64,
+            u64,
+            DeadLetterFields,
+            u16,
+            u16,
+        ) = self
+            .add_delete_queue_commands(conn, &mut commands, qname)
+            .await?
+            .query_async(conn)
+            .await?;
+
+        if deleted == 0 {
+            return Err(RsmqError::QueueNotFound);
+        }
+
+        build_queue_attributes(fields, msgs, hiddenmsgs, dead_letter)
+    }
+
+    /// Runs a custom Lua script with the keys of the queues. Each queue adds two keys: its sorted set and its hash
+    pub async fn eval_script(
+        &self,
+        conn: &mut T,
+        script: &str,
+        qnames: &[&str],
+        args: &[&str],
+    ) -> RsmqResult<redis::Value> {
+        let script = redis::Script::new(script);
+        let mut invocation = script.prepare_invoke();
+
+        for qname in qnames {
+            let key = format!("{}:{}", self.ns, self.queue_name(qname));
+
+            invocation.key(&key).key(format!("{}:Q", key));
+        }
+
+        invocation.arg(args);
+
+        invocation.invoke_async(conn).await.map_err(Into::into)
+    }
+
+    /// Calls `f` with every message of the queue, in delivery order, without changing them. Messages are read in
+    /// pages of `FOR_EACH_PAGE_SIZE`. Returns true if it stopped because of `max_scan`.
+    pub async fn for_each_message<E, F>(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        include_hidden: bool,
+        mut f: F,
+    ) -> RsmqResult<bool>
+    where
+        E: TryFrom<RedisBytes, Error: RsmqDecodeError>,
+        F: FnMut(RsmqMessage<E>) + Send,
+    {
+        let qname = self.queue_name(qname);
+
+        let queue = self.get_queue(conn, qname, false).await?;
+
+        let key = format!("{}:{}", self.ns, qname);
+        let max = if include_hidden {
+            "+inf".to_string()
+        } else {
+            queue.ts.to_string()
+        };
+
+        // The cursor is the score of the last message returned. Messages sharing that score are fetched again, so
+        // the ones already returned are remembered and skipped.
+        let mut min = "-inf".to_string();
+        let mut returned_at_min: Vec<String> = Vec::new();
+        let mut scanned = 0;
+
+        loop {
+            let limit = FOR_EACH_PAGE_SIZE + returned_at_min.len();
+
+            let mut page: Vec<(String, u64)> = redis::cmd("ZRANGEBYSCORE")
+                .arg(&key)
+                .arg(&min)
+                .arg(&max)
+                .arg("WITHSCORES")
+                .arg("LIMIT")
+                .arg(0)
+                .arg(limit)
+                .query_async(conn)
+                .await?;
+
+            let last_page = page.len() < limit;
+
+            page.retain(|(id, _)| !returned_at_min.contains(id));
+
+            let last_score = match page.last() {
+                Some((_, score)) => *score,
+                None => break,
+            };
+
+            if min != last_score.to_string() {
+                min = last_score.to_string();
+                returned_at_min.clear();
+            }
+
+            returned_at_min.extend(
+                page.iter()
+                    .filter(|(_, score)| *score == last_score)
+                    .map(|(id, _)| id.clone()),
+            );
+
+            let mut pipeline = pipe();
+
+            for (id, _) in &page {
+                pipeline
+                    .cmd("HMGET")
+                    .arg(format!("{}:Q", key))
+                    .arg(id)
+                    .arg(format!("{}:rc", id))
+                    .arg(format!("{}:fr", id))
+                    .arg(format!("{}:{}", id, EXPIRES_AT_FIELD));
+            }
+
+            let bodies: Vec<StoredMessage> = pipeline.query_async(conn).await?;
+
+            for ((id, _), (body, rc, fr, expires_at)) in page.into_iter().zip(bodies) {
+                // Deleted since the page was read
+                let Some(body) = body else {
+                    continue;
+                };
+
+                if self.max_scan.is_some_and(|max_scan| scanned >= max_scan) {
+                    return Ok(true);
+                }
+
+                let expired = expires_at.is_some_and(|expires_at| expires_at < queue.ts);
+
+                f(build_message(
+                    id,
+                    body,
+                    rc.unwrap_or(0),
+                    fr.unwrap_or(0),
+                    expired,
+                    self.id_length,
+                )?);
+                scanned += 1;
+            }
+
+            if last_page {
+                break;
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Returns the highest number of messages the queue had since the last "reset_max_depth", as recorded by the send
+    /// methods when `track_max_depth` is enabled. It's never lower than the current number of messages
+    pub async fn get_max_depth(&self, conn: &mut T, qname: &str) -> RsmqResult<u64> {
+        let qname = self.queue_name(qname);
+
+        let key = format!("{}:{}", self.ns, qname);
+
+        let ((vt, max_depth), depth): ((Option<String>, Option<u64>), u64) = pipe()
+            .atomic()
+            .cmd("HMGET")
+            .arg(format!("{}:Q", key))
+            .arg("vt")
+            .arg("maxdepth")
+            .cmd("ZCARD")
+            .arg(&key)
+            .query_async(conn)
+            .await?;
+
+        if vt.is_none() {
+            return Err(RsmqError::QueueNotFound);
+        }
+
+        Ok(max_depth.unwrap_or(0).max(depth))
+    }
+
+    /// Returns the message with the given id, without receiving it: its visibility and counters don't change.
+    /// Returns None if the message is not in the queue
+    pub async fn get_message<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        id: &str,
+    ) -> RsmqResult<Option<RsmqMessage<E>>> {
+        let qname = self.queue_name(qname);
+
+        let queue = self.get_queue(conn, qname, false).await?;
+
+        let key = format!("{}:{}", self.ns, qname);
+
+        // The id is also checked in the sorted set, or an id like "vt" would return a setting of the queue
+        let (score, (body, rc, fr, expires_at)): (Option<f64>, StoredMessage) = pipe()
+            .atomic()
+            .cmd("ZSCORE")
+            .arg(&key)
+            .arg(id)
+            .cmd("HMGET")
+            .arg(format!("{}:Q", key))
+            .arg(id)
+            .arg(format!("{}:rc", id))
+            .arg(format!("{}:fr", id))
+            .arg(format!("{}:{}", id, EXPIRES_AT_FIELD))
+            .query_async(conn)
+            .await?;
+
+        let body = match (score, body) {
+            (Some(_), Some(body)) => body,
+            _ => return Ok(None),
+        };
+
+        let expired = expires_at.is_some_and(|expires_at| expires_at < queue.ts);
+
+        Ok(Some(build_message(
+            id.to_string(),
+            body,
+            rc.unwrap_or(0),
+            fr.unwrap_or(0),
+            expired,
+            self.id_length,
+        )?))
+    }
+
+    /// Returns the queue attributes and statistics
+    pub async fn get_queue_attributes(
+        &self,
+        conn: &mut T,
+        qname: &str,
+    ) -> RsmqResult<RsmqQueueAttributes> {
+        let qname = self.queue_name(qname);
+
+        let key = format!("{}:{}", self.ns, qname);
+
+        let hash_key = format!("{}:Q", key);
+
+        let time = self.redis_time(conn).await?;
+
+        // The message scores are always in milliseconds
+        let now = time.0 * 1000 + time.1 / 1000;
+
+        let cached = self
+            .attributes_cache
+            .as_ref()
+            .and_then(|cache| cache.get(&hash_key));
+
+        // The message counts depend on the current time, so they are never cached
+        let result: QueueAttributesResult<i64> = match cached {
+            Some((fields, dead_letter)) => {
+                let (msgs, hiddenmsgs): (u64, u64) = pipe()
+                    .atomic()
+                    .cmd("ZCARD")
+                    .arg(&key)
+                    .cmd("ZCOUNT")
+                    .arg(&key)
+                    .arg(format!("({}", now))
+                    .arg("+inf")
+                    .query_async(conn)
+                    .await?;
+
+                (fields, msgs, hiddenmsgs, dead_letter)
+            }
+            None => {
+                let generation = self
+                    .attributes_cache
+                    .as_ref()
+                    .map(AttributesCache::generation);
+
+                let result: QueueAttributesResult<i64> =
+                    add_queue_attributes_commands(pipe().atomic(), &key, now)
+                        .query_async(conn)
+                        .await?;
+
+                if let (Some(cache), Some(generation)) = (&self.attributes_cache, generation) {
+                    if !result.0.contains(&None) {
+                        cache.insert(hash_key, (result.0.clone(), result.3.clone()), generation);
+                    }
+                }
+
+                result
+            }
+        };
+
+        build_queue_attributes(result.0, result.1, result.2, result.3)
+    }
+
+    /// Same as "get_queue_attributes" but it fails if the stored vt, delay or maxsize cannot be parsed instead of
+    /// using 0. It always reads Redis, ignoring the attributes cache
+    pub async fn get_queue_attributes_checked(
+        &self,
+        conn: &mut T,
+        qname: &str,
+    ) -> RsmqResult<RsmqQueueAttributes> {
+        let qname = self.queue_name(qname);
+
+        let key = format!("{}:{}", self.ns, qname);
+
+        let time = self.redis_time(conn).await?;
+
+        // The message scores are always in milliseconds
+        let now = time.0 * 1000 + time.1 / 1000;
+
+        let (fields, msgs, hiddenmsgs, dead_letter): QueueAttributesResult<String> =
+            add_queue_attributes_commands(pipe().atomic(), &key, now)
+                .query_async(conn)
+                .await?;
+
+        let fields = fields
+            .into_iter()
+            .collect::<Option<Vec<String>>>()
+            .ok_or(RsmqError::QueueNotFound)?;
+
+        let vt: u64 = fields[0].parse().map_err(|_| RsmqError::CannotParseVT)?;
+        let delay: u64 = fields[1].parse().map_err(|_| RsmqError::CannotParseDelay)?;
+        let maxsize: i64 = fields[2]
+            .parse()
+            .map_err(|_| RsmqError::CannotParseMaxsize)?;
+        MaxSize::try_from(maxsize).map_err(|_| RsmqError::CannotParseMaxsize)?;
+
+        let mut numbers = vec![
+            Some(i64::try_from(vt).map_err(|_| RsmqError::CannotParseVT)?),
+            Some(i64::try_from(delay).map_err(|_| RsmqError::CannotParseDelay)?),
+            Some(maxsize),
+        ];
+
+        // The stats are only informative, so they are still read as 0 if they are not valid
+        numbers.extend(
+            fields[3..]
+                .iter()
+                .map(|field| Some(field.parse().unwrap_or(0))),
+        );
+
+        build_queue_attributes(numbers, msgs, hiddenmsgs, dead_letter)
+    }
+
+    /// Same as "get_queue_attributes" for several queues, read in a single transaction. Each queue has its own
+    /// result, so a missing queue doesn't fail the others. It always reads Redis, ignoring the attributes cache
+    pub async fn get_queue_attributes_many(
+        &self,
+        conn: &mut T,
+        qnames: &[&str],
+    ) -> RsmqResult<Vec<(String, RsmqResult<RsmqQueueAttributes>)>> {
+        self.check_batch_size(qnames.len())?;
+
+        let qnames = qnames
+            .iter()
+            .map(|qname| self.queue_name(qname))
+            .collect::<Vec<_>>();
+
+        let valid = qnames
+            .iter()
+            .filter(|qname| valid_name_format(qname).is_ok())
+            .collect::<Vec<_>>();
+
+        let mut results: Vec<QueueAttributesResult<i64>> = Vec::new();
+
+        if !valid.is_empty() {
+            let time = self.redis_time(conn).await?;
+            let now = time.0 * 1000 + time.1 / 1000;
+
+            let mut pipeline = pipe();
+            pipeline.atomic();
+
+            for qname in &valid {
+                add_queue_attributes_commands(
+                    &mut pipeline,
+                    &format!("{}:{}", self.ns, qname),
+                    now,
+                );
+            }
+
+            results = pipeline.query_async(conn).await?;
+        }
+
+        let mut results = results.into_iter();
+
+        Ok(qnames
+            .into_iter()
+            .map(|qname| {
+                let attributes = valid_name_format(qname).and_then(|()| {
+                    let (fields, msgs, hiddenmsgs, dead_letter) =
+                        results.next().ok_or_else(|| {
+                            RsmqError::InconsistentState(
+                                "Redis returned fewer results than queues".to_string(),
+                            )
+                        })?;
+
+                    build_queue_attributes(fields, msgs, hiddenmsgs, dead_letter)
+                });
+
+                (qname.to_string(), attributes)
+            })
+            .collect())
+    }
+
+    /// Returns the queues that have at least one visible message
+    pub async fn list_active_queues(&self, conn: &mut T) -> RsmqResult<Vec<String>> {
+        let queues = self.list_queues(conn).await?;
+
+        if queues.is_empty() {
+            return Ok(queues);
+        }
+
+        let time = self.redis_time(conn).await?;
+        let now = time.0 * 1000 + time.1 / 1000;
+
+        let mut pipeline = pipe();
+
+        for qname in &queues {
+            pipeline
+                .cmd("ZCOUNT")
+                .arg(format!("{}:{}", self.ns, qname))
+                .arg("-inf")
+                .arg(now);
+        }
+
+        let counts: Vec<u64> = pipeline.query_async(conn).await?;
+
+        Ok(queues
+            .into_iter()
+            .zip(counts)
+            .filter(|(_, count)| *count > 0)
+            .map(|(qname, _)| qname)
+            .collect())
+    }
+
+    /// Returns a list of queues in the namespace
+    pub async fn list_queues(&self, conn: &mut T) -> RsmqResult<Vec<String>> {
+        let queues = redis::cmd("SMEMBERS")
+            .arg(format!("{}:QUEUES", self.ns))
+            .query_async(conn)
+            .await?;
+
+        Ok(queues)
+    }
+
+    /// Returns every queue in the namespace with its attributes, read in a single transaction
+    pub async fn list_queues_with_attributes(
+        &self,
+        conn: &mut T,
+    ) -> RsmqResult<Vec<(String, RsmqQueueAttributes)>> {
+        let queues = self.list_queues(conn).await?;
+
+        if queues.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let time = self.redis_time(conn).await?;
+        let now = time.0 * 1000 + time.1 / 1000;
+
+        let mut pipeline = pipe();
+        pipeline.atomic();
+
+        for qname in &queues {
+            add_queue_attributes_commands(&mut pipeline, &format!("{}:{}", self.ns, qname), now);
+        }
+
+        let results: Vec<QueueAttributesResult<i64>> = pipeline.query_async(conn).await?;
+
+        // Queues deleted after listing them are skipped
+        Ok(queues
+            .into_iter()
+            .zip(results)
+            .filter_map(|(qname, (fields, msgs, hiddenmsgs, dead_letter))| {
+                build_queue_attributes(fields, msgs, hiddenmsgs, dead_letter)
+                    .ok()
+                    .map(|attributes| (qname, attributes))
+            })
+            .collect())
+    }
+
+    /// Returns how long the message will stay hidden before being delivered again. Zero if it is already visible
+    /// and None if the message doesn't exist.
+    pub async fn message_time_remaining(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        id: &str,
+    ) -> RsmqResult<Option<Duration>> {
+        let qname = self.queue_name(qname);
+
+        let queue = self.get_queue(conn, qname, false).await?;
+
+        let score: Option<f64> = redis::cmd("ZSCORE")
+            .arg(format!("{}:{}", self.ns, qname))
+            .arg(id)
+            .query_async(conn)
+            .await?;
+
+        Ok(score.map(|score| Duration::from_millis((score as u64).saturating_sub(queue.ts))))
+    }
+
+    /// Moves the message from the queue `from` to the queue `to`, where it is delivered after the delay of `to`.
+    /// Returns false if the message is not in `from`
+    pub async fn move_message(
+        &self,
+        conn: &mut T,
+        from: &str,
+        to: &str,
+        message_id: &str,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<bool> {
+        let from = self.queue_name(from);
+        let to = self.queue_name(to);
+
+        let queue = self.get_queue(conn, to, false).await?;
+
+        let moved = self
+            .move_messages(
+                conn,
+                from,
+                to,
+                score_after(queue.ts, get_redis_duration(None, &queue.delay))?,
+                &[message_id.to_string()],
+                cached_script,
+            )
+            .await?;
+
+        Ok(moved == 1)
+    }
+
+    /// Returns the next message that would be received without changing it (it stays visible and its receive count
+    /// doesn't increase)
+    pub async fn peek_message<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<Option<RsmqMessage<E>>> {
+        let qname = self.queue_name(qname);
+
+        let queue = self.get_queue(conn, qname, false).await?;
+
+        let result: PeekMessageResult = cached_script
+            .invoke_peek_message(
+                conn,
+                format!("{}:{}", self.ns, qname),
+                queue.ts.to_string(),
+                true,
+            )
+            .await?;
+
+        if !result.0 {
+            return Ok(None);
+        }
+
+        Ok(Some(build_message(
+            result.1,
+            result.5,
+            result.2,
+            result.3,
+            result.6,
+            self.id_length,
+        )?))
+    }
+
+    /// Returns the metadata of the next message that would be received, without its body and without changing it
+    pub async fn peek_message_meta(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<Option<RsmqMessageMeta>> {
+        let qname = self.queue_name(qname);
+
+        let queue = self.get_queue(conn, qname, false).await?;
+
+        let result: PeekMessageResult = cached_script
+            .invoke_peek_message(
+                conn,
+                format!("{}:{}", self.ns, qname),
+                queue.ts.to_string(),
+                false,
+            )
+            .await?;
+
+        if !result.0 {
+            return Ok(None);
+        }
+
+        Ok(Some(RsmqMessageMeta {
+            sent: sent_from_id(&result.1, self.id_length),
+            id: result.1,
+            rc: result.2,
+            fr: result.3,
+            size: result.4,
+        }))
+    }
+
+    /// Deletes and returns a message. Be aware that using this you may end with deleted & unprocessed messages.
+    pub async fn pop_message<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<Option<RsmqMessage<E>>> {
+        let qname = self.queue_name(qname);
+
+        let queue = match self.get_receivable_queue(conn, qname).await? {
+            Some(queue) => queue,
+            None => {
+                self.report_received(qname, 0);
+                return Ok(None);
+            }
+        };
+
+        let result: ReceiveMessageResult = cached_script
+            .invoke_receive_message(
+                conn,
+                &[
+                    format!("{}:{}", self.ns, qname),
+                    queue.ts.to_string(),
+                    queue.ts.to_string(),
+                    format!("{}:", self.ns),
+                ],
+                "true".to_string(),
+                false,
+            )
+            .await?;
+
+        self.report_received(qname, u64::from(result.0));
+
+        if !result.0 {
+            return Ok(None);
+        }
+
+        self.audit(conn, qname, RsmqAuditOperation::Pop, &[&result.1])
+            .await?;
+
+        self.forget_body(&result.1);
+        self.report_deleted(qname, 1);
+
+        Ok(Some(build_message(
+            result.1,
+            result.2.unwrap_or_default(),
+            result.3,
+            result.4,
+            result.5,
+            self.id_length,
+        )?))
+    }
+
+    /// Deletes and returns up to `count` messages. Be aware that using this you may end with deleted & unprocessed
+    /// messages.
+    pub async fn pop_message_batch<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        count: usize,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<Vec<RsmqMessage<E>>> {
+        let qname = self.queue_name(qname);
+
+        self.check_batch_size(count)?;
+
+        let queue = match self.get_receivable_queue(conn, qname).await? {
+            Some(queue) => queue,
+            None => {
+                self.report_received(qname, 0);
+                return Ok(Vec::new());
+            }
+        };
+
+        if count == 0 {
+            self.report_received(qname, 0);
+            return Ok(Vec::new());
+        }
+
+        let result: Vec<ReceiveBatchEntry> = cached_script
+            .invoke_receive_message_batch(
+                conn,
+                &[
+                    format!("{}:{}", self.ns, qname),
+                    queue.ts.to_string(),
+                    queue.ts.to_string(),
+                    format!("{}:", self.ns),
+                ],
+                "true".to_string(),
+                count,
+            )
+            .await?;
+
+        let ids: Vec<&str> = result.iter().map(|(id, ..)| id.as_str()).collect();
+        self.audit(conn, qname, RsmqAuditOperation::Pop, &ids)
+            .await?;
+
+        for id in ids {
+            self.forget_body(id);
+        }
+
+        self.report_received(qname, result.len() as u64);
+        self.report_deleted(qname, result.len() as u64);
+
+        result
+            .into_iter()
+            .map(|(id, body, rc, fr, expired)| {
+                build_message(id, body, rc, fr, expired, self.id_length)
+            })
+            .collect()
+    }
+
+    /// Moves the messages of the retry queue (see "retry_message") whose delay is over back to the queue, where they
+    /// are visible right away. Returns how many messages were moved
+    pub async fn promote_matured_retries(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<u64> {
+        let qname = self.queue_name(qname);
+
+        let retry_qname = retry_queue_name(qname);
+
+        let retry_queue = match self.get_queue(conn, &retry_qname, false).await {
+            Ok(queue) => queue,
+            // Nothing was retried yet
+            Err(RsmqError::QueueNotFound) => return Ok(0),
+            Err(error) => return Err(error),
+        };
+
+        let mut moved = 0;
+
+        loop {
+            let ids: Vec<String> = redis::cmd("ZRANGEBYSCORE")
+                .arg(format!("{}:{}", self.ns, retry_qname))
+                .arg("-inf")
+                .arg(retry_queue.ts)
+                .arg("LIMIT")
+                .arg(0)
+                .arg(self.max_batch_size)
+                .query_async(conn)
+                .await?;
+
+            if ids.is_empty() {
+                break;
+            }
+
+            moved += self
+                .move_messages(
+                    conn,
+                    &retry_qname,
+                    qname,
+                    retry_queue.ts,
+                    &ids,
+                    cached_script,
+                )
+                .await?;
+
+            if ids.len() < self.max_batch_size {
+                break;
+            }
+        }
+
+        Ok(moved)
+    }
+
+    /// Deletes every message of the dead-letter queue of `qname`. Returns how many messages were deleted
+    pub async fn purge_dlq(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<u64> {
+        let qname = self.queue_name(qname);
+
+        let dead_letter_queue = self.dead_letter_queue(conn, qname).await?;
+
+        self.purge_messages(conn, &dead_letter_queue, cached_script)
+            .await
+    }
+
+    /// Deletes every message of the queue, keeping the queue with its settings. Returns how many messages were deleted
+    pub async fn purge_queue(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<u64> {
+        let qname = self.queue_name(qname);
+
+        self.purge_messages(conn, qname, cached_script).await
+    }
+
+    /// Returns if the queue exists, without reading its attributes
+    pub async fn queue_exists(&self, conn: &mut T, qname: &str) -> RsmqResult<bool> {
+        let qname = self.queue_name(qname);
+
+        redis::cmd("EXISTS")
+            .arg(format!("{}:{}:Q", self.ns, qname))
+            .query_async(conn)
+            .await
+            .map_err(Into::into)
+    }
+
+    /// Returns the audit log records of the queue added since the `since` timestamp (epoch in milliseconds)
+    pub async fn read_audit(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        since: u64,
+    ) -> RsmqResult<Vec<RsmqAuditRecord>> {
+        let qname = self.queue_name(qname);
+
+        let entries: Vec<(String, Vec<String>)> = redis::cmd("XRANGE")
+            .arg(format!("{}:{}:audit", self.ns, qname))
+            .arg(since)
+            .arg("+")
+            .query_async(conn)
+            .await?;
+
+        Ok(entries
+            .into_iter()
+            .filter_map(|(entry_id, fields)| {
+                let mut operation = None;
+                let mut message_id = None;
+
+                for pair in fields.chunks(2) {
+                    match (pair[0].as_str(), pair.get(1)) {
+                        ("op", Some(value)) => operation = RsmqAuditOperation::from_str(value),
+                        ("id", Some(value)) => message_id = Some(value.clone()),
+                        _ => {}
+                    }
+                }
+
+                Some(RsmqAuditRecord {
+                    timestamp: entry_id
+                        .split('-')
+                        .next()
+                        .and_then(|ms| ms.parse().ok())
+                        .unwrap_or(0),
+                    entry_id,
+                    operation: operation?,
+                    message_id: message_id?,
+                })
+            })
+            .collect())
+    }
+
+    /// Channel where the sends publish the number of messages of the queue when `realtime` is enabled
+    pub fn realtime_channel(&self, qname: &str) -> String {
+        format!("{}:rt:{}", self.ns, self.queue_name(qname))
+    }
+
+    /// Same as "receive_message" but from the dead-letter queue of `qname`
+    pub async fn receive_from_dlq<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        hidden: Option<Duration>,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<Option<RsmqMessage<E>>> {
+        let qname = self.queue_name(qname);
+
+        let dead_letter_queue = self.dead_letter_queue(conn, qname).await?;
+
+        self.receive_message(conn, &dead_letter_queue, hidden, cached_script)
+            .await
+    }
+
+    /// Returns a message. The message stays hidden for some time (defined by "hidden"
+    /// argument or the queue settings). After that time, the message will be redelivered.
+    /// In order to avoid the redelivery, you need to use the "delete_message" after this function.
+    pub async fn receive_message<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        hidden: Option<Duration>,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<Option<RsmqMessage<E>>> {
+        Ok(self
+            .receive_message_with_sequence(conn, qname, hidden, cached_script)
+            .await?
+            .map(|(message, _)| message))
+    }
+
+    /// Same as "receive_message" but it also returns the total received count of the queue (`totalrecv`) right
+    /// after this receive, read in the same script
+    pub async fn receive_message_with_sequence<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        hidden: Option<Duration>,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<Option<(RsmqMessage<E>, u64)>> {
+        let qname = self.queue_name(qname);
+
+        Ok(self
+            .receive_message_matching(conn, qname, hidden, None, false, cached_script)
+            .await?
+            .0)
+    }
+
+    /// Same as "receive_message" but, while the queue is empty, it keeps trying (waiting a bit longer each time, up to
+    /// `MAX_BLOCKING_POLL_INTERVAL`) until a message is received or `timeout` passes. A `timeout` too big to be
+    /// added to the current time (like `Duration::MAX`) waits forever
+    pub async fn receive_message_blocking<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        hidden: Option<Duration>,
+        timeout: Duration,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<Option<RsmqMessage<E>>> {
+        let qname = self.queue_name(qname);
+
+        let deadline = Instant::now().checked_add(timeout);
+        let mut backoff =
+            PollBackoff::new(MIN_BLOCKING_POLL_INTERVAL, MAX_BLOCKING_POLL_INTERVAL, 2.0);
+
+        loop {
+            // Another client can receive a new message first, in that case this one just keeps waiting
+            if let Some(message) = self
+                .receive_message::<E>(conn, qname, hidden, cached_script)
+                .await?
+            {
+                return Ok(Some(message));
+            }
+
+            let interval = backoff.next_interval();
+
+            let interval = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+
+                    if remaining.is_zero() {
+                        return Ok(None);
+                    }
+
+                    interval.min(remaining)
+                }
+                None => interval,
+            };
+
+            futures_timer::Delay::new(interval).await;
+        }
+    }
+
+    /// Same as "receive_message" but the queue is created with `config` first if it doesn't exist
+    pub async fn receive_message_ensuring<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        config: &RsmqQueueConfig,
+        hidden: Option<Duration>,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<Option<RsmqMessage<E>>> {
+        let qname = self.queue_name(qname);
+
+        match self.create_queue_with_config(conn, qname, config).await {
+            Ok(()) | Err(RsmqError::QueueExists) => {}
+            Err(error) => return Err(error),
+        }
+
+        self.receive_message(conn, qname, hidden, cached_script)
+            .await
+    }
+
+    /// Same as "receive_message" but the message is deserialized from JSON
+    #[cfg(feature = "serde")]
+    pub async fn receive_message_json<M: serde::de::DeserializeOwned>(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        hidden: Option<Duration>,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<Option<RsmqMessage<M>>> {
+        let qname = self.queue_name(qname);
+
+        let message = match self
+            .receive_message::<Vec<u8>>(conn, qname, hidden, cached_script)
+            .await?
+        {
+            Some(message) => message,
+            None => return Ok(None),
+        };
+
+        let payload = serde_json::from_slice(&message.message).map_err(|error| {
+            RsmqError::CannotDecodeJson(message.message.clone(), error.to_string())
+        })?;
+
+        Ok(Some(RsmqMessage {
+            id: message.id,
+            message: payload,
+            rc: message.rc,
+            fr: message.fr,
+            sent: message.sent,
+            expired: message.expired,
+        }))
+    }
+
+    /// Same as "receive_message" but it also returns the OpenTelemetry context injected by "send_message_traced". If
+    /// the message was sent without it, the returned context is the current one.
+    #[cfg(feature = "otel")]
+    pub async fn receive_message_traced<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        hidden: Option<Duration>,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<Option<(RsmqMessage<E>, opentelemetry::Context)>> {
+        let qname = self.queue_name(qname);
+
+        // Decoding happens at the end so `E` doesn't need to be `Send` across the HGET
+        let message = match self
+            .receive_message::<Vec<u8>>(conn, qname, hidden, cached_script)
+            .await?
+        {
+            Some(message) => message,
+            None => return Ok(None),
+        };
+
+        let traceparent: Option<String> = redis::cmd("HGET")
+            .arg(format!("{}:{}:Q", self.ns, qname))
+            .arg(format!("{}:{}", message.id, TRACEPARENT_FIELD))
+            .query_async(conn)
+            .await?;
+
+        let carrier: HashMap<String, String> = traceparent
+            .map(|traceparent| (TRACEPARENT_HEADER.to_string(), traceparent))
+            .into_iter()
+            .collect();
+
+        let context = opentelemetry::global::get_text_map_propagator(|propagator| {
+            propagator.extract(&carrier)
+        });
+
+        let body =
+            E::try_from(RedisBytes(message.message)).map_err(RsmqDecodeError::into_rsmq_error)?;
+
+        Ok(Some((
+            RsmqMessage {
+                id: message.id,
+                message: body,
+                rc: message.rc,
+                fr: message.fr,
+                sent: message.sent,
+                expired: message.expired,
+            },
+            context,
+        )))
+    }
+
+    /// Same as "receive_message" but it receives the first message sent with the attribute `attribute` set to
+    /// `value` (see "send_message_with_attributes"). The other messages are left untouched
+    pub async fn receive_message_where<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        attribute: &str,
+        value: &str,
+        hidden: Option<Duration>,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<Option<RsmqMessage<E>>> {
+        let qname = self.queue_name(qname);
+
+        Ok(self
+            .receive_message_matching(
+                conn,
+                qname,
+                hidden,
+                Some((attribute, value)),
+                false,
+                cached_script,
+            )
+            .await?
+            .0
+            .map(|(message, _)| message))
+    }
+
+    /// Same as "receive_message" but it also returns the queue attributes, read in the same transaction (so the
+    /// received message already counts as hidden). As there are no attributes to return, a missing queue is an error
+    /// even with `missing_queue_as_empty`
+    pub async fn receive_message_with_attributes<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        hidden: Option<Duration>,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<(Option<RsmqMessage<E>>, RsmqQueueAttributes)> {
+        let qname = self.queue_name(qname);
+
+        match self
+            .receive_message_matching(conn, qname, hidden, None, true, cached_script)
+            .await?
+        {
+            (message, Some(attributes)) => Ok((message.map(|(message, _)| message), attributes)),
+            (_, None) => Err(RsmqError::QueueNotFound),
+        }
+    }
+
+    /// Same as "receive_message" but it also returns the queue to reply to given to "send_message_with_reply_to"
+    pub async fn receive_message_with_reply_to<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        hidden: Option<Duration>,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<Option<(RsmqMessage<E>, Option<String>)>> {
+        let qname = self.queue_name(qname);
+
+        // Decoding happens at the end so `E` doesn't need to be `Send` across the HGET
+        let message = match self
+            .receive_message::<Vec<u8>>(conn, qname, hidden, cached_script)
+            .await?
+        {
+            Some(message) => message,
+            None => return Ok(None),
+        };
+
+        let reply_to: Option<String> = redis::cmd("HGET")
+            .arg(format!("{}:{}:Q", self.ns, qname))
+            .arg(format!("{}:{}", message.id, REPLY_TO_FIELD))
+            .query_async(conn)
+            .await?;
+
+        let body =
+            E::try_from(RedisBytes(message.message)).map_err(RsmqDecodeError::into_rsmq_error)?;
+
+        Ok(Some((
+            RsmqMessage {
+                id: message.id,
+                message: body,
+                rc: message.rc,
+                fr: message.fr,
+                sent: message.sent,
+                expired: message.expired,
+            },
+            reply_to,
+        )))
+    }
+
+    /// Same as "receive_message" but it receives up to `count` messages at once. Returns fewer messages (or none) if
+    /// there are not enough visible messages
+    pub async fn receive_messages<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        hidden: Option<Duration>,
+        count: usize,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<Vec<RsmqMessage<E>>> {
+        let qname = self.queue_name(qname);
+
+        let result = traced("receive_messages", qname, None, async {
+            self.check_batch_size(count)?;
+
+            let queue = match self.get_receivable_queue(conn, qname).await? {
+                Some(queue) => queue,
+                None => return Ok(Vec::new()),
+            };
+
+            let hidden = get_redis_duration(hidden, &queue.vt);
+            duration_in_range("hidden", hidden)?;
+
+            if count == 0 {
+                return Ok(Vec::new());
+            }
+
+            let result: Vec<ReceiveBatchEntry> = cached_script
+                .invoke_receive_message_batch(
+                    conn,
+                    &[
+                        format!("{}:{}", self.ns, qname),
+                        queue.ts.to_string(),
+                        score_after(queue.ts, hidden)?.to_string(),
+                        format!("{}:", self.ns),
+                    ],
+                    "false".to_string(),
+                    count,
+                )
+                .await?;
+
+            let ids: Vec<&str> = result.iter().map(|(id, ..)| id.as_str()).collect();
+            self.audit(conn, qname, RsmqAuditOperation::Receive, &ids)
+                .await?;
+
+            for (id, _, rc, ..) in &result {
+                self.check_redeliveries(qname, id, *rc);
+            }
+
+            #[cfg(feature = "tracing")]
+            crate::instrument::record_received_batch(result.iter().map(|(_, _, rc, ..)| *rc));
+
+            result
+                .into_iter()
+                .map(|(id, body, rc, fr, expired)| {
+                    build_message(id, body, rc, fr, expired, self.id_length)
+                })
+                .collect()
+        })
+        .await?;
+
+        self.report_received(qname, result.len() as u64);
+
+        Ok(result)
+    }
+
+    /// Receives up to `max_total` messages from several queues, taking one message from each queue in turns. Each
+    /// message comes with the name of its queue.
+    pub async fn receive_multi<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &self,
+        conn: &mut T,
+        qnames: &[&str],
+        max_total: usize,
+        hidden: Option<Duration>,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<Vec<(String, RsmqMessage<E>)>> {
+        self.check_batch_size(max_total)?;
+
+        let hidden = hidden.map(|hidden| get_redis_duration(Some(hidden), &hidden));
+
+        if let Some(hidden) = hidden {
+            duration_in_range("hidden", hidden)?;
+        }
+
+        if qnames.is_empty() || max_total == 0 {
+            return Ok(Vec::new());
+        }
+
+        let qnames: Vec<&str> = qnames.iter().map(|qname| self.queue_name(qname)).collect();
+
+        // A malformed name is a mistake of the caller, not an empty queue
+        for qname in &qnames {
+            valid_name_format(qname)?;
+        }
+
+        let time = self.redis_time(conn).await?;
+
+        let (missing, result): (usize, Vec<ReceiveMultiEntry>) = cached_script
+            .invoke_receive_message_multi(
+                conn,
+                qnames
+                    .iter()
+                    .map(|qname| format!("{}:{}", self.ns, qname))
+                    .collect(),
+                time.0 * 1000 + time.1 / 1000,
+                max_total,
+                hidden,
+                format!("{}:", self.ns),
+                self.missing_queue_as_empty,
+            )
+            .await?;
+
+        if missing > 0 {
+            return Err(RsmqError::MissingQueue(qnames[missing - 1].to_string()));
+        }
+
+        for (index, id, _, rc, ..) in &result {
+            self.audit(conn, qnames[index - 1], RsmqAuditOperation::Receive, &[id])
+                .await?;
+            self.check_redeliveries(qnames[index - 1], id, *rc);
+        }
+
+        let messages = result
+            .into_iter()
+            .map(|(index, id, body, rc, fr, expired)| {
+                Ok((
+                    qnames[index - 1].to_string(),
+                    build_message(id, body, rc, fr, expired, self.id_length)?,
+                ))
+            })
+            .collect::<RsmqResult<Vec<_>>>()?;
+
+        if let Some(metrics) = &self.metrics {
+            for (qname, _) in &messages {
+                metrics.on_receive(qname, true);
+            }
+        }
+
+        Ok(messages)
+    }
+
+    /// Adds `count` to the "totalsent" stat of the queue, for messages sent without this library
+    pub async fn record_external_send(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        count: u64,
+    ) -> RsmqResult<()> {
+        let qname = self.queue_name(qname);
+
+        self.get_queue(conn, qname, false).await?;
+
+        let queue_name = format!("{}:{}:Q", self.ns, qname);
+
+        let time = self.redis_time(conn).await?;
+
+        pipe()
+            .atomic()
+            .cmd("HINCRBY")
+            .arg(&queue_name)
+            .arg("totalsent")
+            .arg(count)
+            .cmd("HSET")
+            .arg(&queue_name)
+            .arg("modified")
+            .arg(time.0)
+            .query_async::<()>(conn)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Renames the queue, keeping its messages and stats
+    pub async fn rename_queue(
+        &self,
+        conn: &mut T,
+        from: &str,
+        to: &str,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<()> {
+        let from = self.queue_name(from);
+        let to = self.queue_name(to);
+
+        valid_name_format(to)?;
+
+        let result = cached_script
+            .invoke_rename_queue::<i8, T>(
+                conn,
+                [
+                    format!("{}:{}", self.ns, from),
+                    format!("{}:{}", self.ns, to),
+                    format!("{}:QUEUES", self.ns),
+                    from.to_string(),
+                    to.to_string(),
+                ],
+            )
+            .await?;
+
+        match result {
+            -1 => Err(RsmqError::QueueNotFound),
+            0 => Err(RsmqError::QueueExists),
+            _ => Ok(()),
+        }
+    }
+
+    /// Moves the message from the dead-letter queue of `qname` back to `qname`, visible right away. Its receive
+    /// count starts again from 0, so it isn't moved back to the dead-letter queue on its next receive. Returns false
+    /// if the message wasn't found
+    pub async fn replay_from_dlq(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        id: &str,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<bool> {
+        let qname = self.queue_name(qname);
+
+        let queue = self.get_queue(conn, qname, false).await?;
+        let dead_letter_queue = self.dead_letter_queue(conn, qname).await?;
+
+        let moved = cached_script
+            .invoke_move_messages::<i64, T>(
+                conn,
+                &[
+                    format!("{}:{}", self.ns, dead_letter_queue),
+                    format!("{}:{}", self.ns, qname),
+                    queue.ts.to_string(),
+                ],
+                true,
+                &[id.to_string()],
+            )
+            .await?;
+
+        match moved {
+            -1 => Err(RsmqError::QueueNotFound),
+            moved => {
+                self.report_deleted(&dead_letter_queue, moved as u64);
+
+                Ok(moved == 1)
+            }
+        }
+    }
+
+    /// Sends the reply of a request received with "receive_message_with_reply_to"
+    pub async fn reply<E: Into<RedisBytes>>(
+        &self,
+        conn: &mut T,
+        reply_to: &str,
+        payload: E,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<String> {
+        let reply_to = self.queue_name(reply_to);
+
+        self.send_message(conn, reply_to, payload, None, cached_script)
+            .await
+    }
+
+    /// Makes a message visible right away, keeping its receive count. Returns false if the message doesn't exist
+    pub async fn requeue_message(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        message_id: &str,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<bool> {
+        let qname = self.queue_name(qname);
+
+        let queue = self.get_queue(conn, qname, false).await?;
+
+        cached_script
+            .invoke_change_message_visibility::<bool, T>(
+                conn,
+                format!("{}:{}", self.ns, qname),
+                message_id.to_string(),
+                queue.ts.to_string(),
+                None,
+            )
+            .await
+    }
+
+    /// Same as "requeue_message" but with the handle returned by "RsmqMessage::receipt_handle". The message is only
+    /// made visible if it wasn't received again since then
+    pub async fn requeue_by_handle(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        handle: &str,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<bool> {
+        let qname = self.queue_name(qname);
+
+        let (id, receive_count) = parse_handle(handle)?;
+
+        let queue = self.get_queue(conn, qname, false).await?;
+
+        cached_script
+            .invoke_change_message_visibility::<bool, T>(
+                conn,
+                format!("{}:{}", self.ns, qname),
+                id.to_string(),
+                queue.ts.to_string(),
+                Some(receive_count),
+            )
+            .await
+    }
+
+    /// Starts recording the maximum depth of the queue again from its current number of messages. Returns the
+    /// maximum depth recorded until now, like "get_max_depth"
+    pub async fn reset_max_depth(&self, conn: &mut T, qname: &str) -> RsmqResult<u64> {
+        let qname = self.queue_name(qname);
+
+        let key = format!("{}:{}", self.ns, qname);
+
+        let ((vt, max_depth), depth): ((Option<String>, Option<u64>), u64) = pipe()
+            .atomic()
+            .cmd("HMGET")
+            .arg(format!("{}:Q", key))
+            .arg("vt")
+            .arg("maxdepth")
+            .cmd("ZCARD")
+            .arg(&key)
+            .cmd("HDEL")
+            .arg(format!("{}:Q", key))
+            .arg("maxdepth")
+            .ignore()
+            .query_async(conn)
+            .await?;
+
+        if vt.is_none() {
+            return Err(RsmqError::QueueNotFound);
+        }
+
+        Ok(max_depth.unwrap_or(0).max(depth))
+    }
+
+    /// Moves the message to the retry queue of `qname`, creating it if needed. It becomes visible there after
+    /// `base * 2^attempt` (capped to the maximum delay). Returns false if the message wasn't found
+    pub async fn retry_message(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        id: &str,
+        attempt: u32,
+        base: Duration,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<bool> {
+        let qname = self.queue_name(qname);
+
+        let queue = self.get_queue(conn, qname, false).await?;
+
+        let retry_qname = retry_queue_name(qname);
+
+        match self
+            .create_queue(conn, &retry_qname, None, None, None)
+            .await
+        {
+            Ok(()) | Err(RsmqError::QueueExists) => {}
+            Err(error) => return Err(error),
+        }
+
+        let delay = 1_u64
+            .checked_shl(attempt)
+            .and_then(|factor| get_redis_duration(Some(base), &DEFAULT_DELAY).checked_mul(factor))
+            .unwrap_or(u64::MAX)
+            .min(JS_COMPAT_MAX_TIME_MILLIS);
+
+        let moved = self
+            .move_messages(
+                conn,
+                qname,
+                &retry_qname,
+                score_after(queue.ts, delay)?,
+                &[id.to_string()],
+                cached_script,
+            )
+            .await?;
+
+        Ok(moved == 1)
+    }
+
+    /// Sends a message to the queue. The message will be delayed some time (controlled by the "delayed" argument or the queue settings) before being delivered to a client.
+    pub async fn send_message<E: Into<RedisBytes>>(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        message: E,
+        delay: Option<Duration>,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<String> {
+        let qname = self.queue_name(qname);
+
+        self.send_message_with_fields(
+            conn,
+            qname,
+            &message.into().0,
+            SendAt::Delay(delay),
+            SendExtras::default(),
+            cached_script,
+        )
+        .await
+    }
+
+    /// Same as "send_message" but it fails with `RsmqError::DuplicatedMessage` if a message sent with the same
+    /// deduplication id is still in the queue.
+    pub async fn send_message_deduplicated<E: Into<RedisBytes>>(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        deduplication_id: &str,
+        message: E,
+        delay: Option<Duration>,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<String> {
+        let qname = self.queue_name(qname);
+
+        if deduplication_id.is_empty() {
+            return Err(RsmqError::MissingParameter("deduplication id".to_string()));
+        }
+
+        self.send_message_with_fields(
+            conn,
+            qname,
+            &message.into().0,
+            SendAt::Delay(delay),
+            SendExtras {
+                deduplication_id: Some(deduplication_id),
+                ..Default::default()
+            },
+            cached_script,
+        )
+        .await
+    }
+
+    /// Same as "send_message" but the message is tracked as part of the group, so "count_group" can tell how many
+    /// messages of the group remain in the queue.
+    pub async fn send_message_grouped<E: Into<RedisBytes>>(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        group_id: &str,
+        message: E,
+        delay: Option<Duration>,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<String> {
+        let qname = self.queue_name(qname);
+
+        if group_id.is_empty() {
+            return Err(RsmqError::MissingParameter("group id".to_string()));
+        }
+
+        self.send_message_with_fields(
+            conn,
+            qname,
+            &message.into().0,
+            SendAt::Delay(delay),
+            SendExtras {
+                fields: &[(GROUP_FIELD, group_id.to_string())],
+                ..Default::default()
+            },
+            cached_script,
+        )
+        .await
+    }
+
+    /// Same as "send_message" but the message is only sent if the queue has less than `max_depth` messages. The check
+    /// and the send are done atomically. Returns None (and sends nothing) if the queue is at or over `max_depth`
+    pub async fn send_message_if_below<E: Into<RedisBytes>>(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        message: E,
+        delay: Option<Duration>,
+        max_depth: u64,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<Option<String>> {
+        let qname = self.queue_name(qname);
+
+        self.send_message_below(
+            conn,
+            qname,
+            &message.into().0,
+            SendAt::Delay(delay),
+            SendExtras::default(),
+            Some(max_depth),
+            cached_script,
+        )
+        .await
+    }
+
+    /// Same as "send_message" but the message is serialized as JSON
+    #[cfg(feature = "serde")]
+    pub async fn send_message_json<M: serde::Serialize>(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        message: &M,
+        delay: Option<Duration>,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<String> {
+        let qname = self.queue_name(qname);
+
+        let message = serde_json::to_vec(message)
+            .map_err(|error| RsmqError::CannotEncodeJson(error.to_string()))?;
+
+        self.send_message_with_fields(
+            conn,
+            qname,
+            &message,
+            SendAt::Delay(delay),
+            SendExtras::default(),
+            cached_script,
+        )
+        .await
+    }
+
+    /// Same as "send_message" but the message is delivered only after every earlier message sent with the same
+    /// ordering key is deleted. Messages with different keys are still delivered in parallel.
+    pub async fn send_message_ordered<E: Into<RedisBytes>>(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        key: &str,
+        message: E,
+        delay: Option<Duration>,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<String> {
+        let qname = self.queue_name(qname);
+
+        if key.is_empty() {
+            return Err(RsmqError::MissingParameter("ordering key".to_string()));
+        }
+
+        self.send_message_with_fields(
+            conn,
+            qname,
+            &message.into().0,
+            SendAt::Delay(delay),
+            SendExtras {
+                fields: &[(ORDERING_KEY_FIELD, key.to_string())],
+                ..Default::default()
+            },
+            cached_script,
+        )
+        .await
+    }
+
+    /// Same as "send_message" but it borrows the message, so the caller can keep it without cloning it
+    pub async fn send_message_ref(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        message: &[u8],
+        delay: Option<Duration>,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<String> {
+        let qname = self.queue_name(qname);
+
+        self.send_message_with_fields(
+            conn,
+            qname,
+            message,
+            SendAt::Delay(delay),
+            SendExtras::default(),
+            cached_script,
+        )
+        .await
+    }
+
+    /// Same as "send_message" but it also injects the current OpenTelemetry context into the message so the consumer
+    /// can link its spans to the producer ones.
+    #[cfg(feature = "otel")]
+    pub async fn send_message_traced<E: Into<RedisBytes>>(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        message: E,
+        delay: Option<Duration>,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<String> {
+        let qname = self.queue_name(qname);
+
+        let mut carrier = HashMap::new();
+
+        opentelemetry::global::get_text_map_propagator(|propagator| {
+            propagator.inject_context(&opentelemetry::Context::current(), &mut carrier)
+        });
+
+        let fields: Vec<(&str, String)> = carrier
+            .remove(TRACEPARENT_HEADER)
+            .map(|traceparent| (TRACEPARENT_FIELD, traceparent))
+            .into_iter()
+            .collect();
+
+        self.send_message_with_fields(
+            conn,
+            qname,
+            &message.into().0,
+            SendAt::Delay(delay),
+            SendExtras {
+                fields: &fields,
+                ..Default::default()
+            },
+            cached_script,
+        )
+        .await
+    }
+
+    /// Same as "send_message" but it stores the `attributes` (name and value pairs) with the message, so the
+    /// consumers can select it with "receive_message_where"
+    pub async fn send_message_with_attributes<E: Into<RedisBytes>>(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        message: E,
+        delay: Option<Duration>,
+        attributes: &[(&str, &str)],
+        cached_script: &CachedScript,
+    ) -> RsmqResult<String> {
+        let qname = self.queue_name(qname);
+
+        self.send_message_with_fields(
+            conn,
+            qname,
+            &message.into().0,
+            SendAt::Delay(delay),
+            SendExtras {
+                fields: &[(ATTRIBUTES_FIELD, encode_attributes(attributes))],
+                ..Default::default()
+            },
+            cached_script,
+        )
+        .await
+    }
+
+    /// Same as "send_message" but the message gets a deadline `lifetime` after it is sent. Messages received after
+    /// their deadline are delivered with `expired` set to true.
+    pub async fn send_message_with_deadline<E: Into<RedisBytes>>(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        message: E,
+        delay: Option<Duration>,
+        lifetime: Duration,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<String> {
+        let qname = self.queue_name(qname);
+
+        self.send_message_with_fields(
+            conn,
+            qname,
+            &message.into().0,
+            SendAt::Delay(delay),
+            SendExtras {
+                lifetime: Some(get_redis_duration(Some(lifetime), &Duration::ZERO)),
+                ..Default::default()
+            },
+            cached_script,
+        )
+        .await
+    }
+
+    /// Same as "send_message" but it stores the queue where the consumer has to send the reply
+    pub async fn send_message_with_reply_to<E: Into<RedisBytes>>(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        message: E,
+        delay: Option<Duration>,
+        reply_to: &str,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<String> {
+        let qname = self.queue_name(qname);
+        let reply_to = self.queue_name(reply_to);
+
+        valid_name_format(reply_to)?;
+
+        self.send_message_with_fields(
+            conn,
+            qname,
+            &message.into().0,
+            SendAt::Delay(delay),
+            SendExtras {
+                fields: &[(REPLY_TO_FIELD, reply_to.to_string())],
+                ..Default::default()
+            },
+            cached_script,
+        )
+        .await
+    }
+
+    /// Sends all the messages in a single transaction. Returns their ids in the same order
+    pub async fn send_messages<E: Into<RedisBytes>>(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        messages: Vec<(E, Option<Duration>)>,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<Vec<String>> {
+        self.send_messages_with_mode(
+            conn,
+            qname,
+            messages,
+            RsmqBatchMode::AllOrNothing,
+            cached_script,
+        )
+        .await?
+        .into_iter()
+        .collect()
+    }
+
+    /// Same as "send_messages" but, with `RsmqBatchMode::BestEffort`, the invalid messages are skipped and reported
+    /// in their position of the result instead of failing the whole batch
+    pub async fn send_messages_with_mode<E: Into<RedisBytes>>(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        messages: Vec<(E, Option<Duration>)>,
+        mode: RsmqBatchMode,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<Vec<RsmqResult<String>>> {
+        let qname = self.queue_name(qname);
+
+        let result = traced("send_messages", qname, None, async {
+            valid_name_format(qname)?;
+            self.check_batch_size(messages.len())?;
+
+            if messages.is_empty() {
+                return Ok(Vec::new());
+            }
+
+            let queue = if self.unchecked_send {
+                self.get_unchecked_queue(conn).await?
+            } else {
+                self.get_queue(conn, qname, true).await?
+            };
+
+            let queue_uid = match queue.uid {
+                Some(uid) => uid,
+                None => return Err(RsmqError::QueueNotFound),
+            };
+
+            // All the ids share the send time at the beginning of the first one
+            let id_prefix = &queue_uid[..queue_uid.len() - self.id_length];
+
+            let key = format!("{}:{}", self.ns, qname);
+            let queue_key = format!("{}:Q", key);
+
+            let mut results = Vec::with_capacity(messages.len());
+            let mut ids = Vec::with_capacity(messages.len());
+            let mut piping = pipe();
+            let mut commands = piping.atomic();
+
+            // Everything is validated before sending anything, so in AllOrNothing mode a single invalid message fails the
+            // whole batch
+            for (message, delay) in messages {
+                let delay = get_redis_duration(delay, &queue.delay);
+
+                let validated = duration_in_range("delay", delay)
+                    .and_then(|()| score_after(queue.ts, delay))
+                    .and_then(|score| {
+                        let message = message.into().0;
+
+                        // The maxsize of the queue applies to the stored (compressed) message
+                        #[cfg(feature = "compression")]
+                        let message = match self.compress_threshold {
+                            Some(threshold) => {
+                                crate::compression::compress(&message, threshold).into_owned()
+                            }
+                            None => message,
+                        };
+
+                        if !queue.maxsize.allows(message.len()) {
+                            return Err(RsmqError::MessageTooLong);
+                        }
+
+                        Ok((score, message))
+                    });
+
+                let (score, message) = match (validated, mode) {
+                    (Ok(validated), _) => validated,
+                    (Err(error), RsmqBatchMode::AllOrNothing) => return Err(error),
+                    (Err(error), RsmqBatchMode::BestEffort) => {
+                        results.push(Err(error));
+                        continue;
+                    }
+                };
+
+                let id = if ids.is_empty() {
+                    queue_uid.clone()
+                } else {
+                    format!("{}{}", id_prefix, self.make_id()?)
+                };
+
+                commands = commands
+                    .cmd("ZADD")
+                    .arg(&key)
+                    .arg(score)
+                    .arg(&id)
+                    .ignore()
+                    .cmd("HSET")
+                    .arg(&queue_key)
+                    .arg(&id)
+                    .arg(message)
+                    .ignore();
+
+                results.push(Ok(id.clone()));
+                ids.push(id);
+            }
+
+            if ids.is_empty() {
+                return Ok(results);
+            }
+
+            commands = commands
+                .cmd("HINCRBY")
+                .arg(&queue_key)
+                .arg("totalsent")
+                .arg(ids.len())
+                .ignore();
+
+            if self.realtime {
+                commands = commands.cmd("ZCARD").arg(&key);
+            }
+
+            let result: Vec<i64> = commands.query_async(conn).await?;
+
+            // Not part of the transaction, as resending it after a missing script would send the messages twice
+            if self.track_max_depth {
+                cached_script
+                    .invoke_update_max_depth::<(), T>(conn, key)
+                    .await?;
+            }
+
+            if self.realtime {
+                let count = result.last().copied().unwrap_or_default();
+                let sent: Vec<&str> = ids.iter().map(String::as_str).collect();
+
+                self.publish_new_messages(conn, qname, count, &sent).await?;
+            }
+
+            let sent: Vec<&str> = ids.iter().map(String::as_str).collect();
+
+            self.audit(conn, qname, RsmqAuditOperation::Send, &sent)
+                .await?;
+
+            Ok(results)
+        })
+        .await?;
+
+        if let Some(metrics) = &self.metrics {
+            for _ in result.iter().filter(|sent| sent.is_ok()) {
+                metrics.on_send(qname);
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Same as "send_message" but the message becomes visible at `score` (milliseconds since epoch), used as it is
+    pub async fn send_message_with_score<E: Into<RedisBytes>>(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        message: E,
+        score: u64,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<String> {
+        let qname = self.queue_name(qname);
+
+        self.send_message_with_fields(
+            conn,
+            qname,
+            &message.into().0,
+            SendAt::Score(score),
+            SendExtras::default(),
+            cached_script,
+        )
+        .await
+    }
+
+    /// Sends the message and stores, in the same script, the extra per message fields as `{id}:{suffix}` in the
+    /// queue hash. Those fields need to be removed alongside the message when it gets deleted. The ordering key and
+    /// group fields are also indexed by their value. The lifetime of the extras is stored as a deadline timestamp,
+    /// relative to the send time.
+    async fn send_message_with_fields(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        message: &[u8],
+        send_at: SendAt,
+        extras: SendExtras<'_>,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<String> {
+        let sent = self
+            .send_message_below(conn, qname, message, send_at, extras, None, cached_script)
+            .await?;
+
+        sent.ok_or_else(|| RsmqError::InconsistentState("message not sent".to_string()))
+    }
+
+    /// Same as "send_message_with_fields" but, if `max_depth` is given, the message is only sent if the queue has
+    /// less than `max_depth` messages. Returns None (and sends nothing) if the queue is full.
+    #[allow(clippy::too_many_arguments)]
+    async fn send_message_below(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        message: &[u8],
+        send_at: SendAt,
+        extras: SendExtras<'_>,
+        max_depth: Option<u64>,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<Option<String>> {
+        let result = traced("send_message", qname, None, async {
+            valid_name_format(qname)?;
+
+            let queue = if self.unchecked_send {
+                self.get_unchecked_queue(conn).await?
+            } else {
+                self.get_queue(conn, qname, true).await?
+            };
+
+            let score = match send_at {
+                SendAt::Delay(delay) => {
+                    let delay = get_redis_duration(delay, &queue.delay);
+                    duration_in_range("delay", delay)?;
+                    score_after(queue.ts, delay)?
+                }
+                SendAt::Score(score) => score,
+            };
+            let key = format!("{}:{}", self.ns, qname);
+
+            // The maxsize of the queue applies to the stored (compressed) message
+            #[cfg(feature = "compression")]
+            let compressed = match self.compress_threshold {
+                Some(threshold) => crate::compression::compress(message, threshold),
+                None => Cow::Borrowed(message),
+            };
+            #[cfg(feature = "compression")]
+            let message: &[u8] = &compressed;
+
+            if !queue.maxsize.allows(message.len()) {
+                return Err(RsmqError::MessageTooLong);
+            }
+
+            let queue_uid = match queue.uid {
+                Some(uid) => uid,
+                None => return Err(RsmqError::QueueNotFound),
+            };
+
+            let mut fields: Vec<(&str, String)> = extras.fields.to_vec();
+
+            if let Some(lifetime) = extras.lifetime {
+                duration_in_range("lifetime", lifetime)?;
+                fields.push((
+                    EXPIRES_AT_FIELD,
+                    score_after(queue.ts, lifetime)?.to_string(),
+                ));
+            }
+
+            if let Some(deduplication_id) = extras.deduplication_id {
+                fields.push((DEDUPLICATION_FIELD, deduplication_id.to_string()));
+            }
+
+            // The ordering key and the group are also passed apart, so the script indexes them
+            let index = |field: &str| {
+                extras
+                    .fields
+                    .iter()
+                    .find(|(suffix, _)| *suffix == field)
+                    .map_or("", |(_, value)| value.as_str())
+            };
+
+            #[cfg(feature = "tracing")]
+            crate::instrument::script_invoked("sendMessage");
+
+            let mut piping = pipe();
+
+            let mut commands = piping
+                .atomic()
+                .cmd("EVALSHA")
+                .arg(&cached_script.send_message_sha1)
+                .arg(1)
+                .arg(&key)
+                .arg(&queue_uid)
+                .arg(score)
+                .arg(message)
+                .arg(extras.deduplication_id.unwrap_or_default())
+                .arg(index(ORDERING_KEY_FIELD))
+                .arg(index(GROUP_FIELD))
+                .arg(max_depth.map(|depth| depth.to_string()).unwrap_or_default());
+
+            for (suffix, value) in &fields {
+                commands = commands.arg(suffix).arg(value);
+            }
+
+            if self.track_max_depth {
+                #[cfg(feature = "tracing")]
+                crate::instrument::script_invoked("updateMaxDepth");
+
+                commands = commands
+                    .cmd("EVALSHA")
+                    .arg(&cached_script.update_max_depth_sha1)
+                    .arg(1)
+                    .arg(&key)
+                    .ignore();
+            }
+
+            let (count,): (i64,) = query_script_pipeline(conn, commands).await?;
+
+            match count {
+                -2 => return Ok(None),
+                count if count < 0 => return Err(RsmqError::DuplicatedMessage),
+                _ => {}
+            }
+
+            if self.realtime {
+                self.publish_new_messages(conn, qname, count, &[&queue_uid])
+                    .await?;
+            }
+
+            self.audit(conn, qname, RsmqAuditOperation::Send, &[&queue_uid])
+                .await?;
+
+            #[cfg(feature = "tracing")]
+            crate::instrument::record_message_id(&queue_uid);
+
+            Ok(Some(queue_uid))
+        })
+        .await?;
+
+        if let (Some(metrics), Some(_)) = (&self.metrics, &result) {
+            metrics.on_send(qname);
+        }
+
+        Ok(result)
+    }
+
+    /// Swaps the delivery order of two messages of the same queue. Returns false (and changes nothing) if any of the
+    /// messages doesn't exist or is currently hidden because a client received it.
+    pub async fn swap_message_order(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        id_a: &str,
+        id_b: &str,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<bool> {
+        let qname = self.queue_name(qname);
+
+        let queue = self.get_queue(conn, qname, false).await?;
+
+        cached_script
+            .invoke_swap_message_order::<bool, T>(
+                conn,
+                format!("{}:{}", self.ns, qname),
+                id_a.to_string(),
+                id_b.to_string(),
+                queue.ts.to_string(),
+            )
+            .await
+    }
+
+    /// Moves to `dlq` the visible messages (so, not being processed) sent more than `older_than` ago. Returns how
+    /// many messages were moved
+    pub async fn sweep_stale_to_dlq(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        older_than: Duration,
+        dlq: &str,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<u64> {
+        let qname = self.queue_name(qname);
+        let dlq = self.queue_name(dlq);
+
+        let older_than = get_redis_duration(Some(older_than), &Duration::ZERO);
+        let time = queue_time(self.redis_time(conn).await?);
+        let sent_before = time.saturating_sub(older_than.saturating_mul(TIME_MULTIPLIER));
+
+        self.move_stale_messages(
+            conn,
+            qname,
+            dlq,
+            time / TIME_MULTIPLIER,
+            sent_before,
+            cached_script,
+        )
+        .await
+    }
+
+    /// Sets a field of the queue hash only if it isn't set yet (HSETNX). Returns whether the value was applied. The
+    /// settings used by the library are validated like in "set_queue_attributes" ("vt" and "delay" in milliseconds)
+    /// and the fields it maintains by itself (like "totalsent") can't be set
+    pub async fn set_queue_attribute_if_absent(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        field: &str,
+        value: &str,
+    ) -> RsmqResult<bool> {
+        let qname = self.queue_name(qname);
+
+        self.check_queue_attribute(field, value)?;
+
+        // Otherwise HSETNX would create a queue hash with only this field
+        self.get_queue(conn, qname, false).await?;
+
+        let applied: bool = redis::cmd("HSETNX")
+            .arg(format!("{}:{}:Q", self.ns, qname))
+            .arg(field)
+            .arg(value)
+            .query_async(conn)
+            .await?;
+
+        Ok(applied)
     }
 
     /// Modify the queue attributes. Keep in mind that "hidden" and "delay" can be overwritten when the message is sent. "hidden" can be changed by the method "change_message_visibility"
@@ -476,20 +3702,22 @@ impl<T: ConnectionLike> RsmqFunctions<T> {
     ///
     /// delay: Time the messages will be delayed before being delivered
     ///
-    /// maxsize: Maximum size in bytes of each message in the queue. Needs to be between 1024 or 65536 or -1 (unlimited size)
+    /// maxsize: Maximum size of each message in the queue. `MaxSize::Bytes` needs to be between 1024 and `RsmqOptions::maxsize_ceiling` (65536 by default)
     pub async fn set_queue_attributes(
         &self,
         conn: &mut T,
         qname: &str,
         hidden: Option<Duration>,
         delay: Option<Duration>,
-        maxsize: Option<i64>,
+        maxsize: Option<MaxSize>,
     ) -> RsmqResult<RsmqQueueAttributes> {
+        let qname = self.queue_name(qname);
+
         self.get_queue(conn, qname, false).await?;
 
         let queue_name = format!("{}:{}:Q", self.ns, qname);
 
-        let time: (u64, u64) = redis::cmd("TIME").query_async(conn).await?;
+        let time = self.redis_time(conn).await?;
 
         let mut commands = &mut pipe();
 
@@ -502,7 +3730,7 @@ impl<T: ConnectionLike> RsmqFunctions<T> {
 
         if hidden.is_some() {
             let duration = get_redis_duration(hidden, &Duration::from_secs(30));
-            number_in_range(duration, 0, JS_COMPAT_MAX_TIME_MILLIS)?;
+            duration_in_range("hidden", duration)?;
             commands = commands
                 .cmd("HSET")
                 .arg(&queue_name)
@@ -512,7 +3740,7 @@ impl<T: ConnectionLike> RsmqFunctions<T> {
 
         if delay.is_some() {
             let delay = get_redis_duration(delay, &Duration::ZERO);
-            number_in_range(delay, 0, JS_COMPAT_MAX_TIME_MILLIS)?;
+            duration_in_range("delay", delay)?;
             commands = commands
                 .cmd("HSET")
                 .arg(&queue_name)
@@ -521,17 +3749,12 @@ impl<T: ConnectionLike> RsmqFunctions<T> {
         }
 
         if let Some(maxsize) = maxsize {
-            if let Err(error) = number_in_range(maxsize, 1024, 65536) {
-                if maxsize != -1 {
-                    // TODO: Create another error in order to explain that -1 is allowed
-                    return Err(error);
-                }
-            }
+            self.check_maxsize(maxsize)?;
             commands = commands
                 .cmd("HSET")
                 .arg(&queue_name)
                 .arg("maxsize")
-                .arg(maxsize);
+                .arg(i64::from(maxsize));
         }
 
         commands.query_async::<()>(conn).await?;
@@ -539,6 +3762,346 @@ impl<T: ConnectionLike> RsmqFunctions<T> {
         self.get_queue_attributes(conn, qname).await
     }
 
+    /// Rejects the values of the queue hash that would break the queue when it is read
+    fn check_queue_attribute(&self, field: &str, value: &str) -> RsmqResult<()> {
+        let invalid = || RsmqError::InvalidFormat(value.to_string());
+
+        match field {
+            "created" | "modified" | "totalrecv" | "totalsent" => {
+                Err(RsmqError::InvalidFormat(field.to_string()))
+            }
+            "vt" => duration_in_range("hidden", value.parse().map_err(|_| invalid())?),
+            "delay" => duration_in_range("delay", value.parse().map_err(|_| invalid())?),
+            "maxsize" => {
+                let maxsize: i64 = value.parse().map_err(|_| invalid())?;
+
+                if maxsize == -1 {
+                    return Ok(());
+                }
+
+                number_in_range("maxsize", maxsize, 1024, i64::from(self.maxsize_ceiling))
+            }
+            "maxrc" => number_in_range(
+                "max_receives",
+                value.parse().map_err(|_| invalid())?,
+                1,
+                u64::MAX,
+            ),
+            "dlq" => valid_name_format(value),
+            _ => Ok(()),
+        }
+    }
+
+    /// Moves the messages to the queue `to`, where they become visible at `visible_at`. Returns how many messages
+    /// were moved (the ones not in `from` are ignored)
+    async fn move_messages(
+        &self,
+        conn: &mut T,
+        from: &str,
+        to: &str,
+        visible_at: u64,
+        ids: &[String],
+        cached_script: &CachedScript,
+    ) -> RsmqResult<u64> {
+        let result = cached_script
+            .invoke_move_messages::<i64, T>(
+                conn,
+                &[
+                    format!("{}:{}", self.ns, from),
+                    format!("{}:{}", self.ns, to),
+                    visible_at.to_string(),
+                ],
+                false,
+                ids,
+            )
+            .await?;
+
+        match result {
+            -1 => Err(RsmqError::QueueNotFound),
+            moved => {
+                self.report_deleted(from, moved as u64);
+
+                Ok(moved as u64)
+            }
+        }
+    }
+
+    /// Same as "move_messages" but, instead of a list of ids, it moves the messages visible at `now` that were sent
+    /// before `sent_before` (in the unit of the ids timestamp, see `queue_time`). They become visible at `now`
+    async fn move_stale_messages(
+        &self,
+        conn: &mut T,
+        from: &str,
+        to: &str,
+        now: u64,
+        sent_before: u64,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<u64> {
+        let result = cached_script
+            .invoke_move_messages::<i64, T>(
+                conn,
+                &[
+                    format!("{}:{}", self.ns, from),
+                    format!("{}:{}", self.ns, to),
+                    now.to_string(),
+                    sent_before.to_string(),
+                    self.id_length.to_string(),
+                ],
+                false,
+                &[],
+            )
+            .await?;
+
+        match result {
+            -1 => Err(RsmqError::QueueNotFound),
+            moved => {
+                self.report_deleted(from, moved as u64);
+
+                Ok(moved as u64)
+            }
+        }
+    }
+
+    /// Dead-letter queue of `qname`: the one set with `RsmqQueueConfig::dead_letter_queue`, or `{qname}-dlq` if the
+    /// queue doesn't have one
+    async fn dead_letter_queue(&self, conn: &mut T, qname: &str) -> RsmqResult<String> {
+        let dead_letter_queue: Option<String> = redis::cmd("HGET")
+            .arg(format!("{}:{}:Q", self.ns, qname))
+            .arg("dlq")
+            .query_async(conn)
+            .await?;
+
+        Ok(dead_letter_queue.unwrap_or_else(|| dlq_name(qname)))
+    }
+
+    /// Descriptor used by "unchecked_send" instead of reading the queue. The queue delay and maxsize are unknown, so
+    /// there is no delay by default and no size limit
+    async fn get_unchecked_queue(&self, conn: &mut T) -> RsmqResult<QueueDescriptor> {
+        let time = queue_time(self.redis_time(conn).await?);
+
+        Ok(QueueDescriptor {
+            vt: DEFAULT_HIDDEN,
+            delay: DEFAULT_DELAY,
+            maxsize: MaxSize::Unlimited,
+            ts: time / TIME_MULTIPLIER,
+            uid: Some(radix_36(time).to_string() + &self.make_id()?),
+        })
+    }
+
+    /// Adds to the pipeline the commands that delete every key of the queue (first the queue hash, then its
+    /// removal from the queue set). The ordering keys and the groups are read first, so they are deleted too
+    async fn add_delete_queue_commands<'a>(
+        &self,
+        conn: &mut T,
+        pipeline: &'a mut redis::Pipeline,
+        qname: &str,
+    ) -> RsmqResult<&'a mut redis::Pipeline> {
+        let key = format!("{}:{}", self.ns, qname);
+
+        let (ordering_keys, groups): (Vec<String>, Vec<String>) = pipe()
+            .cmd("SMEMBERS")
+            .arg(format!("{}:ordering", &key))
+            .cmd("SMEMBERS")
+            .arg(format!("{}:groups", &key))
+            .query_async(conn)
+            .await?;
+
+        Ok(pipeline
+            .cmd("DEL")
+            .arg(format!("{}:Q", &key))
+            .arg(format!("{}:ordering", &key))
+            .arg(
+                ordering_keys
+                    .iter()
+                    .map(|ordering_key| format!("{}:ordering:{}", &key, ordering_key))
+                    .collect::<Vec<_>>(),
+            )
+            .arg(format!("{}:groups", &key))
+            .arg(format!("{}:dedup", &key))
+            .arg(
+                groups
+                    .iter()
+                    .map(|group| format!("{}:grp:{}", &key, group))
+                    .collect::<Vec<_>>(),
+            )
+            .arg(&key)
+            .cmd("SREM")
+            .arg(format!("{}:QUEUES", self.ns))
+            .arg(qname))
+    }
+
+    /// Receives the first message that has the attribute `filter.0` set to `filter.1` (any message without filter),
+    /// along with the total received count of the queue. With `with_attributes` the queue attributes are read in the
+    /// same transaction. Nothing is returned (not even the attributes) if the queue is missing and
+    /// `missing_queue_as_empty` is set
+    async fn receive_message_matching<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        hidden: Option<Duration>,
+        filter: Option<(&str, &str)>,
+        with_attributes: bool,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<ReceivedMatching<E>> {
+        let result = traced("receive_message", qname, None, async {
+            let queue = match self.get_receivable_queue(conn, qname).await? {
+                Some(queue) => queue,
+                None => return Ok((None, None)),
+            };
+
+            let hidden = get_redis_duration(hidden, &queue.vt);
+            duration_in_range("hidden", hidden)?;
+
+            let key = format!("{}:{}", self.ns, qname);
+
+            let mut keys = vec![
+                key.clone(),
+                queue.ts.to_string(),
+                score_after(queue.ts, hidden)?.to_string(),
+                format!("{}:", self.ns),
+            ];
+
+            if let Some((attribute, value)) = filter {
+                keys.push(attribute.to_string());
+                keys.push(value.to_string());
+            }
+
+            let (result, attributes): (ReceiveMessageResult, _) = if with_attributes {
+                #[cfg(feature = "tracing")]
+                crate::instrument::script_invoked("receiveMessage");
+
+                let mut pipeline = pipe();
+
+                pipeline
+                    .atomic()
+                    .cmd("EVALSHA")
+                    .arg(&cached_script.receive_message_sha1)
+                    .arg(keys.len())
+                    .arg(&keys)
+                    .arg("false")
+                    .arg(self.body_cache.is_some().to_string());
+
+                let (received, fields, msgs, hiddenmsgs, dead_letter) = query_script_pipeline(
+                    conn,
+                    add_queue_attributes_commands(&mut pipeline, &key, queue.ts),
+                )
+                .await?;
+
+                (
+                    received,
+                    Some(build_queue_attributes(
+                        fields,
+                        msgs,
+                        hiddenmsgs,
+                        dead_letter,
+                    )?),
+                )
+            } else {
+                let received = cached_script
+                    .invoke_receive_message(
+                        conn,
+                        &keys,
+                        "false".to_string(),
+                        self.body_cache.is_some(),
+                    )
+                    .await?;
+
+                (received, None)
+            };
+
+            #[cfg(feature = "tracing")]
+            crate::instrument::record_received(result.0.then_some((result.1.as_str(), result.3)));
+
+            if !result.0 {
+                return Ok((None, attributes));
+            }
+
+            self.audit(conn, qname, RsmqAuditOperation::Receive, &[&result.1])
+                .await?;
+
+            self.check_redeliveries(qname, &result.1, result.3);
+
+            let body = match self.received_body(conn, qname, &result.1, result.2).await? {
+                Some(body) => body,
+                None => return Ok((None, attributes)),
+            };
+
+            Ok((
+                Some((
+                    build_message(result.1, body, result.3, result.4, result.5, self.id_length)?,
+                    result.6,
+                )),
+                attributes,
+            ))
+        })
+        .await?;
+
+        if let Some(metrics) = &self.metrics {
+            metrics.on_receive(qname, result.0.is_some());
+        }
+
+        Ok(result)
+    }
+
+    /// Deletes every message of the queue, keeping the queue. Returns how many messages were deleted
+    async fn purge_messages(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        cached_script: &CachedScript,
+    ) -> RsmqResult<u64> {
+        let result = cached_script
+            .invoke_purge_queue::<i64, T>(conn, format!("{}:{}", self.ns, qname))
+            .await?;
+
+        match result {
+            -1 => Err(RsmqError::QueueNotFound),
+            purged => {
+                self.report_deleted(qname, purged as u64);
+
+                Ok(purged as u64)
+            }
+        }
+    }
+
+    /// Reports to the metrics a receive that got `count` messages, or nothing if `count` is 0
+    fn report_received(&self, qname: &str, count: u64) {
+        if let Some(metrics) = &self.metrics {
+            if count == 0 {
+                metrics.on_receive(qname, false);
+            }
+
+            for _ in 0..count {
+                metrics.on_receive(qname, true);
+            }
+        }
+    }
+
+    /// Reports to the metrics `count` messages that left the queue, deleted or moved to another queue
+    fn report_deleted(&self, qname: &str, count: u64) {
+        if let Some(metrics) = &self.metrics {
+            for _ in 0..count {
+                metrics.on_delete(qname, true);
+            }
+        }
+    }
+
+    /// Same as "get_queue" but a missing queue is returned as `None` when `missing_queue_as_empty` is enabled
+    async fn get_receivable_queue(
+        &self,
+        conn: &mut T,
+        qname: &str,
+    ) -> RsmqResult<Option<QueueDescriptor>> {
+        // A malformed name is a mistake of the caller, not an empty queue
+        valid_name_format(qname)?;
+
+        match self.get_queue(conn, qname, false).await {
+            Ok(queue) => Ok(Some(queue)),
+            Err(RsmqError::QueueNotFound) if self.missing_queue_as_empty => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
     async fn get_queue(&self, conn: &mut T, qname: &str, uid: bool) -> RsmqResult<QueueDescriptor> {
         let result: (Vec<Option<String>>, (u64, u64)) = pipe()
             .atomic()
@@ -551,10 +4114,7 @@ impl<T: ConnectionLike> RsmqFunctions<T> {
             .query_async(conn)
             .await?;
 
-        #[cfg(feature = "break-js-comp")]
-        let time = (result.1).0 * 1000000 + (result.1).1;
-        #[cfg(not(feature = "break-js-comp"))]
-        let time = (result.1).0 * 1000;
+        let time = queue_time(self.mocked_time().unwrap_or(result.1));
 
         let (hmget_first, hmget_second, hmget_third) =
             match (result.0.first(), result.0.get(1), result.0.get(2)) {
@@ -563,7 +4123,7 @@ impl<T: ConnectionLike> RsmqFunctions<T> {
             };
 
         let quid = if uid {
-            Some(radix_36(time).to_string() + &RsmqFunctions::<T>::make_id(22)?)
+            Some(radix_36(time).to_string() + &self.make_id()?)
         } else {
             None
         };
@@ -576,24 +4136,203 @@ impl<T: ConnectionLike> RsmqFunctions<T> {
                     .map_err(|_| RsmqError::CannotParseDelay)?,
             ),
             maxsize: hmget_third
-                .parse()
-                .map_err(|_| RsmqError::CannotParseMaxsize)?,
+                .parse::<i64>()
+                .ok()
+                .and_then(|maxsize| MaxSize::try_from(maxsize).ok())
+                .ok_or(RsmqError::CannotParseMaxsize)?,
             ts: time / TIME_MULTIPLIER,
             uid: quid,
         })
     }
 
-    fn make_id(len: usize) -> RsmqResult<String> {
-        let possible = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+    /// Current time as returned by the Redis `TIME` command: (seconds, microseconds)
+    async fn redis_time(&self, conn: &mut T) -> RsmqResult<(u64, u64)> {
+        match self.mocked_time() {
+            Some(time) => Ok(time),
+            None => redis::cmd("TIME")
+                .query_async(conn)
+                .await
+                .map_err(Into::into),
+        }
+    }
+
+    fn mocked_time(&self) -> Option<(u64, u64)> {
+        self.time_source.as_ref().map(|source| {
+            let now = source.now();
+            (now.as_secs(), now.subsec_micros() as u64)
+        })
+    }
+
+    /// Appends a record per message to the audit log of the queue, if it is enabled
+    async fn audit(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        operation: RsmqAuditOperation,
+        ids: &[&str],
+    ) -> RsmqResult<()> {
+        let max_len = match self.audit_log_max_len {
+            Some(max_len) if !ids.is_empty() => max_len,
+            _ => return Ok(()),
+        };
+
+        let key = format!("{}:{}:audit", self.ns, qname);
+
+        let mut pipeline = pipe();
+
+        for id in ids {
+            pipeline
+                .cmd("XADD")
+                .arg(&key)
+                .arg("MAXLEN")
+                .arg("~")
+                .arg(max_len)
+                .arg("*")
+                .arg("op")
+                .arg(operation.as_str())
+                .arg("id")
+                .arg(*id)
+                .ignore();
+        }
+
+        pipeline.query_async::<()>(conn).await?;
+
+        Ok(())
+    }
+
+    /// Checks a maxsize given to "create_queue" or "set_queue_attributes" against the range accepted by the queues
+    fn check_maxsize(&self, maxsize: MaxSize) -> RsmqResult<()> {
+        match maxsize {
+            MaxSize::Unlimited => Ok(()),
+            MaxSize::Bytes(bytes)
+                if (1024..=i64::from(self.maxsize_ceiling)).contains(&i64::from(bytes)) =>
+            {
+                Ok(())
+            }
+            MaxSize::Bytes(value) => Err(RsmqError::InvalidMaxSize {
+                value,
+                max: self.maxsize_ceiling,
+            }),
+        }
+    }
+
+    fn check_batch_size(&self, requested: usize) -> RsmqResult<()> {
+        if requested > self.max_batch_size {
+            return Err(RsmqError::BatchTooLarge {
+                requested,
+                max: self.max_batch_size,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Name of the queue as used in Redis. Without the surrounding whitespace if `trim_queue_names` is enabled
+    fn queue_name<'a>(&self, qname: &'a str) -> &'a str {
+        if self.trim_queue_names {
+            qname.trim()
+        } else {
+            qname
+        }
+    }
+
+    /// Publishes the sent messages on the realtime channel of the queue, as the number of messages of the queue (one
+    /// publish) or as their ids (one publish per message), depending on `realtime_payload`
+    async fn publish_new_messages(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        count: i64,
+        ids: &[&str],
+    ) -> RsmqResult<()> {
+        let channel = self.realtime_channel(qname);
+
+        match self.realtime_payload {
+            RsmqRealtimePayload::Count => {
+                redis::cmd("PUBLISH")
+                    .arg(channel)
+                    .arg(count)
+                    .query_async::<()>(conn)
+                    .await?;
+            }
+            RsmqRealtimePayload::MessageId => {
+                let mut pipeline = pipe();
+
+                for id in ids {
+                    pipeline.cmd("PUBLISH").arg(&channel).arg(*id).ignore();
+                }
+
+                pipeline.query_async::<()>(conn).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Body of a received message. With a body cache Redis doesn't send it for redeliveries, so it is taken from the
+    /// cache, or read and added to the cache if it isn't there. Returns None if the message was deleted before its
+    /// body could be read
+    async fn received_body(
+        &self,
+        conn: &mut T,
+        qname: &str,
+        id: &str,
+        body: Option<Vec<u8>>,
+    ) -> RsmqResult<Option<Vec<u8>>> {
+        let cache = match &self.body_cache {
+            Some(cache) => cache,
+            None => return Ok(Some(body.unwrap_or_default())),
+        };
+
+        if let Some(body) = body {
+            cache.insert(id, &body);
+            return Ok(Some(body));
+        }
+
+        if let Some(body) = cache.get(id) {
+            return Ok(Some(body));
+        }
+
+        let body: Option<Vec<u8>> = redis::cmd("HGET")
+            .arg(format!("{}:{}:Q", self.ns, qname))
+            .arg(id)
+            .query_async(conn)
+            .await?;
+
+        if let Some(body) = &body {
+            cache.insert(id, body);
+        }
+
+        Ok(body)
+    }
+
+    /// Reports the received message to the observer if it was received more times than `max_global_redeliveries`
+    fn check_redeliveries(&self, qname: &str, id: &str, rc: u64) {
+        if let (Some(max), Some(observer)) = (self.max_global_redeliveries, &self.observer) {
+            if rc > max {
+                observer.redelivery_limit_exceeded(qname, id, rc);
+            }
+        }
+    }
+
+    /// Removes a deleted message from the body cache
+    fn forget_body(&self, id: &str) {
+        if let Some(cache) = &self.body_cache {
+            cache.remove(id);
+        }
+    }
 
+    /// Random part of the message ids, `id_length` characters of `id_alphabet`. The id starts with the send time in
+    /// base 36, which is not affected by those options
+    fn make_id(&self) -> RsmqResult<String> {
         let mut rng = rand::thread_rng();
 
-        let mut id = String::with_capacity(len);
+        let mut id = String::with_capacity(self.id_length);
 
-        for _ in 0..len {
+        for _ in 0..self.id_length {
             id.push(
-                possible
-                    .chars()
+                *self
+                    .id_alphabet
                     .choose(&mut rng)
                     .ok_or(RsmqError::BugCreatingRandonValue)?,
             );
@@ -603,7 +4342,137 @@ impl<T: ConnectionLike> RsmqFunctions<T> {
     }
 }
 
+/// Adds to the pipeline the commands that read the queue attributes: the queue hash fields, the number of messages
+/// the number of messages hidden at `now` (scores after it, same unit) and the dead-letter settings.
+/// `build_queue_attributes` parses the results
+fn add_queue_attributes_commands<'a>(
+    pipeline: &'a mut redis::Pipeline,
+    key: &str,
+    now: u64,
+) -> &'a mut redis::Pipeline {
+    pipeline
+        .cmd("HMGET")
+        .arg(format!("{}:Q", key))
+        .arg("vt")
+        .arg("delay")
+        .arg("maxsize")
+        .arg("totalrecv")
+        .arg("totalsent")
+        .arg("created")
+        .arg("modified")
+        .cmd("ZCARD")
+        .arg(key)
+        .cmd("ZCOUNT")
+        .arg(key)
+        .arg(format!("({}", now))
+        .arg("+inf")
+        .cmd("HMGET")
+        .arg(format!("{}:Q", key))
+        .arg("maxrc")
+        .arg("dlq")
+}
+
+fn build_queue_attributes(
+    fields: Vec<Option<i64>>,
+    msgs: u64,
+    hiddenmsgs: u64,
+    (max_receives, dead_letter_queue): DeadLetterFields,
+) -> RsmqResult<RsmqQueueAttributes> {
+    let is_empty = fields.contains(&None);
+
+    if is_empty {
+        return Err(RsmqError::QueueNotFound);
+    }
+
+    Ok(RsmqQueueAttributes {
+        vt: fields
+            .first()
+            .and_then(Option::as_ref)
+            .map(|dur| Duration::from_millis((*dur).try_into().unwrap_or(0)))
+            .unwrap_or(Duration::ZERO),
+        delay: fields
+            .get(1)
+            .and_then(Option::as_ref)
+            .map(|dur| Duration::from_millis((*dur).try_into().unwrap_or(0)))
+            .unwrap_or(Duration::ZERO),
+        maxsize: MaxSize::try_from(fields.get(2).unwrap_or(&Some(0)).unwrap_or(0))
+            .map_err(|_| RsmqError::CannotParseMaxsize)?,
+        totalrecv: u64::try_from(fields.get(3).unwrap_or(&Some(0)).unwrap_or(0)).unwrap_or(0),
+        totalsent: u64::try_from(fields.get(4).unwrap_or(&Some(0)).unwrap_or(0)).unwrap_or(0),
+        created: u64::try_from(fields.get(5).unwrap_or(&Some(0)).unwrap_or(0)).unwrap_or(0),
+        modified: u64::try_from(fields.get(6).unwrap_or(&Some(0)).unwrap_or(0)).unwrap_or(0),
+        max_receives: max_receives.and_then(|max_receives| max_receives.parse().ok()),
+        dead_letter_queue,
+        msgs,
+        hiddenmsgs,
+    })
+}
+
+/// Splits a handle returned by "RsmqMessage::receipt_handle" into the message id and its receive count
+fn parse_handle(handle: &str) -> RsmqResult<(&str, u64)> {
+    handle
+        .rsplit_once(':')
+        .and_then(|(id, receive_count)| Some((id, receive_count.parse::<u64>().ok()?)))
+        .ok_or_else(|| RsmqError::InvalidFormat(handle.to_string()))
+}
+
+/// Fields stored in the queue hash alongside the message body
+fn message_fields(id: &str) -> Vec<String> {
+    MESSAGE_FIELDS
+        .iter()
+        .map(|suffix| format!("{}:{}", id, suffix))
+        .collect()
+}
+
+/// Encodes the message attributes as `{length}:{name}{length}:{value}` for each one, so they can be read by the
+/// Lua scripts without restricting the characters they can have
+fn encode_attributes(attributes: &[(&str, &str)]) -> String {
+    attributes
+        .iter()
+        .map(|(name, value)| format!("{}:{}{}:{}", name.len(), name, value.len(), value))
+        .collect()
+}
+
+fn build_message<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+    id: String,
+    body: Vec<u8>,
+    rc: u64,
+    fr: u64,
+    expired: bool,
+    id_length: usize,
+) -> RsmqResult<RsmqMessage<E>> {
+    #[cfg(feature = "compression")]
+    let body = crate::compression::decompress(body).map_err(RsmqError::CannotDecodeMessage)?;
+
+    let message = E::try_from(RedisBytes(body)).map_err(RsmqDecodeError::into_rsmq_error)?;
+
+    Ok(RsmqMessage {
+        sent: sent_from_id(&id, id_length),
+        id,
+        message,
+        rc,
+        fr,
+        expired,
+    })
+}
+
+/// Checks a duration in milliseconds against the JS compatible limit. The error shows the values as durations, in
+/// the same unit as the `Duration` given by the user
+fn duration_in_range(name: &'static str, millis: u64) -> RsmqResult<()> {
+    if millis <= JS_COMPAT_MAX_TIME_MILLIS {
+        Ok(())
+    } else {
+        Err(RsmqError::InvalidParameter {
+            name,
+            value: format!("{:?}", Duration::from_millis(millis)),
+            min: format!("{:?}", Duration::ZERO),
+            max: format!("{:?}", Duration::from_millis(JS_COMPAT_MAX_TIME_MILLIS)),
+        })
+    }
+}
+
 fn number_in_range<T: std::cmp::PartialOrd + std::fmt::Display>(
+    name: &'static str,
     value: T,
     min: T,
     max: T,
@@ -611,29 +4480,124 @@ fn number_in_range<T: std::cmp::PartialOrd + std::fmt::Display>(
     if value >= min && value <= max {
         Ok(())
     } else {
-        Err(RsmqError::InvalidValue(
-            format!("{}", value),
-            format!("{}", min),
-            format!("{}", max),
-        ))
+        Err(RsmqError::InvalidParameter {
+            name,
+            value: format!("{}", value),
+            min: format!("{}", min),
+            max: format!("{}", max),
+        })
     }
 }
 
-fn valid_name_format(name: &str) -> RsmqResult<()> {
-    if name.is_empty() && name.len() > 160 {
+/// Checks that a queue name (or namespace) is 1 to 160 ASCII letters, digits, `-` or `_`
+pub(crate) fn valid_name_format(name: &str) -> RsmqResult<()> {
+    if name.is_empty()
+        || name.len() > 160
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
         return Err(RsmqError::InvalidFormat(name.to_string()));
-    } else {
-        name.chars()
-            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
     }
 
     Ok(())
 }
 
+/// Current time, from the Redis `TIME` result, as used for the message ids. Divided by `TIME_MULTIPLIER` it becomes
+/// milliseconds
+fn queue_time(redis_time: (u64, u64)) -> u64 {
+    #[cfg(feature = "break-js-comp")]
+    let time = redis_time.0 * 1000000 + redis_time.1;
+    #[cfg(not(feature = "break-js-comp"))]
+    let time = redis_time.0 * 1000;
+
+    time
+}
+
+/// Timestamp (epoch in milliseconds) of when the message was sent, taken from the beginning of its id. The id is the
+/// queue time in base 36 (milliseconds, or microseconds with `break-js-comp`) followed by `id_length` random
+/// characters
+fn sent_from_id(id: &str, id_length: usize) -> u64 {
+    id.get(0..id.len().saturating_sub(id_length))
+        .and_then(|sent| u64::from_str_radix(sent, 36).ok())
+        .map(|sent| sent / TIME_MULTIPLIER)
+        .unwrap_or(0)
+}
+
+/// Name of the dead-letter queue of `qname`
+fn dlq_name(qname: &str) -> String {
+    format!("{}-dlq", qname)
+}
+
+/// Name of the queue used by "retry_message" for the messages of `qname`
+fn retry_queue_name(qname: &str) -> String {
+    format!("{}-retry", qname)
+}
+
+/// Score of a message that becomes visible `duration` milliseconds after `ts`. Fails instead of overflowing
+fn score_after(ts: u64, duration: u64) -> RsmqResult<u64> {
+    ts.checked_add(duration)
+        .ok_or_else(|| RsmqError::InvalidParameter {
+            name: "duration",
+            value: format!("{}", duration),
+            min: "0".to_string(),
+            max: format!("{}", u64::MAX - ts),
+        })
+}
+
+// Durations too big for u64 milliseconds saturate, so the range checks reject them
 fn get_redis_duration(d: Option<Duration>, default: &Duration) -> u64 {
     d.as_ref()
         .map(Duration::as_millis)
-        .map(u64::try_from)
-        .and_then(Result::ok)
+        .map(|millis| u64::try_from(millis).unwrap_or(u64::MAX))
         .unwrap_or_else(|| u64::try_from(default.as_millis()).ok().unwrap_or(30_000))
 }
+
+/// Runs an operation inside its span when the `tracing` feature is enabled. Without it, this is just the operation
+async fn traced<R>(
+    operation: &'static str,
+    qname: &str,
+    message_id: Option<&str>,
+    future: impl Future<Output = RsmqResult<R>>,
+) -> RsmqResult<R> {
+    #[cfg(feature = "tracing")]
+    {
+        use tracing::Instrument;
+
+        future
+            .instrument(crate::instrument::operation_span(
+                operation, qname, message_id,
+            ))
+            .await
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = (operation, qname, message_id);
+        future.await
+    }
+}
+
+/// Same as "traced", used by the facades to put each operation in its span without wrapping the call
+pub(crate) trait TracedOperation: Future + Sized {
+    #[cfg(feature = "tracing")]
+    fn traced(
+        self,
+        operation: &'static str,
+        qname: &str,
+        message_id: Option<&str>,
+    ) -> tracing::instrument::Instrumented<Self> {
+        tracing::Instrument::instrument(
+            self,
+            crate::instrument::operation_span(operation, qname, message_id),
+        )
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    fn traced(self, operation: &'static str, qname: &str, message_id: Option<&str>) -> Self {
+        let _ = (operation, qname, message_id);
+        self
+    }
+}
+
+impl<F: Future> TracedOperation for F {}
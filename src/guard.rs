@@ -0,0 +1,103 @@
+use crate::r#trait::RsmqConnection;
+use crate::types::{RsmqMessage, RsmqUnackedPolicy};
+use crate::RsmqResult;
+use std::ops::Deref;
+
+/// Message returned by "receive_message_guarded". It has to be settled with "ack" (the message was processed and it
+/// is deleted) or "nack" (the message is made visible again, so it is delivered again right away). Dropping it
+/// without calling any of them is treated as a bug: it is logged as a `tracing` warning and, if
+/// `RsmqOptions::unacked_policy` is `RsmqUnackedPolicy::Requeue`, the message is made visible again by a tokio task.
+/// Otherwise the message is delivered again when its hidden time passes, as with a plain "receive_message".
+///
+/// All of them use the receipt handle of the message, so if the hidden time passed and another client received the
+/// message, they leave the new delivery alone.
+///
+/// It dereferences to the received `RsmqMessage`.
+///
+/// ```rust,ignore
+/// if let Some(message) = rsmq.receive_message_guarded::<String>("myqueue", None).await? {
+///     match process(&message.message).await {
+///         Ok(()) => message.ack().await?,
+///         Err(_) => message.nack().await?,
+///     };
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ReceivedMessage<E, R: RsmqConnection + Clone + Send + 'static> {
+    rsmq: R,
+    qname: String,
+    message: RsmqMessage<E>,
+    policy: RsmqUnackedPolicy,
+    settled: bool,
+}
+
+impl<E, R: RsmqConnection + Clone + Send + 'static> ReceivedMessage<E, R> {
+    pub(crate) fn new(
+        rsmq: R,
+        qname: &str,
+        message: RsmqMessage<E>,
+        policy: RsmqUnackedPolicy,
+    ) -> ReceivedMessage<E, R> {
+        ReceivedMessage {
+            rsmq,
+            qname: qname.to_string(),
+            message,
+            policy,
+            settled: false,
+        }
+    }
+
+    /// Deletes the message. Returns false if it was already deleted or received again by another client
+    pub async fn ack(mut self) -> RsmqResult<bool> {
+        self.settled = true;
+
+        self.rsmq
+            .delete_by_handle(&self.qname, &self.message.receipt_handle())
+            .await
+    }
+
+    /// Makes the message visible again, so it is delivered to the next receive. Returns false if it was already
+    /// deleted or received again by another client
+    pub async fn nack(mut self) -> RsmqResult<bool> {
+        self.settled = true;
+
+        self.rsmq
+            .requeue_by_handle(&self.qname, &self.message.receipt_handle())
+            .await
+    }
+}
+
+impl<E, R: RsmqConnection + Clone + Send + 'static> Deref for ReceivedMessage<E, R> {
+    type Target = RsmqMessage<E>;
+
+    fn deref(&self) -> &RsmqMessage<E> {
+        &self.message
+    }
+}
+
+impl<E, R: RsmqConnection + Clone + Send + 'static> Drop for ReceivedMessage<E, R> {
+    fn drop(&mut self) {
+        if self.settled {
+            return;
+        }
+
+        tracing::warn!(
+            qname = self.qname.as_str(),
+            message_id = self.message.id.as_str(),
+            "received message dropped without ack or nack"
+        );
+
+        // Outside of a tokio runtime the message is left hidden, like with the default policy
+        if let (RsmqUnackedPolicy::Requeue, Ok(runtime)) =
+            (self.policy, tokio::runtime::Handle::try_current())
+        {
+            let mut rsmq = self.rsmq.clone();
+            let qname = std::mem::take(&mut self.qname);
+            let handle = self.message.receipt_handle();
+
+            runtime.spawn(async move {
+                let _ = rsmq.requeue_by_handle(&qname, &handle).await;
+            });
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use tracing::field::Empty;
+use tracing::Span;
+
+/// Span of an operation. The sends record the `message_id` once it is known and the receives record `received`
+/// (whether a message was returned) and `rc`, its receive count (the highest one for a batch)
+pub(crate) fn operation_span(
+    operation: &'static str,
+    qname: &str,
+    message_id: Option<&str>,
+) -> Span {
+    tracing::debug_span!(
+        "rsmq",
+        operation,
+        qname,
+        message_id,
+        received = Empty,
+        rc = Empty
+    )
+}
+
+pub(crate) fn record_message_id(id: &str) {
+    Span::current().record("message_id", id);
+}
+
+/// Records the message returned by a single receive, if any
+pub(crate) fn record_received(message: Option<(&str, u64)>) {
+    let span = Span::current();
+
+    span.record("received", message.is_some());
+
+    if let Some((id, rc)) = message {
+        span.record("message_id", id);
+        span.record("rc", rc);
+    }
+}
+
+/// Records the receive counts of the messages returned by a batch receive
+pub(crate) fn record_received_batch(rcs: impl Iterator<Item = u64>) {
+    let span = Span::current();
+    let max_rc = rcs.max();
+
+    span.record("received", max_rc.is_some());
+
+    if let Some(rc) = max_rc {
+        span.record("rc", rc);
+    }
+}
+
+pub(crate) fn script_invoked(script: &'static str) {
+    tracing::debug!(script, "invoking script");
+}
@@ -0,0 +1,120 @@
+use crate::attributes_cache::AttributesCache;
+use crate::types::ConnectionTarget;
+use redis::aio::{ConnectionLike, MultiplexedConnection};
+use redis::{Cmd, ErrorKind, Pipeline, RedisFuture, RedisResult, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Multiplexed connection opened by "Rsmq::new" and "RsmqSync::new". After a connection error or a READONLY reply,
+/// the next request looks up the Redis again and opens a new connection, so with sentinels the instance follows a
+/// failover to the new master. The clones share the connection.
+#[derive(Clone)]
+pub(crate) struct MasterConnection {
+    target: ConnectionTarget,
+    attributes_cache: Option<AttributesCache>,
+    connection: Arc<Mutex<MultiplexedConnection>>,
+    stale: Arc<AtomicBool>,
+}
+
+impl MasterConnection {
+    pub(crate) async fn connect(
+        target: ConnectionTarget,
+        attributes_cache: Option<AttributesCache>,
+    ) -> RedisResult<MasterConnection> {
+        let connection = open(&target, attributes_cache.as_ref()).await?;
+
+        Ok(MasterConnection {
+            target,
+            attributes_cache,
+            connection: Arc::new(Mutex::new(connection)),
+            stale: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    pub(crate) fn target(&self) -> &ConnectionTarget {
+        &self.target
+    }
+
+    /// Connection for the next request, opening a new one if the last request found the current one unusable
+    async fn current(&self) -> RedisResult<MultiplexedConnection> {
+        if self.stale.swap(false, Ordering::AcqRel) {
+            match open(&self.target, self.attributes_cache.as_ref()).await {
+                Ok(connection) => {
+                    *self
+                        .connection
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner) = connection
+                }
+                Err(error) => {
+                    self.stale.store(true, Ordering::Release);
+                    return Err(error);
+                }
+            }
+        }
+
+        Ok(self
+            .connection
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone())
+    }
+
+    fn check<T>(&self, result: &RedisResult<T>) {
+        if let Err(error) = result {
+            if error.kind() == ErrorKind::ReadOnly
+                || error.is_io_error()
+                || error.is_connection_dropped()
+                || error.is_connection_refusal()
+            {
+                self.stale.store(true, Ordering::Release);
+            }
+        }
+    }
+}
+
+impl ConnectionLike for MasterConnection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        Box::pin(async move {
+            let result = self.current().await?.req_packed_command(cmd).await;
+            self.check(&result);
+            result
+        })
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        Box::pin(async move {
+            let result = self
+                .current()
+                .await?
+                .req_packed_commands(cmd, offset, count)
+                .await;
+            self.check(&result);
+            result
+        })
+    }
+
+    fn get_db(&self) -> i64 {
+        self.connection
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_db()
+    }
+}
+
+/// Opens a connection to the Redis of `target`, asking the sentinels for the master if needed
+pub(crate) async fn open(
+    target: &ConnectionTarget,
+    attributes_cache: Option<&AttributesCache>,
+) -> RedisResult<MultiplexedConnection> {
+    let client = target.client().await?;
+
+    match attributes_cache {
+        Some(cache) => cache.tracked_connection(&client).await,
+        None => client.get_multiplexed_async_connection().await,
+    }
+}
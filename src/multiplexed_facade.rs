@@ -1,71 +1,197 @@
-use crate::functions::{CachedScript, RsmqFunctions};
+use crate::attributes_cache::AttributesCache;
+use crate::functions::{CachedScript, RsmqFunctions, TracedOperation};
+use crate::master_connection::MasterConnection;
+use crate::observer::ObservedConnection;
 use crate::r#trait::RsmqConnection;
-use crate::types::{RedisBytes, RsmqMessage, RsmqOptions, RsmqQueueAttributes};
-use crate::RsmqResult;
+use crate::types::{
+    MaxSize, RedisBytes, RsmqAuditRecord, RsmqBatchMode, RsmqConfigSummary, RsmqConnectionInfo,
+    RsmqMessage, RsmqMessageMeta, RsmqOptions, RsmqQueueAttributes, RsmqQueueConfig,
+};
+use crate::{RsmqDecodeError, RsmqResult};
 use core::convert::TryFrom;
-use core::marker::PhantomData;
+use redis::aio::{ConnectionLike, MultiplexedConnection};
+use redis::{Cmd, Pipeline, RedisFuture, Value};
 use std::time::Duration;
 
 #[derive(Clone)]
-struct RedisConnection(redis::aio::MultiplexedConnection);
+enum RedisConnection<C> {
+    /// Connection given to "new_with_connection" and the like
+    Given(C),
+    /// Connection opened by "new", which follows the master after a failover
+    Master(MasterConnection),
+}
 
-impl std::fmt::Debug for RedisConnection {
+impl<C> std::fmt::Debug for RedisConnection<C> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "MultiplexedRedisAsyncConnnection")
+        write!(f, "RedisAsyncConnection({})", std::any::type_name::<C>())
+    }
+}
+
+impl<C: ConnectionLike> ConnectionLike for RedisConnection<C> {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        match self {
+            RedisConnection::Given(connection) => connection.req_packed_command(cmd),
+            RedisConnection::Master(connection) => connection.req_packed_command(cmd),
+        }
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        match self {
+            RedisConnection::Given(connection) => {
+                connection.req_packed_commands(cmd, offset, count)
+            }
+            RedisConnection::Master(connection) => {
+                connection.req_packed_commands(cmd, offset, count)
+            }
+        }
+    }
+
+    fn get_db(&self) -> i64 {
+        match self {
+            RedisConnection::Given(connection) => connection.get_db(),
+            RedisConnection::Master(connection) => connection.get_db(),
+        }
     }
 }
 
+/// RSMQ instance using a single multiplexed connection. Any other connection implementing redis-rs `ConnectionLike`
+/// can be used instead with "new_with_generic_connection" (for example a wrapper around the multiplexed connection).
+/// The connection is cloned for every operation, so it should be cheap to clone.
 #[derive(Debug, Clone)]
-pub struct Rsmq {
-    connection: RedisConnection,
-    functions: RsmqFunctions<redis::aio::MultiplexedConnection>,
+pub struct Rsmq<C: ConnectionLike = MultiplexedConnection> {
+    connection: RedisConnection<C>,
+    functions: RsmqFunctions<ObservedConnection<RedisConnection<C>>>,
     scripts: CachedScript,
 }
 
 impl Rsmq {
-    /// Creates a new RSMQ instance, including its connection
+    /// Creates a new RSMQ instance, including its connection. After a connection error or a READONLY reply the
+    /// connection is opened again, asking the sentinels for the current master when `RsmqOptions::sentinel` is set
     pub async fn new(options: RsmqOptions) -> RsmqResult<Rsmq> {
-        let conn_info = redis::ConnectionInfo {
-            addr: redis::ConnectionAddr::Tcp(options.host, options.port),
-            redis: redis::RedisConnectionInfo {
-                db: options.db.into(),
-                username: options.username,
-                password: options.password,
-                protocol: options.protocol,
-            },
-        };
+        let cache = options.client_side_caching.then(AttributesCache::default);
+
+        let connection =
+            MasterConnection::connect(options.connection_target()?, cache.clone()).await?;
 
-        let client = redis::Client::open(conn_info)?;
+        let mut rsmq = Rsmq::with_connection(RedisConnection::Master(connection), &options).await?;
 
-        let connection = client.get_multiplexed_async_connection().await?;
+        rsmq.functions.attributes_cache = cache;
 
-        Rsmq::new_with_connection(connection, options.realtime, Some(&options.ns)).await
+        Ok(rsmq)
     }
 
     /// Special method for when you already have a redis-rs connection and you don't want redis_async to create a new one.
     pub async fn new_with_connection(
-        mut connection: redis::aio::MultiplexedConnection,
+        connection: MultiplexedConnection,
         realtime: bool,
         ns: Option<&str>,
     ) -> RsmqResult<Rsmq> {
-        let functions = RsmqFunctions {
+        let options = RsmqOptions {
+            realtime,
             ns: ns.unwrap_or("rsmq").to_string(),
+            ..Default::default()
+        };
+
+        Rsmq::new_with_connection_and_options(connection, &options).await
+    }
+
+    /// Same as "new_with_connection" but it takes the rest of the settings from `options`. The connection related
+    /// options (host, port, etc) and `client_side_caching` are ignored.
+    pub async fn new_with_connection_and_options(
+        connection: MultiplexedConnection,
+        options: &RsmqOptions,
+    ) -> RsmqResult<Rsmq> {
+        Rsmq::new_with_generic_connection_and_options(connection, options).await
+    }
+}
+
+impl<C: ConnectionLike + Clone + Send + Sync> Rsmq<C> {
+    /// Same as "new_with_connection" but with any redis-rs connection instead of a multiplexed one
+    pub async fn new_with_generic_connection(
+        connection: C,
+        realtime: bool,
+        ns: Option<&str>,
+    ) -> RsmqResult<Rsmq<C>> {
+        let options = RsmqOptions {
             realtime,
-            conn: PhantomData,
+            ns: ns.unwrap_or("rsmq").to_string(),
+            ..Default::default()
         };
 
-        let scripts = functions.load_scripts(&mut connection).await?;
+        Rsmq::new_with_generic_connection_and_options(connection, &options).await
+    }
+
+    /// Same as "new_with_connection_and_options" but with any redis-rs connection instead of a multiplexed one
+    pub async fn new_with_generic_connection_and_options(
+        connection: C,
+        options: &RsmqOptions,
+    ) -> RsmqResult<Rsmq<C>> {
+        Rsmq::with_connection(RedisConnection::Given(connection), options).await
+    }
+
+    async fn with_connection(
+        connection: RedisConnection<C>,
+        options: &RsmqOptions,
+    ) -> RsmqResult<Rsmq<C>> {
+        let mut functions = RsmqFunctions::new(options);
+
+        functions.connection_info.db = connection.get_db();
+
+        let scripts = functions
+            .load_scripts(&mut ObservedConnection::new(
+                connection.clone(),
+                "load_scripts",
+                functions.observer.clone(),
+            ))
+            .await?;
 
         Ok(Rsmq {
-            connection: RedisConnection(connection),
+            connection,
             functions,
             scripts,
         })
     }
+
+    /// Subscribes to the realtime channel of the queue (see "realtime_channel") with a new connection and returns
+    /// what each send publishes: the number of messages of the queue, or the id of the sent message with
+    /// `RsmqRealtimePayload::MessageId`. The queue needs `RsmqOptions::realtime`, or nothing is published. Fails
+    /// with `RsmqError::NoClient` if this instance was created with "new_with_connection", as there is no client to
+    /// open the new connection. With sentinels, the connection goes to the current master.
+    #[cfg(feature = "stream")]
+    pub async fn subscribe_new_messages(
+        &self,
+        qname: &str,
+    ) -> RsmqResult<impl futures_util::Stream<Item = crate::RsmqNewMessage>> {
+        let client = match &self.connection {
+            RedisConnection::Master(connection) => Some(connection.target().client().await?),
+            RedisConnection::Given(_) => None,
+        };
+
+        crate::stream::new_messages(
+            client.as_ref(),
+            self.functions.realtime_channel(qname),
+            self.functions.realtime_payload,
+        )
+        .await
+    }
+
+    /// Connection for a single operation, reporting its round trips to the observer
+    fn observed(&self, operation: &'static str) -> ObservedConnection<RedisConnection<C>> {
+        ObservedConnection::new(
+            self.connection.clone(),
+            operation,
+            self.functions.observer.clone(),
+        )
+    }
 }
 
 #[async_trait::async_trait]
-impl RsmqConnection for Rsmq {
+impl<C: ConnectionLike + Clone + Send + Sync> RsmqConnection for Rsmq<C> {
     async fn change_message_visibility(
         &mut self,
         qname: &str,
@@ -74,12 +200,51 @@ impl RsmqConnection for Rsmq {
     ) -> RsmqResult<()> {
         self.functions
             .change_message_visibility(
-                &mut self.connection.0,
+                &mut self.observed("change_message_visibility"),
                 qname,
                 message_id,
                 hidden,
                 &self.scripts,
             )
+            .traced("change_message_visibility", qname, Some(message_id))
+            .await
+    }
+
+    async fn clone_queue_config(&mut self, src: &str, dest: &str) -> RsmqResult<()> {
+        self.functions
+            .clone_queue_config(&mut self.observed("clone_queue_config"), src, dest)
+            .traced("clone_queue_config", src, None)
+            .await
+    }
+
+    fn config_summary(&self) -> RsmqConfigSummary {
+        self.functions.config_summary()
+    }
+
+    fn connection_info(&self) -> RsmqConnectionInfo {
+        match &self.connection {
+            RedisConnection::Master(connection) => connection.target().connection_info(),
+            RedisConnection::Given(_) => None,
+        }
+        .unwrap_or_else(|| self.functions.connection_info.clone())
+    }
+
+    async fn count_group(&mut self, qname: &str, group_id: &str) -> RsmqResult<u64> {
+        self.functions
+            .count_group(
+                &mut self.observed("count_group"),
+                qname,
+                group_id,
+                &self.scripts,
+            )
+            .traced("count_group", qname, None)
+            .await
+    }
+
+    async fn count_visible_messages(&mut self, qname: &str) -> RsmqResult<u64> {
+        self.functions
+            .count_visible_messages(&mut self.observed("count_visible_messages"), qname)
+            .traced("count_visible_messages", qname, None)
             .await
     }
 
@@ -88,72 +253,992 @@ impl RsmqConnection for Rsmq {
         qname: &str,
         hidden: Option<Duration>,
         delay: Option<Duration>,
-        maxsize: Option<i32>,
+        maxsize: Option<MaxSize>,
+    ) -> RsmqResult<()> {
+        self.functions
+            .create_queue(
+                &mut self.observed("create_queue"),
+                qname,
+                hidden,
+                delay,
+                maxsize,
+            )
+            .traced("create_queue", qname, None)
+            .await
+    }
+
+    async fn create_queue_if_not_exists(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+        delay: Option<Duration>,
+        maxsize: Option<MaxSize>,
+    ) -> RsmqResult<bool> {
+        self.functions
+            .create_queue_if_not_exists(
+                &mut self.observed("create_queue_if_not_exists"),
+                qname,
+                hidden,
+                delay,
+                maxsize,
+            )
+            .traced("create_queue_if_not_exists", qname, None)
+            .await
+    }
+
+    async fn create_queue_with_config(
+        &mut self,
+        qname: &str,
+        config: &RsmqQueueConfig,
     ) -> RsmqResult<()> {
         self.functions
-            .create_queue(&mut self.connection.0, qname, hidden, delay, maxsize)
+            .create_queue_with_config(
+                &mut self.observed("create_queue_with_config"),
+                qname,
+                config,
+            )
+            .traced("create_queue_with_config", qname, None)
+            .await
+    }
+
+    async fn delete_by_handle(&mut self, qname: &str, handle: &str) -> RsmqResult<bool> {
+        self.functions
+            .delete_by_handle(
+                &mut self.observed("delete_by_handle"),
+                qname,
+                handle,
+                &self.scripts,
+            )
+            .traced("delete_by_handle", qname, None)
             .await
     }
 
     async fn delete_message(&mut self, qname: &str, id: &str) -> RsmqResult<bool> {
         self.functions
-            .delete_message(&mut self.connection.0, qname, id)
+            .delete_message(
+                &mut self.observed("delete_message"),
+                qname,
+                id,
+                &self.scripts,
+            )
+            .await
+    }
+    async fn delete_message_if_unchanged(
+        &mut self,
+        qname: &str,
+        id: &str,
+        expected: &[u8],
+    ) -> RsmqResult<bool> {
+        self.functions
+            .delete_message_if_unchanged(
+                &mut self.observed("delete_message_if_unchanged"),
+                qname,
+                id,
+                expected,
+                &self.scripts,
+            )
+            .traced("delete_message_if_unchanged", qname, Some(id))
+            .await
+    }
+
+    async fn delete_message_strict(&mut self, qname: &str, id: &str) -> RsmqResult<bool> {
+        self.functions
+            .delete_message_strict(
+                &mut self.observed("delete_message_strict"),
+                qname,
+                id,
+                &self.scripts,
+            )
+            .traced("delete_message_strict", qname, Some(id))
+            .await
+    }
+
+    async fn delete_messages(&mut self, qname: &str, ids: &[&str]) -> RsmqResult<Vec<bool>> {
+        self.functions
+            .delete_messages(
+                &mut self.observed("delete_messages"),
+                qname,
+                ids,
+                &self.scripts,
+            )
             .await
     }
+
     async fn delete_queue(&mut self, qname: &str) -> RsmqResult<()> {
         self.functions
-            .delete_queue(&mut self.connection.0, qname)
+            .delete_queue(&mut self.observed("delete_queue"), qname)
+            .traced("delete_queue", qname, None)
+            .await
+    }
+    async fn delete_queue_if_empty(&mut self, qname: &str) -> RsmqResult<bool> {
+        self.functions
+            .delete_queue_if_empty(
+                &mut self.observed("delete_queue_if_empty"),
+                qname,
+                &self.scripts,
+            )
+            .traced("delete_queue_if_empty", qname, None)
+            .await
+    }
+
+    async fn delete_queue_returning_attributes(
+        &mut self,
+        qname: &str,
+    ) -> RsmqResult<RsmqQueueAttributes> {
+        self.functions
+            .delete_queue_returning_attributes(
+                &mut self.observed("delete_queue_returning_attributes"),
+                qname,
+            )
+            .traced("delete_queue_returning_attributes", qname, None)
+            .await
+    }
+
+    async fn eval_script(
+        &mut self,
+        script: &str,
+        qnames: &[&str],
+        args: &[&str],
+    ) -> RsmqResult<redis::Value> {
+        self.functions
+            .eval_script(&mut self.observed("eval_script"), script, qnames, args)
+            .traced("eval_script", "", None)
+            .await
+    }
+
+    async fn for_each_message<E, F>(
+        &mut self,
+        qname: &str,
+        include_hidden: bool,
+        f: F,
+    ) -> RsmqResult<bool>
+    where
+        E: TryFrom<RedisBytes, Error: RsmqDecodeError>,
+        F: FnMut(RsmqMessage<E>) + Send,
+    {
+        self.functions
+            .for_each_message(
+                &mut self.observed("for_each_message"),
+                qname,
+                include_hidden,
+                f,
+            )
+            .traced("for_each_message", qname, None)
+            .await
+    }
+
+    async fn get_max_depth(&mut self, qname: &str) -> RsmqResult<u64> {
+        self.functions
+            .get_max_depth(&mut self.observed("get_max_depth"), qname)
+            .traced("get_max_depth", qname, None)
+            .await
+    }
+
+    async fn get_message<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        message_id: &str,
+    ) -> RsmqResult<Option<RsmqMessage<E>>> {
+        self.functions
+            .get_message(&mut self.observed("get_message"), qname, message_id)
+            .traced("get_message", qname, Some(message_id))
             .await
     }
+
     async fn get_queue_attributes(&mut self, qname: &str) -> RsmqResult<RsmqQueueAttributes> {
         self.functions
-            .get_queue_attributes(&mut self.connection.0, qname)
+            .get_queue_attributes(&mut self.observed("get_queue_attributes"), qname)
+            .traced("get_queue_attributes", qname, None)
+            .await
+    }
+
+    async fn get_queue_attributes_checked(
+        &mut self,
+        qname: &str,
+    ) -> RsmqResult<RsmqQueueAttributes> {
+        self.functions
+            .get_queue_attributes_checked(&mut self.observed("get_queue_attributes_checked"), qname)
+            .traced("get_queue_attributes_checked", qname, None)
+            .await
+    }
+
+    async fn get_queue_attributes_many(
+        &mut self,
+        qnames: &[&str],
+    ) -> RsmqResult<Vec<(String, RsmqResult<RsmqQueueAttributes>)>> {
+        self.functions
+            .get_queue_attributes_many(&mut self.observed("get_queue_attributes_many"), qnames)
+            .traced("get_queue_attributes_many", "", None)
+            .await
+    }
+
+    async fn list_active_queues(&mut self) -> RsmqResult<Vec<String>> {
+        self.functions
+            .list_active_queues(&mut self.observed("list_active_queues"))
+            .traced("list_active_queues", "", None)
             .await
     }
 
     async fn list_queues(&mut self) -> RsmqResult<Vec<String>> {
-        self.functions.list_queues(&mut self.connection.0).await
+        self.functions
+            .list_queues(&mut self.observed("list_queues"))
+            .traced("list_queues", "", None)
+            .await
+    }
+
+    async fn list_queues_with_attributes(
+        &mut self,
+    ) -> RsmqResult<Vec<(String, RsmqQueueAttributes)>> {
+        self.functions
+            .list_queues_with_attributes(&mut self.observed("list_queues_with_attributes"))
+            .traced("list_queues_with_attributes", "", None)
+            .await
+    }
+
+    async fn message_time_remaining(
+        &mut self,
+        qname: &str,
+        id: &str,
+    ) -> RsmqResult<Option<Duration>> {
+        self.functions
+            .message_time_remaining(&mut self.observed("message_time_remaining"), qname, id)
+            .traced("message_time_remaining", qname, Some(id))
+            .await
+    }
+
+    async fn move_message(&mut self, from: &str, to: &str, message_id: &str) -> RsmqResult<bool> {
+        self.functions
+            .move_message(
+                &mut self.observed("move_message"),
+                from,
+                to,
+                message_id,
+                &self.scripts,
+            )
+            .traced("move_message", from, Some(message_id))
+            .await
+    }
+
+    async fn peek_message<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+    ) -> RsmqResult<Option<RsmqMessage<E>>> {
+        self.functions
+            .peek_message::<E>(&mut self.observed("peek_message"), qname, &self.scripts)
+            .traced("peek_message", qname, None)
+            .await
+    }
+
+    async fn peek_message_meta(&mut self, qname: &str) -> RsmqResult<Option<RsmqMessageMeta>> {
+        self.functions
+            .peek_message_meta(
+                &mut self.observed("peek_message_meta"),
+                qname,
+                &self.scripts,
+            )
+            .traced("peek_message_meta", qname, None)
+            .await
     }
 
-    async fn pop_message<E: TryFrom<RedisBytes, Error = Vec<u8>>>(
+    async fn pop_message<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
         &mut self,
         qname: &str,
     ) -> RsmqResult<Option<RsmqMessage<E>>> {
         self.functions
-            .pop_message::<E>(&mut self.connection.0, qname, &self.scripts)
+            .pop_message::<E>(&mut self.observed("pop_message"), qname, &self.scripts)
+            .traced("pop_message", qname, None)
+            .await
+    }
+
+    async fn pop_message_batch<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        count: usize,
+    ) -> RsmqResult<Vec<RsmqMessage<E>>> {
+        self.functions
+            .pop_message_batch::<E>(
+                &mut self.observed("pop_message_batch"),
+                qname,
+                count,
+                &self.scripts,
+            )
+            .traced("pop_message_batch", qname, None)
+            .await
+    }
+
+    async fn promote_matured_retries(&mut self, qname: &str) -> RsmqResult<u64> {
+        self.functions
+            .promote_matured_retries(
+                &mut self.observed("promote_matured_retries"),
+                qname,
+                &self.scripts,
+            )
+            .traced("promote_matured_retries", qname, None)
+            .await
+    }
+
+    async fn purge_dlq(&mut self, qname: &str) -> RsmqResult<u64> {
+        self.functions
+            .purge_dlq(&mut self.observed("purge_dlq"), qname, &self.scripts)
+            .traced("purge_dlq", qname, None)
+            .await
+    }
+
+    async fn purge_queue(&mut self, qname: &str) -> RsmqResult<u64> {
+        self.functions
+            .purge_queue(&mut self.observed("purge_queue"), qname, &self.scripts)
+            .traced("purge_queue", qname, None)
+            .await
+    }
+
+    async fn queue_exists(&mut self, qname: &str) -> RsmqResult<bool> {
+        self.functions
+            .queue_exists(&mut self.observed("queue_exists"), qname)
+            .traced("queue_exists", qname, None)
+            .await
+    }
+
+    async fn read_audit(&mut self, qname: &str, since: u64) -> RsmqResult<Vec<RsmqAuditRecord>> {
+        self.functions
+            .read_audit(&mut self.observed("read_audit"), qname, since)
+            .traced("read_audit", qname, None)
             .await
     }
 
-    async fn receive_message<E: TryFrom<RedisBytes, Error = Vec<u8>>>(
+    fn realtime_channel(&self, qname: &str) -> String {
+        self.functions.realtime_channel(qname)
+    }
+
+    async fn receive_from_dlq<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
         &mut self,
         qname: &str,
         hidden: Option<Duration>,
     ) -> RsmqResult<Option<RsmqMessage<E>>> {
         self.functions
-            .receive_message::<E>(&mut self.connection.0, qname, hidden, &self.scripts)
+            .receive_from_dlq::<E>(
+                &mut self.observed("receive_from_dlq"),
+                qname,
+                hidden,
+                &self.scripts,
+            )
+            .traced("receive_from_dlq", qname, None)
             .await
     }
 
-    async fn send_message<E: Into<RedisBytes> + Send>(
+    async fn receive_message<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
         &mut self,
         qname: &str,
-        message: E,
-        delay: Option<Duration>,
-    ) -> RsmqResult<String> {
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<RsmqMessage<E>>> {
         self.functions
-            .send_message(&mut self.connection.0, qname, message, delay)
+            .receive_message::<E>(
+                &mut self.observed("receive_message"),
+                qname,
+                hidden,
+                &self.scripts,
+            )
             .await
     }
 
-    async fn set_queue_attributes(
+    async fn receive_message_blocking<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
         &mut self,
         qname: &str,
         hidden: Option<Duration>,
-        delay: Option<Duration>,
-        maxsize: Option<i64>,
-    ) -> RsmqResult<RsmqQueueAttributes> {
+        timeout: Duration,
+    ) -> RsmqResult<Option<RsmqMessage<E>>> {
         self.functions
-            .set_queue_attributes(&mut self.connection.0, qname, hidden, delay, maxsize)
+            .receive_message_blocking::<E>(
+                &mut self.observed("receive_message_blocking"),
+                qname,
+                hidden,
+                timeout,
+                &self.scripts,
+            )
+            .traced("receive_message_blocking", qname, None)
+            .await
+    }
+
+    async fn receive_message_ensuring<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        config: &RsmqQueueConfig,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<RsmqMessage<E>>> {
+        self.functions
+            .receive_message_ensuring::<E>(
+                &mut self.observed("receive_message_ensuring"),
+                qname,
+                config,
+                hidden,
+                &self.scripts,
+            )
+            .traced("receive_message_ensuring", qname, None)
+            .await
+    }
+
+    #[cfg(feature = "serde")]
+    async fn receive_message_json<M: serde::de::DeserializeOwned>(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<RsmqMessage<M>>> {
+        self.functions
+            .receive_message_json::<M>(
+                &mut self.observed("receive_message_json"),
+                qname,
+                hidden,
+                &self.scripts,
+            )
+            .traced("receive_message_json", qname, None)
+            .await
+    }
+
+    #[cfg(feature = "otel")]
+    async fn receive_message_traced<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<(RsmqMessage<E>, opentelemetry::Context)>> {
+        self.functions
+            .receive_message_traced::<E>(
+                &mut self.observed("receive_message_traced"),
+                qname,
+                hidden,
+                &self.scripts,
+            )
+            .traced("receive_message_traced", qname, None)
+            .await
+    }
+
+    async fn receive_message_where<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        attribute: &str,
+        value: &str,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<RsmqMessage<E>>> {
+        self.functions
+            .receive_message_where::<E>(
+                &mut self.observed("receive_message_where"),
+                qname,
+                attribute,
+                value,
+                hidden,
+                &self.scripts,
+            )
+            .traced("receive_message_where", qname, None)
+            .await
+    }
+
+    async fn receive_message_with_attributes<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<(Option<RsmqMessage<E>>, RsmqQueueAttributes)> {
+        self.functions
+            .receive_message_with_attributes::<E>(
+                &mut self.observed("receive_message_with_attributes"),
+                qname,
+                hidden,
+                &self.scripts,
+            )
+            .traced("receive_message_with_attributes", qname, None)
+            .await
+    }
+
+    async fn receive_message_with_reply_to<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<(RsmqMessage<E>, Option<String>)>> {
+        self.functions
+            .receive_message_with_reply_to::<E>(
+                &mut self.observed("receive_message_with_reply_to"),
+                qname,
+                hidden,
+                &self.scripts,
+            )
+            .traced("receive_message_with_reply_to", qname, None)
+            .await
+    }
+
+    async fn receive_message_with_sequence<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<(RsmqMessage<E>, u64)>> {
+        self.functions
+            .receive_message_with_sequence::<E>(
+                &mut self.observed("receive_message_with_sequence"),
+                qname,
+                hidden,
+                &self.scripts,
+            )
+            .traced("receive_message_with_sequence", qname, None)
+            .await
+    }
+
+    async fn receive_messages<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+        count: usize,
+    ) -> RsmqResult<Vec<RsmqMessage<E>>> {
+        self.functions
+            .receive_messages::<E>(
+                &mut self.observed("receive_messages"),
+                qname,
+                hidden,
+                count,
+                &self.scripts,
+            )
+            .await
+    }
+
+    async fn receive_multi<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qnames: &[&str],
+        max_total: usize,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Vec<(String, RsmqMessage<E>)>> {
+        self.functions
+            .receive_multi::<E>(
+                &mut self.observed("receive_multi"),
+                qnames,
+                max_total,
+                hidden,
+                &self.scripts,
+            )
+            .traced("receive_multi", "", None)
+            .await
+    }
+
+    async fn record_external_send(&mut self, qname: &str, count: u64) -> RsmqResult<()> {
+        self.functions
+            .record_external_send(&mut self.observed("record_external_send"), qname, count)
+            .traced("record_external_send", qname, None)
+            .await
+    }
+
+    async fn rename_queue(&mut self, from: &str, to: &str) -> RsmqResult<()> {
+        self.functions
+            .rename_queue(&mut self.observed("rename_queue"), from, to, &self.scripts)
+            .traced("rename_queue", from, None)
+            .await
+    }
+
+    async fn replay_from_dlq(&mut self, qname: &str, id: &str) -> RsmqResult<bool> {
+        self.functions
+            .replay_from_dlq(
+                &mut self.observed("replay_from_dlq"),
+                qname,
+                id,
+                &self.scripts,
+            )
+            .traced("replay_from_dlq", qname, Some(id))
+            .await
+    }
+
+    async fn reply<E: Into<RedisBytes> + Send>(
+        &mut self,
+        reply_to: &str,
+        payload: E,
+    ) -> RsmqResult<String> {
+        self.functions
+            .reply(
+                &mut self.observed("reply"),
+                reply_to,
+                payload,
+                &self.scripts,
+            )
+            .traced("reply", reply_to, None)
+            .await
+    }
+
+    async fn requeue_message(&mut self, qname: &str, message_id: &str) -> RsmqResult<bool> {
+        self.functions
+            .requeue_message(
+                &mut self.observed("requeue_message"),
+                qname,
+                message_id,
+                &self.scripts,
+            )
+            .traced("requeue_message", qname, Some(message_id))
+            .await
+    }
+
+    async fn requeue_by_handle(&mut self, qname: &str, handle: &str) -> RsmqResult<bool> {
+        self.functions
+            .requeue_by_handle(
+                &mut self.observed("requeue_by_handle"),
+                qname,
+                handle,
+                &self.scripts,
+            )
+            .traced("requeue_by_handle", qname, None)
+            .await
+    }
+
+    async fn reset_max_depth(&mut self, qname: &str) -> RsmqResult<u64> {
+        self.functions
+            .reset_max_depth(&mut self.observed("reset_max_depth"), qname)
+            .traced("reset_max_depth", qname, None)
+            .await
+    }
+
+    async fn retry_message(
+        &mut self,
+        qname: &str,
+        id: &str,
+        attempt: u32,
+        base: Duration,
+    ) -> RsmqResult<bool> {
+        self.functions
+            .retry_message(
+                &mut self.observed("retry_message"),
+                qname,
+                id,
+                attempt,
+                base,
+                &self.scripts,
+            )
+            .traced("retry_message", qname, Some(id))
+            .await
+    }
+
+    async fn send_message<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        message: E,
+        delay: Option<Duration>,
+    ) -> RsmqResult<String> {
+        self.functions
+            .send_message(
+                &mut self.observed("send_message"),
+                qname,
+                message,
+                delay,
+                &self.scripts,
+            )
+            .await
+    }
+
+    async fn send_message_deduplicated<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        deduplication_id: &str,
+        message: E,
+        delay: Option<Duration>,
+    ) -> RsmqResult<String> {
+        self.functions
+            .send_message_deduplicated(
+                &mut self.observed("send_message_deduplicated"),
+                qname,
+                deduplication_id,
+                message,
+                delay,
+                &self.scripts,
+            )
+            .traced("send_message_deduplicated", qname, None)
+            .await
+    }
+
+    async fn send_message_grouped<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        group_id: &str,
+        message: E,
+        delay: Option<Duration>,
+    ) -> RsmqResult<String> {
+        self.functions
+            .send_message_grouped(
+                &mut self.observed("send_message_grouped"),
+                qname,
+                group_id,
+                message,
+                delay,
+                &self.scripts,
+            )
+            .traced("send_message_grouped", qname, None)
+            .await
+    }
+
+    async fn send_message_if_below<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        message: E,
+        delay: Option<Duration>,
+        max_depth: u64,
+    ) -> RsmqResult<Option<String>> {
+        self.functions
+            .send_message_if_below(
+                &mut self.observed("send_message_if_below"),
+                qname,
+                message,
+                delay,
+                max_depth,
+                &self.scripts,
+            )
+            .traced("send_message_if_below", qname, None)
+            .await
+    }
+
+    #[cfg(feature = "serde")]
+    async fn send_message_json<M: serde::Serialize + Sync>(
+        &mut self,
+        qname: &str,
+        message: &M,
+        delay: Option<Duration>,
+    ) -> RsmqResult<String> {
+        self.functions
+            .send_message_json(
+                &mut self.observed("send_message_json"),
+                qname,
+                message,
+                delay,
+                &self.scripts,
+            )
+            .traced("send_message_json", qname, None)
+            .await
+    }
+
+    async fn send_message_ordered<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        key: &str,
+        message: E,
+        delay: Option<Duration>,
+    ) -> RsmqResult<String> {
+        self.functions
+            .send_message_ordered(
+                &mut self.observed("send_message_ordered"),
+                qname,
+                key,
+                message,
+                delay,
+                &self.scripts,
+            )
+            .traced("send_message_ordered", qname, None)
+            .await
+    }
+
+    async fn send_message_ref(
+        &mut self,
+        qname: &str,
+        message: &[u8],
+        delay: Option<Duration>,
+    ) -> RsmqResult<String> {
+        self.functions
+            .send_message_ref(
+                &mut self.observed("send_message_ref"),
+                qname,
+                message,
+                delay,
+                &self.scripts,
+            )
+            .traced("send_message_ref", qname, None)
+            .await
+    }
+
+    #[cfg(feature = "otel")]
+    async fn send_message_traced<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        message: E,
+        delay: Option<Duration>,
+    ) -> RsmqResult<String> {
+        self.functions
+            .send_message_traced(
+                &mut self.observed("send_message_traced"),
+                qname,
+                message,
+                delay,
+                &self.scripts,
+            )
+            .traced("send_message_traced", qname, None)
+            .await
+    }
+
+    async fn send_message_with_attributes<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        message: E,
+        delay: Option<Duration>,
+        attributes: &[(&str, &str)],
+    ) -> RsmqResult<String> {
+        self.functions
+            .send_message_with_attributes(
+                &mut self.observed("send_message_with_attributes"),
+                qname,
+                message,
+                delay,
+                attributes,
+                &self.scripts,
+            )
+            .traced("send_message_with_attributes", qname, None)
+            .await
+    }
+
+    async fn send_message_with_deadline<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        message: E,
+        delay: Option<Duration>,
+        lifetime: Duration,
+    ) -> RsmqResult<String> {
+        self.functions
+            .send_message_with_deadline(
+                &mut self.observed("send_message_with_deadline"),
+                qname,
+                message,
+                delay,
+                lifetime,
+                &self.scripts,
+            )
+            .traced("send_message_with_deadline", qname, None)
+            .await
+    }
+
+    async fn send_message_with_reply_to<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        message: E,
+        delay: Option<Duration>,
+        reply_to: &str,
+    ) -> RsmqResult<String> {
+        self.functions
+            .send_message_with_reply_to(
+                &mut self.observed("send_message_with_reply_to"),
+                qname,
+                message,
+                delay,
+                reply_to,
+                &self.scripts,
+            )
+            .traced("send_message_with_reply_to", qname, None)
+            .await
+    }
+
+    async fn send_message_with_score<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        message: E,
+        score: u64,
+    ) -> RsmqResult<String> {
+        self.functions
+            .send_message_with_score(
+                &mut self.observed("send_message_with_score"),
+                qname,
+                message,
+                score,
+                &self.scripts,
+            )
+            .traced("send_message_with_score", qname, None)
+            .await
+    }
+
+    async fn send_messages<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        messages: Vec<(E, Option<Duration>)>,
+    ) -> RsmqResult<Vec<String>> {
+        self.functions
+            .send_messages(
+                &mut self.observed("send_messages"),
+                qname,
+                messages,
+                &self.scripts,
+            )
+            .await
+    }
+
+    async fn send_messages_with_mode<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        messages: Vec<(E, Option<Duration>)>,
+        mode: RsmqBatchMode,
+    ) -> RsmqResult<Vec<RsmqResult<String>>> {
+        self.functions
+            .send_messages_with_mode(
+                &mut self.observed("send_messages_with_mode"),
+                qname,
+                messages,
+                mode,
+                &self.scripts,
+            )
+            .traced("send_messages_with_mode", qname, None)
+            .await
+    }
+
+    async fn set_queue_attribute_if_absent(
+        &mut self,
+        qname: &str,
+        field: &str,
+        value: &str,
+    ) -> RsmqResult<bool> {
+        self.functions
+            .set_queue_attribute_if_absent(
+                &mut self.observed("set_queue_attribute_if_absent"),
+                qname,
+                field,
+                value,
+            )
+            .traced("set_queue_attribute_if_absent", qname, None)
+            .await
+    }
+
+    async fn set_queue_attributes(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+        delay: Option<Duration>,
+        maxsize: Option<MaxSize>,
+    ) -> RsmqResult<RsmqQueueAttributes> {
+        self.functions
+            .set_queue_attributes(
+                &mut self.observed("set_queue_attributes"),
+                qname,
+                hidden,
+                delay,
+                maxsize,
+            )
+            .traced("set_queue_attributes", qname, None)
+            .await
+    }
+
+    async fn swap_message_order(
+        &mut self,
+        qname: &str,
+        id_a: &str,
+        id_b: &str,
+    ) -> RsmqResult<bool> {
+        self.functions
+            .swap_message_order(
+                &mut self.observed("swap_message_order"),
+                qname,
+                id_a,
+                id_b,
+                &self.scripts,
+            )
+            .traced("swap_message_order", qname, None)
+            .await
+    }
+
+    async fn sweep_stale_to_dlq(
+        &mut self,
+        qname: &str,
+        older_than: Duration,
+        dlq: &str,
+    ) -> RsmqResult<u64> {
+        self.functions
+            .sweep_stale_to_dlq(
+                &mut self.observed("sweep_stale_to_dlq"),
+                qname,
+                older_than,
+                dlq,
+                &self.scripts,
+            )
+            .traced("sweep_stale_to_dlq", qname, None)
             .await
     }
 }
@@ -0,0 +1,115 @@
+use redis::aio::{ConnectionLike, MultiplexedConnection};
+use redis::{Cmd, Pipeline, RedisFuture, Value};
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// Receives information about the operations made by an RSMQ instance, for debugging or for collecting metrics. Set it
+/// in `RsmqOptions::observer`. Every method has an empty default implementation, so you only need to implement the
+/// ones you are interested in. They are called inline, so they should be fast.
+///
+/// ```rust,ignore
+/// #[derive(Debug)]
+/// struct RoundTripLogger;
+///
+/// impl RsmqObserver for RoundTripLogger {
+///     fn round_trips(&self, operation: &'static str, count: u32) {
+///         println!("{} made {} round trips to Redis", operation, count);
+///     }
+/// }
+/// ```
+pub trait RsmqObserver: Debug + Send + Sync {
+    /// Called when an operation (like "send_message") ends, successfully or not, with the number of requests it sent
+    /// to Redis. A pipeline or a transaction counts as one request. The connection checks done by the pool are not
+    /// counted.
+    fn round_trips(&self, _operation: &'static str, _count: u32) {}
+
+    /// Called when a received message was already received more times than `RsmqOptions::max_global_redeliveries`,
+    /// whatever the settings of its queue. `rc` is its receive count including this receive. The message is delivered
+    /// as usual, so this is the place to log it, count it or remember it to move it or delete it later.
+    fn redelivery_limit_exceeded(&self, _qname: &str, _message_id: &str, _rc: u64) {}
+}
+
+/// Counts the messages sent, received and deleted, for example to export them as Prometheus counters without reading
+/// the queue attributes all the time. Set it in `RsmqOptions::metrics`. Every method has an empty default
+/// implementation and they are called inline once the operation succeeded, so they should be fast.
+///
+/// ```rust,ignore
+/// #[derive(Debug, Default)]
+/// struct SentCounter(AtomicU64);
+///
+/// impl RsmqMetrics for SentCounter {
+///     fn on_send(&self, _qname: &str) {
+///         self.0.fetch_add(1, Ordering::Relaxed);
+///     }
+/// }
+/// ```
+pub trait RsmqMetrics: Debug + Send + Sync {
+    /// Called for each message sent by the send methods, including each message of "send_messages"
+    fn on_send(&self, _qname: &str) {}
+
+    /// Called for each receive, by every receive and pop method. `found` is false when the queue had no message to
+    /// deliver. The batch methods ("receive_messages", "pop_message_batch") call it once per received message, or once
+    /// with `found` false when nothing was received, and "receive_multi" once per received message with the queue of
+    /// the message
+    fn on_receive(&self, _qname: &str, _found: bool) {}
+
+    /// Called for each message that leaves the queue: by every delete method (`deleted` is false when the message
+    /// didn't exist), the pop methods, the purges ("purge_queue", "purge_dlq") once per purged message, and the
+    /// methods moving messages to another queue ("move_message", "retry_message", "promote_matured_retries",
+    /// "sweep_stale_to_dlq", "replay_from_dlq") once per moved message, with the queue they left. The messages moved
+    /// to the dead-letter queue by the receives (see `RsmqQueueConfig::max_receives`) are not reported
+    fn on_delete(&self, _qname: &str, _deleted: bool) {}
+}
+
+/// Connection used for a single operation. It counts the requests sent to Redis and reports them to the observer
+/// when dropped.
+pub(crate) struct ObservedConnection<C: ConnectionLike = MultiplexedConnection> {
+    connection: C,
+    operation: &'static str,
+    round_trips: u32,
+    observer: Option<Arc<dyn RsmqObserver>>,
+}
+
+impl<C: ConnectionLike> ObservedConnection<C> {
+    pub(crate) fn new(
+        connection: C,
+        operation: &'static str,
+        observer: Option<Arc<dyn RsmqObserver>>,
+    ) -> ObservedConnection<C> {
+        ObservedConnection {
+            connection,
+            operation,
+            round_trips: 0,
+            observer,
+        }
+    }
+}
+
+impl<C: ConnectionLike> ConnectionLike for ObservedConnection<C> {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        self.round_trips += 1;
+        self.connection.req_packed_command(cmd)
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        self.round_trips += 1;
+        self.connection.req_packed_commands(cmd, offset, count)
+    }
+
+    fn get_db(&self) -> i64 {
+        self.connection.get_db()
+    }
+}
+
+impl<C: ConnectionLike> Drop for ObservedConnection<C> {
+    fn drop(&mut self) {
+        if let Some(observer) = &self.observer {
+            observer.round_trips(self.operation, self.round_trips);
+        }
+    }
+}
@@ -1,43 +1,121 @@
-use crate::functions::{CachedScript, RsmqFunctions};
+use crate::attributes_cache::AttributesCache;
+use crate::functions::{CachedScript, RsmqFunctions, TracedOperation};
+use crate::master_connection;
+use crate::observer::ObservedConnection;
 use crate::r#trait::RsmqConnection;
-use crate::types::RedisBytes;
-use crate::types::{RsmqMessage, RsmqOptions, RsmqQueueAttributes};
-use crate::RsmqResult;
+use crate::types::{ConnectionTarget, RedisBytes};
+use crate::types::{
+    MaxSize, RsmqAuditRecord, RsmqBatchMode, RsmqConfigSummary, RsmqConnectionInfo, RsmqMessage,
+    RsmqMessageMeta, RsmqOptions, RsmqQueueAttributes, RsmqQueueConfig,
+};
+use crate::{RsmqDecodeError, RsmqResult};
 use async_trait::async_trait;
 use core::convert::TryFrom;
-use redis::RedisError;
-use std::marker::PhantomData;
+use redis::aio::{ConnectionLike, MultiplexedConnection};
+use redis::{Cmd, ErrorKind, Pipeline, RedisError, RedisFuture, RedisResult, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 
 #[derive(Clone, Debug)]
 pub struct RedisConnectionManager {
-    client: redis::Client,
+    target: ConnectionTarget,
+    attributes_cache: Option<AttributesCache>,
+    close_read_only_connections: bool,
 }
 
 impl RedisConnectionManager {
     pub fn from_client(client: redis::Client) -> Result<RedisConnectionManager, RedisError> {
-        Ok(RedisConnectionManager { client })
+        Ok(RedisConnectionManager {
+            target: ConnectionTarget::Client(client),
+            attributes_cache: None,
+            close_read_only_connections: false,
+        })
+    }
+}
+
+/// Connection kept by the pool. It remembers if Redis answered with a READONLY error, which means that it is
+/// connected to a replica. The clones share that flag.
+#[derive(Clone)]
+pub struct PooledConnection {
+    connection: MultiplexedConnection,
+    read_only: Arc<AtomicBool>,
+}
+
+impl PooledConnection {
+    fn new(connection: MultiplexedConnection) -> PooledConnection {
+        PooledConnection {
+            connection,
+            read_only: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// True if Redis answered a request of this connection with a READONLY error
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed)
+    }
+
+    fn flag_read_only<T>(&self, result: &RedisResult<T>) {
+        if let Err(error) = result {
+            if error.kind() == ErrorKind::ReadOnly {
+                self.read_only.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl ConnectionLike for PooledConnection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        Box::pin(async move {
+            let result = self.connection.req_packed_command(cmd).await;
+            self.flag_read_only(&result);
+            result
+        })
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        Box::pin(async move {
+            let result = self
+                .connection
+                .req_packed_commands(cmd, offset, count)
+                .await;
+            self.flag_read_only(&result);
+            result
+        })
+    }
+
+    fn get_db(&self) -> i64 {
+        self.connection.get_db()
     }
 }
 
 #[async_trait]
 impl bb8::ManageConnection for RedisConnectionManager {
-    type Connection = redis::aio::MultiplexedConnection;
+    type Connection = PooledConnection;
     type Error = RedisError;
 
+    // With sentinels the master is looked up again for every connection, so the ones opened after a failover reach
+    // the new master
     async fn connect(&self) -> Result<Self::Connection, Self::Error> {
-        self.client.get_multiplexed_async_connection().await
+        let connection =
+            master_connection::open(&self.target, self.attributes_cache.as_ref()).await?;
+
+        Ok(PooledConnection::new(connection))
     }
 
-    async fn is_valid(
-        &self,
-        conn: &mut redis::aio::MultiplexedConnection,
-    ) -> Result<(), Self::Error> {
+    async fn is_valid(&self, conn: &mut PooledConnection) -> Result<(), Self::Error> {
         redis::cmd("PING").query_async(conn).await
     }
 
-    fn has_broken(&self, _: &mut Self::Connection) -> bool {
-        false
+    // A connection that got a READONLY error still works, but it is connected to a replica. It is closed, so the new
+    // ones can reach the new master after a failover
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        self.close_read_only_connections && conn.is_read_only()
     }
 }
 
@@ -45,43 +123,44 @@ impl bb8::ManageConnection for RedisConnectionManager {
 pub struct PoolOptions {
     pub max_size: Option<u32>,
     pub min_idle: Option<u32>,
+    /// Closes the connections that get a READONLY error (`RsmqError::ReadOnlyReplica`) instead of putting them back
+    /// in the pool, so after a failover the next operations open connections to the new master
+    pub close_read_only_connections: bool,
 }
 
 pub struct PooledRsmq {
     pool: bb8::Pool<RedisConnectionManager>,
-    functions: RsmqFunctions<redis::aio::MultiplexedConnection>,
+    functions: RsmqFunctions<ObservedConnection<PooledConnection>>,
     scripts: CachedScript,
+    target: Option<ConnectionTarget>,
 }
 
 impl Clone for PooledRsmq {
     fn clone(&self) -> Self {
         PooledRsmq {
             pool: self.pool.clone(),
-            functions: RsmqFunctions {
-                ns: self.functions.ns.clone(),
-                realtime: self.functions.realtime,
-                conn: PhantomData,
-            },
+            functions: self.functions.clone(),
             scripts: self.scripts.clone(),
+            target: self.target.clone(),
         }
     }
 }
 
 impl PooledRsmq {
     pub async fn new(options: RsmqOptions, pool_options: PoolOptions) -> RsmqResult<PooledRsmq> {
-        let conn_info = redis::ConnectionInfo {
-            addr: redis::ConnectionAddr::Tcp(options.host, options.port),
-            redis: redis::RedisConnectionInfo {
-                db: options.db.into(),
-                username: options.username,
-                password: options.password,
-                protocol: options.protocol,
-            },
-        };
+        let target = options.connection_target()?;
+        let client = target.client().await?;
 
-        let client = redis::Client::open(conn_info)?;
+        let mut functions = RsmqFunctions::<ObservedConnection<PooledConnection>>::new(&options);
 
-        let manager = RedisConnectionManager::from_client(client)?;
+        functions.attributes_cache = options.client_side_caching.then(AttributesCache::default);
+        functions.connection_info = RsmqConnectionInfo::from_redis(client.get_connection_info());
+
+        let manager = RedisConnectionManager {
+            target: target.clone(),
+            attributes_cache: functions.attributes_cache.clone(),
+            close_read_only_connections: pool_options.close_read_only_connections,
+        };
         let builder = bb8::Pool::builder();
 
         let mut builder = if let Some(value) = pool_options.max_size {
@@ -94,15 +173,15 @@ impl PooledRsmq {
 
         let pool = builder.build(manager).await?;
 
-        let mut conn = pool.get().await?;
-
-        let functions = RsmqFunctions::<redis::aio::MultiplexedConnection> {
-            ns: options.ns.clone(),
-            realtime: options.realtime,
-            conn: PhantomData,
-        };
+        let conn = pool.get().await?;
 
-        let scripts = functions.load_scripts(&mut conn).await?;
+        let scripts = functions
+            .load_scripts(&mut ObservedConnection::new(
+                conn.clone(),
+                "load_scripts",
+                functions.observer.clone(),
+            ))
+            .await?;
 
         drop(conn);
 
@@ -110,36 +189,84 @@ impl PooledRsmq {
             pool,
             functions,
             scripts,
+            target: Some(target),
         })
     }
 
+    /// Uses a pool created outside. Its connections are closed after a READONLY error only if the pool was created
+    /// by "new", as `PoolOptions::close_read_only_connections` is a setting of the connection manager
     pub async fn new_with_pool(
         pool: bb8::Pool<RedisConnectionManager>,
         realtime: bool,
         ns: Option<&str>,
     ) -> RsmqResult<PooledRsmq> {
-        let mut conn = pool.get().await?;
+        let conn = pool.get().await?;
 
-        let functions = RsmqFunctions::<redis::aio::MultiplexedConnection> {
-            ns: ns.unwrap_or("rsmq").to_string(),
-            realtime,
-            conn: PhantomData,
-        };
+        let mut functions =
+            RsmqFunctions::<ObservedConnection<PooledConnection>>::new(&RsmqOptions {
+                realtime,
+                ns: ns.unwrap_or("rsmq").to_string(),
+                ..Default::default()
+            });
+
+        functions.connection_info.db = conn.get_db();
 
-        let scripts = functions.load_scripts(&mut conn).await?;
+        let scripts = functions
+            .load_scripts(&mut ObservedConnection::new(
+                conn.clone(),
+                "load_scripts",
+                functions.observer.clone(),
+            ))
+            .await?;
 
         drop(conn);
 
         Ok(PooledRsmq {
             pool,
-            functions: RsmqFunctions {
-                ns: ns.unwrap_or("rsmq").to_string(),
-                realtime,
-                conn: PhantomData,
-            },
+            functions,
             scripts,
+            target: None,
         })
     }
+
+    /// Subscribes to the realtime channel of the queue (see "realtime_channel") with a new connection, outside of the
+    /// pool, and returns what each send publishes: the number of messages of the queue, or the id of the sent message
+    /// with `RsmqRealtimePayload::MessageId`. The queue needs `RsmqOptions::realtime`, or nothing is published. Fails
+    /// with `RsmqError::NoClient` if this instance was created with "new_with_pool". With sentinels, the connection
+    /// goes to the current master.
+    #[cfg(feature = "stream")]
+    pub async fn subscribe_new_messages(
+        &self,
+        qname: &str,
+    ) -> RsmqResult<impl futures_util::Stream<Item = crate::RsmqNewMessage>> {
+        let client = match &self.target {
+            Some(target) => Some(target.client().await?),
+            None => None,
+        };
+
+        crate::stream::new_messages(
+            client.as_ref(),
+            self.functions.realtime_channel(qname),
+            self.functions.realtime_payload,
+        )
+        .await
+    }
+
+    /// Takes a connection from the pool for a single operation. The connection returned first has to be kept until
+    /// the operation ends so it doesn't go back to the pool
+    async fn observed(
+        &self,
+        operation: &'static str,
+    ) -> RsmqResult<(
+        bb8::PooledConnection<'_, RedisConnectionManager>,
+        ObservedConnection<PooledConnection>,
+    )> {
+        let pooled = self.pool.get().await?;
+        let conn =
+            ObservedConnection::new(pooled.clone(), operation, self.functions.observer.clone());
+
+        Ok((pooled, conn))
+    }
 }
 
 #[async_trait::async_trait]
@@ -150,10 +277,49 @@ impl RsmqConnection for PooledRsmq {
         message_id: &str,
         hidden: Duration,
     ) -> RsmqResult<()> {
-        let mut conn = self.pool.get().await?;
+        let (_pooled, mut conn) = self.observed("change_message_visibility").await?;
 
         self.functions
             .change_message_visibility(&mut conn, qname, message_id, hidden, &self.scripts)
+            .traced("change_message_visibility", qname, Some(message_id))
+            .await
+    }
+
+    async fn clone_queue_config(&mut self, src: &str, dest: &str) -> RsmqResult<()> {
+        let (_pooled, mut conn) = self.observed("clone_queue_config").await?;
+
+        self.functions
+            .clone_queue_config(&mut conn, src, dest)
+            .traced("clone_queue_config", src, None)
+            .await
+    }
+
+    fn config_summary(&self) -> RsmqConfigSummary {
+        self.functions.config_summary()
+    }
+
+    fn connection_info(&self) -> RsmqConnectionInfo {
+        self.target
+            .as_ref()
+            .and_then(ConnectionTarget::connection_info)
+            .unwrap_or_else(|| self.functions.connection_info.clone())
+    }
+
+    async fn count_group(&mut self, qname: &str, group_id: &str) -> RsmqResult<u64> {
+        let (_pooled, mut conn) = self.observed("count_group").await?;
+
+        self.functions
+            .count_group(&mut conn, qname, group_id, &self.scripts)
+            .traced("count_group", qname, None)
+            .await
+    }
+
+    async fn count_visible_messages(&mut self, qname: &str) -> RsmqResult<u64> {
+        let (_pooled, mut conn) = self.observed("count_visible_messages").await?;
+
+        self.functions
+            .count_visible_messages(&mut conn, qname)
+            .traced("count_visible_messages", qname, None)
             .await
     }
 
@@ -162,70 +328,824 @@ impl RsmqConnection for PooledRsmq {
         qname: &str,
         hidden: Option<Duration>,
         delay: Option<Duration>,
-        maxsize: Option<i32>,
+        maxsize: Option<MaxSize>,
     ) -> RsmqResult<()> {
-        let mut conn = self.pool.get().await?;
+        let (_pooled, mut conn) = self.observed("create_queue").await?;
 
         self.functions
             .create_queue(&mut conn, qname, hidden, delay, maxsize)
+            .traced("create_queue", qname, None)
+            .await
+    }
+
+    async fn create_queue_if_not_exists(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+        delay: Option<Duration>,
+        maxsize: Option<MaxSize>,
+    ) -> RsmqResult<bool> {
+        let (_pooled, mut conn) = self.observed("create_queue_if_not_exists").await?;
+
+        self.functions
+            .create_queue_if_not_exists(&mut conn, qname, hidden, delay, maxsize)
+            .traced("create_queue_if_not_exists", qname, None)
+            .await
+    }
+
+    async fn create_queue_with_config(
+        &mut self,
+        qname: &str,
+        config: &RsmqQueueConfig,
+    ) -> RsmqResult<()> {
+        let (_pooled, mut conn) = self.observed("create_queue_with_config").await?;
+
+        self.functions
+            .create_queue_with_config(&mut conn, qname, config)
+            .traced("create_queue_with_config", qname, None)
+            .await
+    }
+
+    async fn delete_by_handle(&mut self, qname: &str, handle: &str) -> RsmqResult<bool> {
+        let (_pooled, mut conn) = self.observed("delete_by_handle").await?;
+
+        self.functions
+            .delete_by_handle(&mut conn, qname, handle, &self.scripts)
+            .traced("delete_by_handle", qname, None)
             .await
     }
 
     async fn delete_message(&mut self, qname: &str, id: &str) -> RsmqResult<bool> {
-        let mut conn = self.pool.get().await?;
+        let (_pooled, mut conn) = self.observed("delete_message").await?;
+
+        self.functions
+            .delete_message(&mut conn, qname, id, &self.scripts)
+            .await
+    }
+    async fn delete_message_if_unchanged(
+        &mut self,
+        qname: &str,
+        id: &str,
+        expected: &[u8],
+    ) -> RsmqResult<bool> {
+        let (_pooled, mut conn) = self.observed("delete_message_if_unchanged").await?;
+
+        self.functions
+            .delete_message_if_unchanged(&mut conn, qname, id, expected, &self.scripts)
+            .traced("delete_message_if_unchanged", qname, Some(id))
+            .await
+    }
 
-        self.functions.delete_message(&mut conn, qname, id).await
+    async fn delete_message_strict(&mut self, qname: &str, id: &str) -> RsmqResult<bool> {
+        let (_pooled, mut conn) = self.observed("delete_message_strict").await?;
+
+        self.functions
+            .delete_message_strict(&mut conn, qname, id, &self.scripts)
+            .traced("delete_message_strict", qname, Some(id))
+            .await
+    }
+
+    async fn delete_messages(&mut self, qname: &str, ids: &[&str]) -> RsmqResult<Vec<bool>> {
+        let (_pooled, mut conn) = self.observed("delete_messages").await?;
+
+        self.functions
+            .delete_messages(&mut conn, qname, ids, &self.scripts)
+            .await
     }
+
     async fn delete_queue(&mut self, qname: &str) -> RsmqResult<()> {
-        let mut conn = self.pool.get().await?;
+        let (_pooled, mut conn) = self.observed("delete_queue").await?;
+
+        self.functions
+            .delete_queue(&mut conn, qname)
+            .traced("delete_queue", qname, None)
+            .await
+    }
+    async fn delete_queue_if_empty(&mut self, qname: &str) -> RsmqResult<bool> {
+        let (_pooled, mut conn) = self.observed("delete_queue_if_empty").await?;
+
+        self.functions
+            .delete_queue_if_empty(&mut conn, qname, &self.scripts)
+            .traced("delete_queue_if_empty", qname, None)
+            .await
+    }
+
+    async fn delete_queue_returning_attributes(
+        &mut self,
+        qname: &str,
+    ) -> RsmqResult<RsmqQueueAttributes> {
+        let (_pooled, mut conn) = self.observed("delete_queue_returning_attributes").await?;
+
+        self.functions
+            .delete_queue_returning_attributes(&mut conn, qname)
+            .traced("delete_queue_returning_attributes", qname, None)
+            .await
+    }
+
+    async fn eval_script(
+        &mut self,
+        script: &str,
+        qnames: &[&str],
+        args: &[&str],
+    ) -> RsmqResult<redis::Value> {
+        let (_pooled, mut conn) = self.observed("eval_script").await?;
+
+        self.functions
+            .eval_script(&mut conn, script, qnames, args)
+            .traced("eval_script", "", None)
+            .await
+    }
+
+    async fn for_each_message<E, F>(
+        &mut self,
+        qname: &str,
+        include_hidden: bool,
+        f: F,
+    ) -> RsmqResult<bool>
+    where
+        E: TryFrom<RedisBytes, Error: RsmqDecodeError>,
+        F: FnMut(RsmqMessage<E>) + Send,
+    {
+        let (_pooled, mut conn) = self.observed("for_each_message").await?;
+
+        self.functions
+            .for_each_message(&mut conn, qname, include_hidden, f)
+            .traced("for_each_message", qname, None)
+            .await
+    }
+
+    async fn get_max_depth(&mut self, qname: &str) -> RsmqResult<u64> {
+        let (_pooled, mut conn) = self.observed("get_max_depth").await?;
+
+        self.functions
+            .get_max_depth(&mut conn, qname)
+            .traced("get_max_depth", qname, None)
+            .await
+    }
+
+    async fn get_message<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        message_id: &str,
+    ) -> RsmqResult<Option<RsmqMessage<E>>> {
+        let (_pooled, mut conn) = self.observed("get_message").await?;
 
-        self.functions.delete_queue(&mut conn, qname).await
+        self.functions
+            .get_message(&mut conn, qname, message_id)
+            .traced("get_message", qname, Some(message_id))
+            .await
     }
+
     async fn get_queue_attributes(&mut self, qname: &str) -> RsmqResult<RsmqQueueAttributes> {
-        let mut conn = self.pool.get().await?;
+        let (_pooled, mut conn) = self.observed("get_queue_attributes").await?;
+
+        self.functions
+            .get_queue_attributes(&mut conn, qname)
+            .traced("get_queue_attributes", qname, None)
+            .await
+    }
+
+    async fn get_queue_attributes_checked(
+        &mut self,
+        qname: &str,
+    ) -> RsmqResult<RsmqQueueAttributes> {
+        let (_pooled, mut conn) = self.observed("get_queue_attributes_checked").await?;
+
+        self.functions
+            .get_queue_attributes_checked(&mut conn, qname)
+            .traced("get_queue_attributes_checked", qname, None)
+            .await
+    }
+
+    async fn get_queue_attributes_many(
+        &mut self,
+        qnames: &[&str],
+    ) -> RsmqResult<Vec<(String, RsmqResult<RsmqQueueAttributes>)>> {
+        let (_pooled, mut conn) = self.observed("get_queue_attributes_many").await?;
+
+        self.functions
+            .get_queue_attributes_many(&mut conn, qnames)
+            .traced("get_queue_attributes_many", "", None)
+            .await
+    }
+
+    async fn list_active_queues(&mut self) -> RsmqResult<Vec<String>> {
+        let (_pooled, mut conn) = self.observed("list_active_queues").await?;
 
-        self.functions.get_queue_attributes(&mut conn, qname).await
+        self.functions
+            .list_active_queues(&mut conn)
+            .traced("list_active_queues", "", None)
+            .await
     }
 
     async fn list_queues(&mut self) -> RsmqResult<Vec<String>> {
-        let mut conn = self.pool.get().await?;
+        let (_pooled, mut conn) = self.observed("list_queues").await?;
+
+        self.functions
+            .list_queues(&mut conn)
+            .traced("list_queues", "", None)
+            .await
+    }
+
+    async fn list_queues_with_attributes(
+        &mut self,
+    ) -> RsmqResult<Vec<(String, RsmqQueueAttributes)>> {
+        let (_pooled, mut conn) = self.observed("list_queues_with_attributes").await?;
+
+        self.functions
+            .list_queues_with_attributes(&mut conn)
+            .traced("list_queues_with_attributes", "", None)
+            .await
+    }
+
+    async fn message_time_remaining(
+        &mut self,
+        qname: &str,
+        id: &str,
+    ) -> RsmqResult<Option<Duration>> {
+        let (_pooled, mut conn) = self.observed("message_time_remaining").await?;
+
+        self.functions
+            .message_time_remaining(&mut conn, qname, id)
+            .traced("message_time_remaining", qname, Some(id))
+            .await
+    }
+
+    async fn move_message(&mut self, from: &str, to: &str, message_id: &str) -> RsmqResult<bool> {
+        let (_pooled, mut conn) = self.observed("move_message").await?;
+
+        self.functions
+            .move_message(&mut conn, from, to, message_id, &self.scripts)
+            .traced("move_message", from, Some(message_id))
+            .await
+    }
+
+    async fn peek_message<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+    ) -> RsmqResult<Option<RsmqMessage<E>>> {
+        let (_pooled, mut conn) = self.observed("peek_message").await?;
+
+        self.functions
+            .peek_message::<E>(&mut conn, qname, &self.scripts)
+            .traced("peek_message", qname, None)
+            .await
+    }
 
-        self.functions.list_queues(&mut conn).await
+    async fn peek_message_meta(&mut self, qname: &str) -> RsmqResult<Option<RsmqMessageMeta>> {
+        let (_pooled, mut conn) = self.observed("peek_message_meta").await?;
+
+        self.functions
+            .peek_message_meta(&mut conn, qname, &self.scripts)
+            .traced("peek_message_meta", qname, None)
+            .await
     }
 
-    async fn pop_message<E: TryFrom<RedisBytes, Error = Vec<u8>>>(
+    async fn pop_message<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
         &mut self,
         qname: &str,
     ) -> RsmqResult<Option<RsmqMessage<E>>> {
-        let mut conn = self.pool.get().await?;
+        let (_pooled, mut conn) = self.observed("pop_message").await?;
 
         self.functions
             .pop_message::<E>(&mut conn, qname, &self.scripts)
+            .traced("pop_message", qname, None)
+            .await
+    }
+
+    async fn pop_message_batch<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        count: usize,
+    ) -> RsmqResult<Vec<RsmqMessage<E>>> {
+        let (_pooled, mut conn) = self.observed("pop_message_batch").await?;
+
+        self.functions
+            .pop_message_batch::<E>(&mut conn, qname, count, &self.scripts)
+            .traced("pop_message_batch", qname, None)
+            .await
+    }
+
+    async fn promote_matured_retries(&mut self, qname: &str) -> RsmqResult<u64> {
+        let (_pooled, mut conn) = self.observed("promote_matured_retries").await?;
+
+        self.functions
+            .promote_matured_retries(&mut conn, qname, &self.scripts)
+            .traced("promote_matured_retries", qname, None)
+            .await
+    }
+
+    async fn purge_dlq(&mut self, qname: &str) -> RsmqResult<u64> {
+        let (_pooled, mut conn) = self.observed("purge_dlq").await?;
+
+        self.functions
+            .purge_dlq(&mut conn, qname, &self.scripts)
+            .traced("purge_dlq", qname, None)
+            .await
+    }
+
+    async fn purge_queue(&mut self, qname: &str) -> RsmqResult<u64> {
+        let (_pooled, mut conn) = self.observed("purge_queue").await?;
+
+        self.functions
+            .purge_queue(&mut conn, qname, &self.scripts)
+            .traced("purge_queue", qname, None)
+            .await
+    }
+
+    async fn queue_exists(&mut self, qname: &str) -> RsmqResult<bool> {
+        let (_pooled, mut conn) = self.observed("queue_exists").await?;
+
+        self.functions
+            .queue_exists(&mut conn, qname)
+            .traced("queue_exists", qname, None)
+            .await
+    }
+
+    async fn read_audit(&mut self, qname: &str, since: u64) -> RsmqResult<Vec<RsmqAuditRecord>> {
+        let (_pooled, mut conn) = self.observed("read_audit").await?;
+
+        self.functions
+            .read_audit(&mut conn, qname, since)
+            .traced("read_audit", qname, None)
+            .await
+    }
+
+    fn realtime_channel(&self, qname: &str) -> String {
+        self.functions.realtime_channel(qname)
+    }
+
+    async fn receive_from_dlq<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<RsmqMessage<E>>> {
+        let (_pooled, mut conn) = self.observed("receive_from_dlq").await?;
+
+        self.functions
+            .receive_from_dlq::<E>(&mut conn, qname, hidden, &self.scripts)
+            .traced("receive_from_dlq", qname, None)
             .await
     }
 
-    async fn receive_message<E: TryFrom<RedisBytes, Error = Vec<u8>>>(
+    async fn receive_message<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
         &mut self,
         qname: &str,
         hidden: Option<Duration>,
     ) -> RsmqResult<Option<RsmqMessage<E>>> {
-        let mut conn = self.pool.get().await?;
+        let (_pooled, mut conn) = self.observed("receive_message").await?;
 
         self.functions
             .receive_message::<E>(&mut conn, qname, hidden, &self.scripts)
             .await
     }
 
+    async fn receive_message_blocking<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+        timeout: Duration,
+    ) -> RsmqResult<Option<RsmqMessage<E>>> {
+        let (_pooled, mut conn) = self.observed("receive_message_blocking").await?;
+
+        self.functions
+            .receive_message_blocking::<E>(&mut conn, qname, hidden, timeout, &self.scripts)
+            .traced("receive_message_blocking", qname, None)
+            .await
+    }
+
+    async fn receive_message_ensuring<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        config: &RsmqQueueConfig,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<RsmqMessage<E>>> {
+        let (_pooled, mut conn) = self.observed("receive_message_ensuring").await?;
+
+        self.functions
+            .receive_message_ensuring::<E>(&mut conn, qname, config, hidden, &self.scripts)
+            .traced("receive_message_ensuring", qname, None)
+            .await
+    }
+
+    #[cfg(feature = "serde")]
+    async fn receive_message_json<M: serde::de::DeserializeOwned>(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<RsmqMessage<M>>> {
+        let (_pooled, mut conn) = self.observed("receive_message_json").await?;
+
+        self.functions
+            .receive_message_json::<M>(&mut conn, qname, hidden, &self.scripts)
+            .traced("receive_message_json", qname, None)
+            .await
+    }
+
+    #[cfg(feature = "otel")]
+    async fn receive_message_traced<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<(RsmqMessage<E>, opentelemetry::Context)>> {
+        let (_pooled, mut conn) = self.observed("receive_message_traced").await?;
+
+        self.functions
+            .receive_message_traced::<E>(&mut conn, qname, hidden, &self.scripts)
+            .traced("receive_message_traced", qname, None)
+            .await
+    }
+
+    async fn receive_message_where<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        attribute: &str,
+        value: &str,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<RsmqMessage<E>>> {
+        let (_pooled, mut conn) = self.observed("receive_message_where").await?;
+
+        self.functions
+            .receive_message_where::<E>(&mut conn, qname, attribute, value, hidden, &self.scripts)
+            .traced("receive_message_where", qname, None)
+            .await
+    }
+
+    async fn receive_message_with_attributes<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<(Option<RsmqMessage<E>>, RsmqQueueAttributes)> {
+        let (_pooled, mut conn) = self.observed("receive_message_with_attributes").await?;
+
+        self.functions
+            .receive_message_with_attributes::<E>(&mut conn, qname, hidden, &self.scripts)
+            .traced("receive_message_with_attributes", qname, None)
+            .await
+    }
+
+    async fn receive_message_with_reply_to<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<(RsmqMessage<E>, Option<String>)>> {
+        let (_pooled, mut conn) = self.observed("receive_message_with_reply_to").await?;
+
+        self.functions
+            .receive_message_with_reply_to::<E>(&mut conn, qname, hidden, &self.scripts)
+            .traced("receive_message_with_reply_to", qname, None)
+            .await
+    }
+
+    async fn receive_message_with_sequence<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<(RsmqMessage<E>, u64)>> {
+        let (_pooled, mut conn) = self.observed("receive_message_with_sequence").await?;
+
+        self.functions
+            .receive_message_with_sequence::<E>(&mut conn, qname, hidden, &self.scripts)
+            .traced("receive_message_with_sequence", qname, None)
+            .await
+    }
+
+    async fn receive_messages<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+        count: usize,
+    ) -> RsmqResult<Vec<RsmqMessage<E>>> {
+        let (_pooled, mut conn) = self.observed("receive_messages").await?;
+
+        self.functions
+            .receive_messages::<E>(&mut conn, qname, hidden, count, &self.scripts)
+            .await
+    }
+
+    async fn receive_multi<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qnames: &[&str],
+        max_total: usize,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Vec<(String, RsmqMessage<E>)>> {
+        let (_pooled, mut conn) = self.observed("receive_multi").await?;
+
+        self.functions
+            .receive_multi::<E>(&mut conn, qnames, max_total, hidden, &self.scripts)
+            .traced("receive_multi", "", None)
+            .await
+    }
+
+    async fn record_external_send(&mut self, qname: &str, count: u64) -> RsmqResult<()> {
+        let (_pooled, mut conn) = self.observed("record_external_send").await?;
+
+        self.functions
+            .record_external_send(&mut conn, qname, count)
+            .traced("record_external_send", qname, None)
+            .await
+    }
+
+    async fn rename_queue(&mut self, from: &str, to: &str) -> RsmqResult<()> {
+        let (_pooled, mut conn) = self.observed("rename_queue").await?;
+
+        self.functions
+            .rename_queue(&mut conn, from, to, &self.scripts)
+            .traced("rename_queue", from, None)
+            .await
+    }
+
+    async fn replay_from_dlq(&mut self, qname: &str, id: &str) -> RsmqResult<bool> {
+        let (_pooled, mut conn) = self.observed("replay_from_dlq").await?;
+
+        self.functions
+            .replay_from_dlq(&mut conn, qname, id, &self.scripts)
+            .traced("replay_from_dlq", qname, Some(id))
+            .await
+    }
+
+    async fn reply<E: Into<RedisBytes> + Send>(
+        &mut self,
+        reply_to: &str,
+        payload: E,
+    ) -> RsmqResult<String> {
+        let (_pooled, mut conn) = self.observed("reply").await?;
+
+        self.functions
+            .reply(&mut conn, reply_to, payload, &self.scripts)
+            .traced("reply", reply_to, None)
+            .await
+    }
+
+    async fn requeue_message(&mut self, qname: &str, message_id: &str) -> RsmqResult<bool> {
+        let (_pooled, mut conn) = self.observed("requeue_message").await?;
+
+        self.functions
+            .requeue_message(&mut conn, qname, message_id, &self.scripts)
+            .traced("requeue_message", qname, Some(message_id))
+            .await
+    }
+
+    async fn requeue_by_handle(&mut self, qname: &str, handle: &str) -> RsmqResult<bool> {
+        let (_pooled, mut conn) = self.observed("requeue_by_handle").await?;
+
+        self.functions
+            .requeue_by_handle(&mut conn, qname, handle, &self.scripts)
+            .traced("requeue_by_handle", qname, None)
+            .await
+    }
+
+    async fn reset_max_depth(&mut self, qname: &str) -> RsmqResult<u64> {
+        let (_pooled, mut conn) = self.observed("reset_max_depth").await?;
+
+        self.functions
+            .reset_max_depth(&mut conn, qname)
+            .traced("reset_max_depth", qname, None)
+            .await
+    }
+
+    async fn retry_message(
+        &mut self,
+        qname: &str,
+        id: &str,
+        attempt: u32,
+        base: Duration,
+    ) -> RsmqResult<bool> {
+        let (_pooled, mut conn) = self.observed("retry_message").await?;
+
+        self.functions
+            .retry_message(&mut conn, qname, id, attempt, base, &self.scripts)
+            .traced("retry_message", qname, Some(id))
+            .await
+    }
+
     async fn send_message<E: Into<RedisBytes> + Send>(
         &mut self,
         qname: &str,
         message: E,
         delay: Option<Duration>,
     ) -> RsmqResult<String> {
-        let mut conn = self.pool.get().await?;
+        let (_pooled, mut conn) = self.observed("send_message").await?;
+
+        self.functions
+            .send_message(&mut conn, qname, message, delay, &self.scripts)
+            .await
+    }
+
+    async fn send_message_deduplicated<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        deduplication_id: &str,
+        message: E,
+        delay: Option<Duration>,
+    ) -> RsmqResult<String> {
+        let (_pooled, mut conn) = self.observed("send_message_deduplicated").await?;
 
         self.functions
-            .send_message(&mut conn, qname, message, delay)
+            .send_message_deduplicated(
+                &mut conn,
+                qname,
+                deduplication_id,
+                message,
+                delay,
+                &self.scripts,
+            )
+            .traced("send_message_deduplicated", qname, None)
+            .await
+    }
+
+    async fn send_message_grouped<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        group_id: &str,
+        message: E,
+        delay: Option<Duration>,
+    ) -> RsmqResult<String> {
+        let (_pooled, mut conn) = self.observed("send_message_grouped").await?;
+
+        self.functions
+            .send_message_grouped(&mut conn, qname, group_id, message, delay, &self.scripts)
+            .traced("send_message_grouped", qname, None)
+            .await
+    }
+
+    async fn send_message_if_below<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        message: E,
+        delay: Option<Duration>,
+        max_depth: u64,
+    ) -> RsmqResult<Option<String>> {
+        let (_pooled, mut conn) = self.observed("send_message_if_below").await?;
+
+        self.functions
+            .send_message_if_below(&mut conn, qname, message, delay, max_depth, &self.scripts)
+            .traced("send_message_if_below", qname, None)
+            .await
+    }
+
+    #[cfg(feature = "serde")]
+    async fn send_message_json<M: serde::Serialize + Sync>(
+        &mut self,
+        qname: &str,
+        message: &M,
+        delay: Option<Duration>,
+    ) -> RsmqResult<String> {
+        let (_pooled, mut conn) = self.observed("send_message_json").await?;
+
+        self.functions
+            .send_message_json(&mut conn, qname, message, delay, &self.scripts)
+            .traced("send_message_json", qname, None)
+            .await
+    }
+
+    async fn send_message_ordered<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        key: &str,
+        message: E,
+        delay: Option<Duration>,
+    ) -> RsmqResult<String> {
+        let (_pooled, mut conn) = self.observed("send_message_ordered").await?;
+
+        self.functions
+            .send_message_ordered(&mut conn, qname, key, message, delay, &self.scripts)
+            .traced("send_message_ordered", qname, None)
+            .await
+    }
+
+    async fn send_message_ref(
+        &mut self,
+        qname: &str,
+        message: &[u8],
+        delay: Option<Duration>,
+    ) -> RsmqResult<String> {
+        let (_pooled, mut conn) = self.observed("send_message_ref").await?;
+
+        self.functions
+            .send_message_ref(&mut conn, qname, message, delay, &self.scripts)
+            .traced("send_message_ref", qname, None)
+            .await
+    }
+
+    #[cfg(feature = "otel")]
+    async fn send_message_traced<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        message: E,
+        delay: Option<Duration>,
+    ) -> RsmqResult<String> {
+        let (_pooled, mut conn) = self.observed("send_message_traced").await?;
+
+        self.functions
+            .send_message_traced(&mut conn, qname, message, delay, &self.scripts)
+            .traced("send_message_traced", qname, None)
+            .await
+    }
+
+    async fn send_message_with_attributes<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        message: E,
+        delay: Option<Duration>,
+        attributes: &[(&str, &str)],
+    ) -> RsmqResult<String> {
+        let (_pooled, mut conn) = self.observed("send_message_with_attributes").await?;
+
+        self.functions
+            .send_message_with_attributes(
+                &mut conn,
+                qname,
+                message,
+                delay,
+                attributes,
+                &self.scripts,
+            )
+            .traced("send_message_with_attributes", qname, None)
+            .await
+    }
+
+    async fn send_message_with_deadline<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        message: E,
+        delay: Option<Duration>,
+        lifetime: Duration,
+    ) -> RsmqResult<String> {
+        let (_pooled, mut conn) = self.observed("send_message_with_deadline").await?;
+
+        self.functions
+            .send_message_with_deadline(&mut conn, qname, message, delay, lifetime, &self.scripts)
+            .traced("send_message_with_deadline", qname, None)
+            .await
+    }
+
+    async fn send_message_with_reply_to<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        message: E,
+        delay: Option<Duration>,
+        reply_to: &str,
+    ) -> RsmqResult<String> {
+        let (_pooled, mut conn) = self.observed("send_message_with_reply_to").await?;
+
+        self.functions
+            .send_message_with_reply_to(&mut conn, qname, message, delay, reply_to, &self.scripts)
+            .traced("send_message_with_reply_to", qname, None)
+            .await
+    }
+
+    async fn send_message_with_score<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        message: E,
+        score: u64,
+    ) -> RsmqResult<String> {
+        let (_pooled, mut conn) = self.observed("send_message_with_score").await?;
+
+        self.functions
+            .send_message_with_score(&mut conn, qname, message, score, &self.scripts)
+            .traced("send_message_with_score", qname, None)
+            .await
+    }
+
+    async fn send_messages<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        messages: Vec<(E, Option<Duration>)>,
+    ) -> RsmqResult<Vec<String>> {
+        let (_pooled, mut conn) = self.observed("send_messages").await?;
+
+        self.functions
+            .send_messages(&mut conn, qname, messages, &self.scripts)
+            .await
+    }
+
+    async fn send_messages_with_mode<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        messages: Vec<(E, Option<Duration>)>,
+        mode: RsmqBatchMode,
+    ) -> RsmqResult<Vec<RsmqResult<String>>> {
+        let (_pooled, mut conn) = self.observed("send_messages_with_mode").await?;
+
+        self.functions
+            .send_messages_with_mode(&mut conn, qname, messages, mode, &self.scripts)
+            .traced("send_messages_with_mode", qname, None)
+            .await
+    }
+
+    async fn set_queue_attribute_if_absent(
+        &mut self,
+        qname: &str,
+        field: &str,
+        value: &str,
+    ) -> RsmqResult<bool> {
+        let (_pooled, mut conn) = self.observed("set_queue_attribute_if_absent").await?;
+
+        self.functions
+            .set_queue_attribute_if_absent(&mut conn, qname, field, value)
+            .traced("set_queue_attribute_if_absent", qname, None)
             .await
     }
 
@@ -234,12 +1154,41 @@ impl RsmqConnection for PooledRsmq {
         qname: &str,
         hidden: Option<Duration>,
         delay: Option<Duration>,
-        maxsize: Option<i64>,
+        maxsize: Option<MaxSize>,
     ) -> RsmqResult<RsmqQueueAttributes> {
-        let mut conn = self.pool.get().await?;
+        let (_pooled, mut conn) = self.observed("set_queue_attributes").await?;
 
         self.functions
             .set_queue_attributes(&mut conn, qname, hidden, delay, maxsize)
+            .traced("set_queue_attributes", qname, None)
+            .await
+    }
+
+    async fn swap_message_order(
+        &mut self,
+        qname: &str,
+        id_a: &str,
+        id_b: &str,
+    ) -> RsmqResult<bool> {
+        let (_pooled, mut conn) = self.observed("swap_message_order").await?;
+
+        self.functions
+            .swap_message_order(&mut conn, qname, id_a, id_b, &self.scripts)
+            .traced("swap_message_order", qname, None)
+            .await
+    }
+
+    async fn sweep_stale_to_dlq(
+        &mut self,
+        qname: &str,
+        older_than: Duration,
+        dlq: &str,
+    ) -> RsmqResult<u64> {
+        let (_pooled, mut conn) = self.observed("sweep_stale_to_dlq").await?;
+
+        self.functions
+            .sweep_stale_to_dlq(&mut conn, qname, older_than, dlq, &self.scripts)
+            .traced("sweep_stale_to_dlq", qname, None)
             .await
     }
 }
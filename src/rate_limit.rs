@@ -0,0 +1,83 @@
+use std::time::{Duration, Instant};
+
+/// Token bucket limiting how many messages per second a consumer loop receives, whatever the depth of the queue. Up
+/// to `burst` messages can be received at once after an idle period; after that, one message every `1 / rate`
+/// seconds. With the `stream` feature, "message_stream_limited" uses it for you. Otherwise ask it before each receive
+/// and wait the returned duration:
+///
+/// ```rust,ignore
+/// let mut limiter = RateLimiter::new(10.0, 5);
+/// let mut backoff = PollBackoff::default();
+///
+/// loop {
+///     tokio::time::sleep(limiter.acquire()).await;
+///
+///     match rsmq.receive_message::<String>("myqueue", None).await? {
+///         Some(message) => {
+///             backoff.reset();
+///             // process the message
+///         }
+///         None => {
+///             // Nothing was received, so the token is not used
+///             limiter.release();
+///             tokio::time::sleep(backoff.next_interval()).await;
+///         }
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    /// Creates a new limiter allowing `rate` messages per second, with a full bucket of `burst` tokens. A `burst`
+    /// of 0 is treated as 1 and a `rate` that is not a positive number means no limit.
+    pub fn new(rate: f64, burst: u32) -> RateLimiter {
+        let burst = f64::from(burst.max(1));
+
+        RateLimiter {
+            rate,
+            burst,
+            tokens: burst,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Takes a token and returns how long to wait before receiving the next message. Zero if a token was available
+    pub fn acquire(&mut self) -> Duration {
+        if !self.is_limited() {
+            return Duration::ZERO;
+        }
+
+        self.refill();
+
+        self.tokens -= 1.0;
+
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::try_from_secs_f64(-self.tokens / self.rate).unwrap_or(Duration::MAX)
+        }
+    }
+
+    /// Gives back the token taken by the last "acquire". Call it when the receive didn't return any message
+    pub fn release(&mut self) {
+        self.tokens = (self.tokens + 1.0).min(self.burst);
+    }
+
+    fn is_limited(&self) -> bool {
+        self.rate.is_finite() && self.rate > 0.0
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.last_refill = now;
+    }
+}
@@ -3,6 +3,8 @@
 -- KEYS[1]: The Redis key for the sorted set representing the message queue.
 -- KEYS[2]: The message ID whose visibility is to be updated.
 -- KEYS[3]: The new visibility timestamp to be set for the message.
+-- ARGV[1]: Optional. The receive count of the message when it was received. If given, nothing is changed if the
+--          message was received again since then.
 
 -- Retrieve the current score (visibility timestamp) of the message
 local currentScore = redis.call("ZSCORE", KEYS[1], KEYS[2])
@@ -12,6 +14,11 @@ if not currentScore then
     return false
 end
 
+-- Every receive increments the receive count, so a different one means the message was received again
+if ARGV[1] and redis.call("HGET", KEYS[1] .. ":Q", KEYS[2] .. ":rc") ~= ARGV[1] then
+    return false
+end
+
 -- Update the message's visibility timestamp (score) to the new value provided
 redis.call("ZADD", KEYS[1], KEYS[3], KEYS[2])
 
@@ -0,0 +1,24 @@
+-- countGroup.lua
+-- This script counts the messages of a group that are still in the queue. The group set is only cleaned here, so
+-- the ids of the messages deleted since the last count are removed from it.
+-- KEYS[1]: The Redis key for the sorted set representing the message queue.
+-- KEYS[2]: The group id.
+
+local group = KEYS[1] .. ":grp:" .. KEYS[2]
+local count = 0
+
+for _, id in ipairs(redis.call("SMEMBERS", group)) do
+    if redis.call("HEXISTS", KEYS[1] .. ":Q", id) == 1 then
+        count = count + 1
+    else
+        redis.call("SREM", group, id)
+    end
+end
+
+-- The group set is gone once all its messages are deleted, so it doesn't need to be tracked anymore
+if count == 0 then
+    redis.call("SREM", KEYS[1] .. ":groups", KEYS[2])
+end
+
+-- Return the number of messages of the group still in the queue
+return count
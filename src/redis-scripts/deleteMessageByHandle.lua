@@ -0,0 +1,25 @@
+-- deleteMessageByHandle.lua
+-- This script deletes a message only if it wasn't received again since the receive that returned the handle.
+-- KEYS[1]: The Redis key for the sorted set representing the message queue.
+-- KEYS[2]: The ID of the message.
+-- ARGV[1]: The receive count of the message when the handle was returned.
+-- ARGV[2..n]: The per message fields (like the receive count) to delete alongside the body.
+
+local queueHash = KEYS[1] .. ":Q"
+
+-- Every receive increments the receive count, so a different one means the message was received again (or it
+-- doesn't exist anymore)
+local receiveCount = redis.call("HGET", queueHash, KEYS[2] .. ":rc")
+if receiveCount ~= ARGV[1] then
+    return { 0, false }
+end
+
+local deduplicationId = redis.call("HGET", queueHash, KEYS[2] .. ":dd")
+
+redis.call("ZREM", KEYS[1], KEYS[2])
+redis.call("HDEL", queueHash, KEYS[2], unpack(ARGV, 2))
+
+-- Return:
+-- [1] 1 as the message was deleted,
+-- [2] the deduplication id of the message, if any, so it can be released
+return { 1, deduplicationId }
@@ -0,0 +1,24 @@
+-- deleteMessageIfUnchanged.lua
+-- This script deletes a message only if its body is still the expected one.
+-- KEYS[1]: The Redis key for the sorted set representing the message queue.
+-- KEYS[2]: The ID of the message.
+-- ARGV[1]: The expected body of the message.
+-- ARGV[2..n]: The per message fields (like the receive count) to delete alongside the body.
+
+local queueHash = KEYS[1] .. ":Q"
+
+-- If the message doesn't exist or it was changed, don't delete anything
+local body = redis.call("HGET", queueHash, KEYS[2])
+if body ~= ARGV[1] then
+    return { 0, false }
+end
+
+local deduplicationId = redis.call("HGET", queueHash, KEYS[2] .. ":dd")
+
+redis.call("ZREM", KEYS[1], KEYS[2])
+redis.call("HDEL", queueHash, KEYS[2], unpack(ARGV, 2))
+
+-- Return:
+-- [1] 1 as the message was deleted,
+-- [2] the deduplication id of the message, if any, so it can be released
+return { 1, deduplicationId }
@@ -0,0 +1,30 @@
+-- deleteQueueIfEmpty.lua
+-- This script deletes the queue only if it doesn't contain any message.
+-- KEYS[1]: The Redis key for the sorted set representing the message queue.
+-- KEYS[2]: The Redis key for the set containing the queue names of the namespace.
+-- KEYS[3]: The queue name.
+
+-- If the queue doesn't exist, return -1
+if redis.call("EXISTS", KEYS[1] .. ":Q") == 0 then
+    return -1
+end
+
+-- If the queue still has messages, don't delete anything
+if redis.call("ZCARD", KEYS[1]) > 0 then
+    return 0
+end
+
+-- Remove the ordering key lists and group sets left by already deleted messages
+for _, orderingKey in ipairs(redis.call("SMEMBERS", KEYS[1] .. ":ordering")) do
+    redis.call("DEL", KEYS[1] .. ":ordering:" .. orderingKey)
+end
+
+for _, group in ipairs(redis.call("SMEMBERS", KEYS[1] .. ":groups")) do
+    redis.call("DEL", KEYS[1] .. ":grp:" .. group)
+end
+
+redis.call("DEL", KEYS[1] .. ":Q", KEYS[1], KEYS[1] .. ":ordering", KEYS[1] .. ":groups", KEYS[1] .. ":dedup")
+redis.call("SREM", KEYS[2], KEYS[3])
+
+-- Return 1 indicating that the queue has been deleted
+return 1
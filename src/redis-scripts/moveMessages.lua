@@ -0,0 +1,48 @@
+-- moveMessages.lua
+-- This script moves messages from one queue to another, keeping their id, body, receive count, first received
+-- time, trace context, reply queue, deadline and attributes.
+-- KEYS[1]: The Redis key for the sorted set of the queue the messages are taken from.
+-- KEYS[2]: The Redis key for the sorted set of the queue the messages are moved to.
+-- KEYS[3]: The timestamp when the moved messages become visible in the destination queue.
+-- KEYS[4]: Optional. If given, the ids of ARGV are ignored and the messages moved are the ones visible at KEYS[3] that
+--          were sent before KEYS[4] (same unit as the timestamp at the beginning of the message ids).
+-- KEYS[5]: Required with KEYS[4]. The number of random characters at the end of the message ids.
+-- ARGV[1]: "true" to reset the receive count and first received time of the moved messages, as if they were new.
+-- ARGV[2..n]: The ids of the messages to move.
+
+local sourceHash = KEYS[1] .. ":Q"
+local destinationHash = KEYS[2] .. ":Q"
+
+-- If any of the queues doesn't exist, return -1
+if redis.call("EXISTS", sourceHash) == 0 or redis.call("EXISTS", destinationHash) == 0 then
+    return -1
+end
+
+local ids = {}
+for i = 2, #ARGV do
+    table.insert(ids, ARGV[i])
+end
+
+if KEYS[4] then
+    ids = {}
+    for _, id in ipairs(redis.call("ZRANGEBYSCORE", KEYS[1], "-inf", KEYS[3])) do
+        -- The ids are the base 36 send time followed by KEYS[5] random characters
+        local sent = tonumber(string.sub(id, 1, #id - tonumber(KEYS[5])), 36)
+        if sent and sent < tonumber(KEYS[4]) then
+            table.insert(ids, id)
+        end
+    end
+end
+
+local moved = 0
+
+for _, id in ipairs(ids) do
+    -- Messages already deleted (or moved) are ignored
+    if redis.call("HEXISTS", sourceHash, id) == 1 and redis.call("ZSCORE", KEYS[1], id) then
+        moveMessage(KEYS[1], KEYS[2], KEYS[3], id, ARGV[1] == "true")
+        moved = moved + 1
+    end
+end
+
+-- Return the number of messages moved
+return moved
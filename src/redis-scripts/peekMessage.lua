@@ -0,0 +1,53 @@
+-- peekMessage.lua
+-- This script returns the next message that would be received from the queue, without changing it.
+-- KEYS[1]: The Redis key for the sorted set representing the message queue.
+-- KEYS[2]: The current time used for score comparisons.
+-- ARGV[1]: A string "true" or "false" indicating whether to return the message body.
+
+local queueHash = KEYS[1] .. ":Q"
+
+-- Find the next message due to be visible, skipping the ones waiting for an earlier message with the same ordering
+-- key
+local message = nil
+local offset = 0
+while not message do
+    local candidates = redis.call("ZRANGEBYSCORE", KEYS[1], "-inf", KEYS[2], "LIMIT", offset, 100)
+    for _, id in ipairs(candidates) do
+        local key = redis.call("HGET", queueHash, id .. ":ok")
+        if not key or isFirstOfKey(KEYS[1], id, key, true) then
+            message = id
+            break
+        end
+    end
+    if #candidates < 100 then
+        break
+    end
+    offset = offset + 100
+end
+
+-- If no message is found, return a default empty response
+if not message then
+    return { false, "", 0, 0, 0, "", 0 }
+end
+
+local fields = redis.call("HMGET", queueHash, message .. ":rc", message .. ":fr", message .. ":ex")
+local body = ""
+if ARGV[1] == "true" then
+    body = redis.call("HGET", queueHash, message)
+end
+
+-- Flag the message if it was sent with a deadline that already passed
+local expired = 0
+if fields[3] and tonumber(fields[3]) < tonumber(KEYS[2]) then
+    expired = 1
+end
+
+-- Return the response containing:
+-- [1] boolean indicating if a message was found,
+-- [2] message ID,
+-- [3] receive count,
+-- [4] first received timestamp (0 if it was never received),
+-- [5] size of the stored body in bytes,
+-- [6] message body (empty unless requested),
+-- [7] 1 if the deadline of the message passed, 0 otherwise
+return { true, message, tonumber(fields[1]) or 0, tonumber(fields[2]) or 0, redis.call("HSTRLEN", queueHash, message), body, expired }
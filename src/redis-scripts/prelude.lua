@@ -0,0 +1,60 @@
+-- prelude.lua
+-- Functions shared by the scripts. It is loaded in front of each script, after the tables messageFields (suffixes of
+-- the "{id}:{suffix}" queue hash fields of a message) and carriedFields (the ones a message keeps when it is moved to
+-- another queue), which are generated from the constants of functions.rs.
+
+-- Returns the fields of a message in the queue hash: its body followed by every "{id}:{suffix}" field
+local function messageKeys(id)
+    local keys = { id }
+    for _, suffix in ipairs(messageFields) do
+        table.insert(keys, id .. ":" .. suffix)
+    end
+    return keys
+end
+
+-- Messages sent with an ordering key are only delivered when every earlier message with the same key was deleted.
+-- The ids of each key are kept, in send order, in the list {queue}:ordering:{key}. The ids of the messages deleted
+-- without going through the scripts are removed from the front of the list, or only skipped with readOnly
+local function isFirstOfKey(queue, id, orderingKey, readOnly)
+    local list = queue .. ":ordering:" .. orderingKey
+    local index = 0
+    while true do
+        local head = redis.call("LINDEX", list, index)
+        if not head or head == id then
+            return true
+        end
+        if redis.call("HEXISTS", queue .. ":Q", head) == 1 then
+            return false
+        end
+        if readOnly then
+            index = index + 1
+        else
+            redis.call("LPOP", list)
+            if redis.call("LLEN", list) == 0 then
+                redis.call("SREM", queue .. ":ordering", orderingKey)
+            end
+        end
+    end
+end
+
+-- Moves a message from the queue sourceKey to the queue destinationKey, where it is visible at score. It keeps its
+-- id, body and carried fields (without the receive count and first received time with resetReceives). The ordering
+-- key, group and deduplication id belong to the source queue. Their indexes are cleaned lazily once the message is
+-- not in the source queue anymore
+local function moveMessage(sourceKey, destinationKey, score, id, resetReceives)
+    local sourceHash = sourceKey .. ":Q"
+    local destinationHash = destinationKey .. ":Q"
+    redis.call("ZREM", sourceKey, id)
+    redis.call("ZADD", destinationKey, score, id)
+    redis.call("HSET", destinationHash, id, redis.call("HGET", sourceHash, id))
+    for _, suffix in ipairs(carriedFields) do
+        if not resetReceives or (suffix ~= "rc" and suffix ~= "fr") then
+            local value = redis.call("HGET", sourceHash, id .. ":" .. suffix)
+            if value then
+                redis.call("HSET", destinationHash, id .. ":" .. suffix, value)
+            end
+        end
+    end
+    redis.call("HINCRBY", destinationHash, "totalsent", 1)
+    redis.call("HDEL", sourceHash, unpack(messageKeys(id)))
+end
@@ -0,0 +1,39 @@
+-- purgeQueue.lua
+-- This script deletes every message of the queue, keeping the queue with its settings and stats.
+-- KEYS[1]: The Redis key for the sorted set representing the message queue.
+
+local queueHash = KEYS[1] .. ":Q"
+
+-- If the queue doesn't exist, return -1
+if redis.call("EXISTS", queueHash) == 0 then
+    return -1
+end
+
+local purged = redis.call("ZCARD", KEYS[1])
+
+-- Remove the ordering key lists, group sets and deduplication ids of the messages
+for _, orderingKey in ipairs(redis.call("SMEMBERS", KEYS[1] .. ":ordering")) do
+    redis.call("DEL", KEYS[1] .. ":ordering:" .. orderingKey)
+end
+
+for _, group in ipairs(redis.call("SMEMBERS", KEYS[1] .. ":groups")) do
+    redis.call("DEL", KEYS[1] .. ":grp:" .. group)
+end
+
+redis.call("DEL", KEYS[1] .. ":ordering", KEYS[1] .. ":groups", KEYS[1] .. ":dedup", KEYS[1])
+
+-- The message fields are mixed with the queue fields in the hash, so the hash is recreated with only the queue fields
+local queueFields = { "vt", "delay", "maxsize", "created", "modified", "totalrecv", "totalsent", "maxrc", "dlq",
+    "maxdepth" }
+local values = redis.call("HMGET", queueHash, unpack(queueFields))
+
+redis.call("DEL", queueHash)
+
+for index, field in ipairs(queueFields) do
+    if values[index] then
+        redis.call("HSET", queueHash, field, values[index])
+    end
+end
+
+-- Return the number of messages deleted
+return purged
@@ -3,56 +3,159 @@
 -- KEYS[1]: The Redis key for the sorted set representing the message queue.
 -- KEYS[2]: The current time or a specific timestamp used for score comparisons.
 -- KEYS[3]: The new visibility timestamp used to update the message score.
+-- KEYS[4]: The namespace prefix of the queue keys, used to find the dead-letter queue.
+-- KEYS[5]: Optional. Only the messages with this attribute set to KEYS[6] are received.
+-- KEYS[6]: Optional. The value the attribute KEYS[5] must have.
 -- ARGV[1]: A string "true" or "false" indicating whether to delete the message after processing.
+-- ARGV[2]: A string "true" or "false" indicating whether to omit the body of redelivered messages, for a client that
+--          caches them. The body of a first delivery is always returned.
 
--- Find the next message due to be visible based on the current time (KEYS[2])
-local message = redis.call("ZRANGEBYSCORE", KEYS[1], "-inf", KEYS[2], "LIMIT", "0", "1")
-
--- If no message is found, return a default empty response
-if #message == 0 then
-    return { false, "", "", 0, 0 }
-end
+local queueHash = KEYS[1] .. ":Q"
 
 -- Check if the message should be deleted
 local should_delete = ARGV[1] == "true"
 
--- Increment the total received count for the queue
-redis.call("HINCRBY", KEYS[1] .. ":Q", "totalrecv", 1)
+-- Messages already received "maxrc" times are moved to the dead-letter queue ("dlq" field of the queue hash)
+-- instead of being delivered again. Nothing is moved if the dead-letter queue doesn't exist
+local maxReceives = tonumber(redis.call("HGET", queueHash, "maxrc") or "")
+local deadLetterKey = nil
+if maxReceives and not should_delete then
+    local deadLetterQueue = redis.call("HGET", queueHash, "dlq")
+    if deadLetterQueue and redis.call("EXISTS", KEYS[4] .. deadLetterQueue .. ":Q") == 1 then
+        deadLetterKey = KEYS[4] .. deadLetterQueue
+    end
+end
+
+-- The message is visible right away in the dead-letter queue (see moveMessage in prelude.lua)
+local function deadLetter(id)
+    moveMessage(KEYS[1], deadLetterKey, KEYS[2], id, false)
+end
+
+-- Returns the value of an attribute from the "at" field of a message, encoded as {length}:{name}{length}:{value}
+-- for each attribute
+local function attributeValue(id, name)
+    local encoded = redis.call("HGET", queueHash, id .. ":at")
+    if not encoded then
+        return nil
+    end
+    local position = 1
+    while position <= #encoded do
+        local separator = string.find(encoded, ":", position, true)
+        local nameEnd = separator + tonumber(string.sub(encoded, position, separator - 1))
+        local currentName = string.sub(encoded, separator + 1, nameEnd)
+        separator = string.find(encoded, ":", nameEnd + 1, true)
+        local valueEnd = separator + tonumber(string.sub(encoded, nameEnd + 1, separator - 1))
+        if currentName == name then
+            return string.sub(encoded, separator + 1, valueEnd)
+        end
+        position = valueEnd + 1
+    end
+    return nil
+end
 
--- Get the message body from the hash
-local messageBody = redis.call("HGET", KEYS[1] .. ":Q", message[1])
+local function matchesFilter(id)
+    return not KEYS[5] or attributeValue(id, KEYS[5]) == KEYS[6]
+end
+
+-- Find the next message due to be visible based on the current time (KEYS[2]), skipping the ones
+-- waiting for an earlier message with the same ordering key and the ones that don't match the attribute filter
+local message = nil
+local orderingKey = nil
+local offset = 0
+while not message do
+    local candidates = redis.call("ZRANGEBYSCORE", KEYS[1], "-inf", KEYS[2], "LIMIT", offset, 100)
+    local removed = 0
+    for _, id in ipairs(candidates) do
+        local key = redis.call("HGET", queueHash, id .. ":ok")
+        if not key or isFirstOfKey(KEYS[1], id, key, false) then
+            if deadLetterKey and tonumber(redis.call("HGET", queueHash, id .. ":rc") or "0") >= maxReceives then
+                deadLetter(id)
+                removed = removed + 1
+            elseif matchesFilter(id) then
+                message = id
+                orderingKey = key
+                break
+            end
+        end
+    end
+    if #candidates < 100 then
+        break
+    end
+    -- The messages moved to the dead-letter queue are not in the sorted set anymore
+    offset = offset + 100 - removed
+end
+
+-- If no message is found, return a default empty response
+if not message then
+    return { false, "", "", 0, 0, 0, 0 }
+end
+
+-- Increment the total received count for the queue
+local totalReceived = redis.call("HINCRBY", queueHash, "totalrecv", 1)
 
 -- Increment the receive count for this message
-local receiveCount = redis.call("HINCRBY", KEYS[1] .. ":Q", message[1] .. ":rc", 1)
+local receiveCount = redis.call("HINCRBY", queueHash, message .. ":rc", 1)
+
+-- Get the message body from the hash, unless it is a redelivery and the client reads it from its own cache
+local messageBody = false
+if ARGV[2] ~= "true" or receiveCount == 1 then
+    messageBody = redis.call("HGET", queueHash, message)
+end
 
 -- Prepare the response table with message details
-local response = { true, message[1], messageBody, receiveCount }
+local response = { true, message, messageBody, receiveCount }
 
 -- If the message is received for the first time, set and add the current time to the response
 if receiveCount == 1 then
-    redis.call("HSET", KEYS[1] .. ":Q", message[1] .. ":fr", KEYS[2])
+    redis.call("HSET", queueHash, message .. ":fr", KEYS[2])
     table.insert(response, KEYS[2])
 else
     -- Otherwise, get the first received time and add it to the response
-    local firstReceived = redis.call("HGET", KEYS[1] .. ":Q", message[1] .. ":fr")
+    local firstReceived = redis.call("HGET", queueHash, message .. ":fr")
     table.insert(response, firstReceived)
 end
 
+-- Flag the message if it was sent with a deadline that already passed
+local expiresAt = redis.call("HGET", queueHash, message .. ":ex")
+if expiresAt and tonumber(expiresAt) < tonumber(KEYS[2]) then
+    table.insert(response, 1)
+else
+    table.insert(response, 0)
+end
+
+-- Add the new total received count of the queue, used as delivery sequence number
+table.insert(response, totalReceived)
+
 -- Update or remove the message based on the should_delete flag
 if should_delete then
     -- Remove the message from the sorted set
-    redis.call("ZREM", KEYS[1], message[1])
+    redis.call("ZREM", KEYS[1], message)
+    -- Free the deduplication id if it's still held by this message
+    local dedupId = redis.call("HGET", queueHash, message .. ":dd")
+    if dedupId and redis.call("HGET", KEYS[1] .. ":dedup", dedupId) == message then
+        redis.call("HDEL", KEYS[1] .. ":dedup", dedupId)
+    end
     -- Delete the message details from the hash
-    redis.call("HDEL", KEYS[1] .. ":Q", message[1], message[1] .. ":rc", message[1] .. ":fr")
+    redis.call("HDEL", queueHash, unpack(messageKeys(message)))
+    -- Let the next message with the same ordering key be delivered
+    if orderingKey then
+        local list = KEYS[1] .. ":ordering:" .. orderingKey
+        redis.call("LREM", list, 1, message)
+        if redis.call("LLEN", list) == 0 then
+            redis.call("SREM", KEYS[1] .. ":ordering", orderingKey)
+        end
+    end
 else
     -- Update the message's score to the new visibility timestamp (KEYS[3])
-    redis.call("ZADD", KEYS[1], KEYS[3], message[1])
+    redis.call("ZADD", KEYS[1], KEYS[3], message)
 end
 
 -- Return the response containing:
 -- [1] boolean indicating if a message was found,
 -- [2] message ID,
--- [3] message body,
+-- [3] message body (nil if it was omitted for a redelivery),
 -- [4] receive count,
--- [5] first received timestamp (either current time or previously set time)
+-- [5] first received timestamp (either current time or previously set time),
+-- [6] 1 if the deadline of the message passed, 0 otherwise,
+-- [7] total received count of the queue after this receive
 return response
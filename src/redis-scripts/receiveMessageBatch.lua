@@ -0,0 +1,121 @@
+-- receiveMessageBatch.lua
+-- Same as receiveMessage.lua but it handles up to ARGV[2] messages in a single invocation.
+-- KEYS[1]: The Redis key for the sorted set representing the message queue.
+-- KEYS[2]: The current time or a specific timestamp used for score comparisons.
+-- KEYS[3]: The new visibility timestamp used to update the message score.
+-- KEYS[4]: The namespace prefix of the queue keys, used to find the dead-letter queue.
+-- ARGV[1]: A string "true" or "false" indicating whether to delete the messages after processing.
+-- ARGV[2]: The maximum number of messages to return.
+
+local queueHash = KEYS[1] .. ":Q"
+local count = tonumber(ARGV[2])
+
+-- Check if the messages should be deleted
+local should_delete = ARGV[1] == "true"
+
+-- Same as in receiveMessage.lua. Messages already received "maxrc" times are moved to the dead-letter queue
+-- instead of being delivered again
+local maxReceives = tonumber(redis.call("HGET", queueHash, "maxrc") or "")
+local deadLetterKey = nil
+if maxReceives and not should_delete then
+    local deadLetterQueue = redis.call("HGET", queueHash, "dlq")
+    if deadLetterQueue and redis.call("EXISTS", KEYS[4] .. deadLetterQueue .. ":Q") == 1 then
+        deadLetterKey = KEYS[4] .. deadLetterQueue
+    end
+end
+
+local function deadLetter(id)
+    moveMessage(KEYS[1], deadLetterKey, KEYS[2], id, false)
+end
+
+-- Find the next messages due to be visible based on the current time (KEYS[2]). At most one message
+-- per ordering key is taken on each invocation
+local messages = {}
+local orderingKeys = {}
+local usedKeys = {}
+local offset = 0
+while #messages < count do
+    local candidates = redis.call("ZRANGEBYSCORE", KEYS[1], "-inf", KEYS[2], "LIMIT", offset, 100)
+    local removed = 0
+    for _, id in ipairs(candidates) do
+        local key = redis.call("HGET", queueHash, id .. ":ok")
+        if not key or (not usedKeys[key] and isFirstOfKey(KEYS[1], id, key, false)) then
+            if deadLetterKey and tonumber(redis.call("HGET", queueHash, id .. ":rc") or "0") >= maxReceives then
+                deadLetter(id)
+                removed = removed + 1
+            else
+                if key then
+                    usedKeys[key] = true
+                    orderingKeys[id] = key
+                end
+                table.insert(messages, id)
+            end
+        end
+        if #messages == count then
+            break
+        end
+    end
+    if #candidates < 100 then
+        break
+    end
+    -- The messages moved to the dead-letter queue are not in the sorted set anymore
+    offset = offset + 100 - removed
+end
+
+local response = {}
+
+for _, id in ipairs(messages) do
+    -- Increment the total received count for the queue
+    redis.call("HINCRBY", queueHash, "totalrecv", 1)
+
+    -- Get the message body from the hash
+    local messageBody = redis.call("HGET", queueHash, id)
+
+    -- Increment the receive count for this message
+    local receiveCount = redis.call("HINCRBY", queueHash, id .. ":rc", 1)
+
+    -- Set the first received time if this is the first time, otherwise read it
+    local firstReceived = KEYS[2]
+    if receiveCount == 1 then
+        redis.call("HSET", queueHash, id .. ":fr", KEYS[2])
+    else
+        firstReceived = redis.call("HGET", queueHash, id .. ":fr")
+    end
+
+    -- Flag the message if it was sent with a deadline that already passed
+    local expiresAt = redis.call("HGET", queueHash, id .. ":ex")
+    local expired = 0
+    if expiresAt and tonumber(expiresAt) < tonumber(KEYS[2]) then
+        expired = 1
+    end
+
+    -- Update or remove the message based on the should_delete flag
+    if should_delete then
+        redis.call("ZREM", KEYS[1], id)
+        local dedupId = redis.call("HGET", queueHash, id .. ":dd")
+        if dedupId and redis.call("HGET", KEYS[1] .. ":dedup", dedupId) == id then
+            redis.call("HDEL", KEYS[1] .. ":dedup", dedupId)
+        end
+        redis.call("HDEL", queueHash, unpack(messageKeys(id)))
+        local orderingKey = orderingKeys[id]
+        if orderingKey then
+            local list = KEYS[1] .. ":ordering:" .. orderingKey
+            redis.call("LREM", list, 1, id)
+            if redis.call("LLEN", list) == 0 then
+                redis.call("SREM", KEYS[1] .. ":ordering", orderingKey)
+            end
+        end
+    else
+        redis.call("ZADD", KEYS[1], KEYS[3], id)
+    end
+
+    table.insert(response, { id, messageBody, receiveCount, firstReceived, expired })
+end
+
+-- Return a list where each element contains:
+-- [1] message ID,
+-- [2] message body,
+-- [3] receive count,
+-- [4] first received timestamp,
+-- [5] 1 if the deadline of the message passed, 0 otherwise
+return response
@@ -0,0 +1,119 @@
+-- receiveMessageMulti.lua
+-- Receives up to ARGV[2] messages from several queues, taking one message from each queue per round, and updates
+-- their visibility timeout like receiveMessage.lua.
+-- KEYS[1..n]: The Redis keys for the sorted sets of the queues.
+-- ARGV[1]: The current time used for score comparisons.
+-- ARGV[2]: The maximum number of messages to return.
+-- ARGV[3]: The hidden time, or an empty string to use the "vt" setting of each queue.
+-- ARGV[4]: The namespace prefix of the queue keys, used to find the dead-letter queues.
+-- ARGV[5]: A string "true" or "false" indicating whether the queues that don't exist are skipped, as if they were empty.
+
+local now = tonumber(ARGV[1])
+local maxTotal = tonumber(ARGV[2])
+
+-- Finds the next deliverable message of the queue that wasn't already taken in this invocation. Messages already
+-- received "maxReceives" times are moved to the dead-letter queue of the queue, if it has one, instead
+local function findMessage(queue, taken, maxReceives, deadLetterKey)
+    local offset = 0
+    while true do
+        local candidates = redis.call("ZRANGEBYSCORE", queue, "-inf", now, "LIMIT", offset, 100)
+        local removed = 0
+        for _, id in ipairs(candidates) do
+            if not taken[id] then
+                local key = redis.call("HGET", queue .. ":Q", id .. ":ok")
+                if not key or isFirstOfKey(queue, id, key, false) then
+                    if deadLetterKey and tonumber(redis.call("HGET", queue .. ":Q", id .. ":rc") or "0") >= maxReceives then
+                        moveMessage(queue, deadLetterKey, ARGV[1], id, false)
+                        removed = removed + 1
+                    else
+                        return id
+                    end
+                end
+            end
+        end
+        if #candidates < 100 then
+            return nil
+        end
+        -- The messages moved to the dead-letter queue are not in the sorted set anymore
+        offset = offset + 100 - removed
+    end
+end
+
+local hidden = {}
+local taken = {}
+local exhausted = {}
+local maxReceives = {}
+local deadLetterKeys = {}
+local remaining = #KEYS
+
+-- If any of the queues doesn't exist, return its position, unless the missing queues are skipped
+for i, queue in ipairs(KEYS) do
+    if redis.call("EXISTS", queue .. ":Q") == 0 then
+        if ARGV[5] ~= "true" then
+            return { i, {} }
+        end
+        exhausted[i] = true
+        remaining = remaining - 1
+    end
+end
+
+for i, queue in ipairs(KEYS) do
+    hidden[i] = tonumber(ARGV[3]) or tonumber(redis.call("HGET", queue .. ":Q", "vt") or "")
+    taken[i] = {}
+
+    -- Same as in receiveMessage.lua. Nothing is moved if the dead-letter queue doesn't exist
+    maxReceives[i] = tonumber(redis.call("HGET", queue .. ":Q", "maxrc") or "")
+    if maxReceives[i] then
+        local deadLetterQueue = redis.call("HGET", queue .. ":Q", "dlq")
+        if deadLetterQueue and redis.call("EXISTS", ARGV[4] .. deadLetterQueue .. ":Q") == 1 then
+            deadLetterKeys[i] = ARGV[4] .. deadLetterQueue
+        end
+    end
+end
+
+local response = {}
+
+while #response < maxTotal and remaining > 0 do
+    for i, queue in ipairs(KEYS) do
+        if not exhausted[i] and #response < maxTotal then
+            local id = findMessage(queue, taken[i], maxReceives[i], deadLetterKeys[i])
+
+            if not id then
+                exhausted[i] = true
+                remaining = remaining - 1
+            else
+                taken[i][id] = true
+
+                redis.call("HINCRBY", queue .. ":Q", "totalrecv", 1)
+                local messageBody = redis.call("HGET", queue .. ":Q", id)
+                local receiveCount = redis.call("HINCRBY", queue .. ":Q", id .. ":rc", 1)
+
+                local firstReceived = ARGV[1]
+                if receiveCount == 1 then
+                    redis.call("HSET", queue .. ":Q", id .. ":fr", ARGV[1])
+                else
+                    firstReceived = redis.call("HGET", queue .. ":Q", id .. ":fr")
+                end
+
+                local expiresAt = redis.call("HGET", queue .. ":Q", id .. ":ex")
+                local expired = 0
+                if expiresAt and tonumber(expiresAt) < now then
+                    expired = 1
+                end
+
+                redis.call("ZADD", queue, now + hidden[i], id)
+
+                table.insert(response, { i, id, messageBody, receiveCount, firstReceived, expired })
+            end
+        end
+    end
+end
+
+-- Return 0 (all the queues exist or were skipped) and a list where each element contains:
+-- [1] position of the queue in KEYS,
+-- [2] message ID,
+-- [3] message body,
+-- [4] receive count,
+-- [5] first received timestamp,
+-- [6] 1 if the deadline of the message passed, 0 otherwise
+return { 0, response }
@@ -0,0 +1,11 @@
+-- releaseDeduplication.lua
+-- This script frees a deduplication id once its message was deleted, unless another message already reserved it.
+-- KEYS[1]: The Redis key for the sorted set representing the message queue.
+-- KEYS[2]: The deduplication id.
+-- KEYS[3]: The ID of the deleted message.
+
+if redis.call("HGET", KEYS[1] .. ":dedup", KEYS[2]) == KEYS[3] then
+    redis.call("HDEL", KEYS[1] .. ":dedup", KEYS[2])
+end
+
+return 1
@@ -0,0 +1,47 @@
+-- renameQueue.lua
+-- This script renames a queue, keeping its messages and stats.
+-- KEYS[1]: The Redis key for the sorted set of the queue to rename.
+-- KEYS[2]: The Redis key for the sorted set with the new name.
+-- KEYS[3]: The Redis key for the set containing the queue names of the namespace.
+-- KEYS[4]: The current queue name.
+-- KEYS[5]: The new queue name.
+
+-- If the queue doesn't exist, return -1
+if redis.call("EXISTS", KEYS[1] .. ":Q") == 0 then
+    return -1
+end
+
+-- If there is already a queue with the new name, return 0
+if redis.call("EXISTS", KEYS[2] .. ":Q") == 1 then
+    return 0
+end
+
+-- Rename a key only if it exists (the sorted set doesn't exist when the queue is empty)
+local function renameIfExists(from, to)
+    if redis.call("EXISTS", from) == 1 then
+        redis.call("RENAME", from, to)
+    end
+end
+
+-- Rename the ordering key lists and the group sets
+for _, orderingKey in ipairs(redis.call("SMEMBERS", KEYS[1] .. ":ordering")) do
+    renameIfExists(KEYS[1] .. ":ordering:" .. orderingKey, KEYS[2] .. ":ordering:" .. orderingKey)
+end
+
+for _, group in ipairs(redis.call("SMEMBERS", KEYS[1] .. ":groups")) do
+    renameIfExists(KEYS[1] .. ":grp:" .. group, KEYS[2] .. ":grp:" .. group)
+end
+
+renameIfExists(KEYS[1] .. ":ordering", KEYS[2] .. ":ordering")
+renameIfExists(KEYS[1] .. ":groups", KEYS[2] .. ":groups")
+renameIfExists(KEYS[1] .. ":dedup", KEYS[2] .. ":dedup")
+renameIfExists(KEYS[1] .. ":audit", KEYS[2] .. ":audit")
+renameIfExists(KEYS[1], KEYS[2])
+redis.call("RENAME", KEYS[1] .. ":Q", KEYS[2] .. ":Q")
+
+-- Update the queue names of the namespace
+redis.call("SREM", KEYS[3], KEYS[4])
+redis.call("SADD", KEYS[3], KEYS[5])
+
+-- Return 1 indicating that the queue has been renamed
+return 1
@@ -0,0 +1,53 @@
+-- sendMessage.lua
+-- This script stores a new message with its extra fields. The deduplication check is done in the same script, so
+-- two senders can't both get the same deduplication id.
+-- KEYS[1]: The Redis key for the sorted set representing the message queue.
+-- ARGV[1]: The ID of the message.
+-- ARGV[2]: The timestamp when the message becomes visible.
+-- ARGV[3]: The message body.
+-- ARGV[4]: The deduplication id, or an empty string.
+-- ARGV[5]: The ordering key, or an empty string.
+-- ARGV[6]: The group, or an empty string.
+-- ARGV[7]: The maximum number of messages of the queue, or an empty string. Nothing is sent if the queue is full.
+-- ARGV[8...]: Pairs of field suffix and value, stored as "{id}:{suffix}" in the queue hash.
+
+local queueHash = KEYS[1] .. ":Q"
+local id = ARGV[1]
+
+-- If the queue is already at (or over) the maximum, nothing is sent
+if ARGV[7] ~= "" and redis.call("ZCARD", KEYS[1]) >= tonumber(ARGV[7]) then
+    return -2
+end
+
+if ARGV[4] ~= "" then
+    local current = redis.call("HGET", KEYS[1] .. ":dedup", ARGV[4])
+
+    -- If the message holding the deduplication id is still in the queue, the new one is a duplicate
+    if current and redis.call("HEXISTS", queueHash, current) == 1 then
+        return -1
+    end
+
+    redis.call("HSET", KEYS[1] .. ":dedup", ARGV[4], id)
+end
+
+redis.call("ZADD", KEYS[1], ARGV[2], id)
+redis.call("HSET", queueHash, id, ARGV[3])
+redis.call("HINCRBY", queueHash, "totalsent", 1)
+
+for i = 8, #ARGV, 2 do
+    redis.call("HSET", queueHash, id .. ":" .. ARGV[i], ARGV[i + 1])
+end
+
+-- The ordering key and the group are also indexed by their value
+if ARGV[5] ~= "" then
+    redis.call("RPUSH", KEYS[1] .. ":ordering:" .. ARGV[5], id)
+    redis.call("SADD", KEYS[1] .. ":ordering", ARGV[5])
+end
+
+if ARGV[6] ~= "" then
+    redis.call("SADD", KEYS[1] .. ":grp:" .. ARGV[6], id)
+    redis.call("SADD", KEYS[1] .. ":groups", ARGV[6])
+end
+
+-- Return the number of messages of the queue, including the new one
+return redis.call("ZCARD", KEYS[1])
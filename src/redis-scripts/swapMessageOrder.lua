@@ -0,0 +1,31 @@
+-- swapMessageOrder.lua
+-- This script swaps the delivery timestamps (scores) of two messages so they are delivered in each other's order.
+-- KEYS[1]: The Redis key for the sorted set representing the message queue.
+-- KEYS[2]: The ID of the first message.
+-- KEYS[3]: The ID of the second message.
+-- KEYS[4]: The current time used to know if a message is being processed by a client.
+
+local scores = {}
+
+for i = 2, 3 do
+    local score = redis.call("ZSCORE", KEYS[1], KEYS[i])
+
+    -- If the message doesn't exist anymore, don't swap anything
+    if not score then
+        return false
+    end
+
+    -- If the message was received and is still hidden, a client is processing it, so don't swap anything
+    local receiveCount = redis.call("HGET", KEYS[1] .. ":Q", KEYS[i] .. ":rc")
+    if receiveCount and tonumber(score) > tonumber(KEYS[4]) then
+        return false
+    end
+
+    scores[i] = score
+end
+
+-- Give each message the score of the other one
+redis.call("ZADD", KEYS[1], scores[3], KEYS[2], scores[2], KEYS[3])
+
+-- Return true indicating that the order has been successfully swapped
+return true
@@ -0,0 +1,15 @@
+-- updateMaxDepth.lua
+-- This script stores the number of messages of the queue as its maximum depth if it's bigger than the stored one.
+-- KEYS[1]: The Redis key for the sorted set representing the message queue.
+
+local queueHash = KEYS[1] .. ":Q"
+
+local depth = redis.call("ZCARD", KEYS[1])
+local maxDepth = tonumber(redis.call("HGET", queueHash, "maxdepth") or "0")
+
+if depth > maxDepth then
+    redis.call("HSET", queueHash, "maxdepth", depth)
+end
+
+-- Return the current number of messages
+return depth
@@ -0,0 +1,190 @@
+use crate::r#trait::RsmqConnection;
+use crate::types::{RedisBytes, RsmqBatchMode};
+use crate::{RsmqError, RsmqResult};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+enum Command {
+    Send(RedisBytes, oneshot::Sender<RsmqResult<String>>),
+    Flush(oneshot::Sender<()>),
+}
+
+/// Collects the messages pushed one by one and sends them with "send_messages_with_mode" (in best effort mode), so
+/// bursts of messages take one round trip per batch instead of one per message. A batch is sent when it has
+/// `max_messages` messages or `flush_interval` after its first message, whatever happens first.
+///
+/// The batches are sent by a tokio task, so it needs to be created inside a tokio runtime. Dropping the buffer sends
+/// the messages still waiting in the background; use "close" to wait until they are sent.
+///
+/// ```rust,ignore
+/// let buffer = SendBuffer::new(rsmq, "myqueue", 100, Duration::from_millis(50));
+///
+/// let first = buffer.push("first message");
+/// let second = buffer.push("second message");
+///
+/// // Both messages are sent in the same batch
+/// let first_id = first.await?;
+/// let second_id = second.await?;
+///
+/// buffer.close().await;
+/// ```
+#[derive(Debug)]
+pub struct SendBuffer {
+    commands: mpsc::UnboundedSender<Command>,
+    worker: JoinHandle<()>,
+}
+
+impl SendBuffer {
+    /// Starts the task sending the batches to `qname`. `max_messages` is raised to 1 if it is 0 and should not be
+    /// bigger than `RsmqOptions::max_batch_size`, or every batch fails with `RsmqError::BatchTooLarge`.
+    pub fn new<R>(rsmq: R, qname: &str, max_messages: usize, flush_interval: Duration) -> SendBuffer
+    where
+        R: RsmqConnection + Send + 'static,
+    {
+        let (commands, receiver) = mpsc::unbounded_channel();
+
+        let worker = tokio::spawn(run(
+            rsmq,
+            qname.to_string(),
+            max_messages.max(1),
+            flush_interval,
+            receiver,
+        ));
+
+        SendBuffer { commands, worker }
+    }
+
+    /// Adds a message to the current batch. The returned future resolves to the id of the message once its batch is
+    /// sent, or to the error of the message. If the whole batch fails (for example because the connection is lost)
+    /// every message of the batch gets `RsmqError::SendBufferFailed` with the original error.
+    pub fn push<E: Into<RedisBytes>>(&self, message: E) -> PendingSend {
+        let (sender, receiver) = oneshot::channel();
+
+        // If the task is gone the sender is dropped with the command and the receiver reports it
+        let _ = self.commands.send(Command::Send(message.into(), sender));
+
+        PendingSend(receiver)
+    }
+
+    /// Sends the current batch without waiting for it to be full or for the flush interval. Returns when every
+    /// message pushed before the call was sent.
+    pub async fn flush(&self) {
+        let (sender, receiver) = oneshot::channel();
+
+        if self.commands.send(Command::Flush(sender)).is_ok() {
+            let _ = receiver.await;
+        }
+    }
+
+    /// Sends the messages still waiting and stops the task
+    pub async fn close(self) {
+        let SendBuffer { commands, worker } = self;
+
+        drop(commands);
+
+        let _ = worker.await;
+    }
+}
+
+/// Id of a message pushed to a `SendBuffer`. Resolves to `RsmqError::SendBufferClosed` if the task sending the
+/// batches stopped before sending it.
+#[derive(Debug)]
+pub struct PendingSend(oneshot::Receiver<RsmqResult<String>>);
+
+impl Future for PendingSend {
+    type Output = RsmqResult<String>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.0)
+            .poll(cx)
+            .map(|result| result.unwrap_or(Err(RsmqError::SendBufferClosed)))
+    }
+}
+
+async fn run<R: RsmqConnection + Send>(
+    mut rsmq: R,
+    qname: String,
+    max_messages: usize,
+    flush_interval: Duration,
+    mut commands: mpsc::UnboundedReceiver<Command>,
+) {
+    let mut pending = Vec::new();
+    // None when the flush interval is too big to be represented, so only a full batch is sent
+    let mut deadline = None;
+
+    loop {
+        let command = match deadline {
+            Some(deadline) if !pending.is_empty() => {
+                match tokio::time::timeout_at(deadline, commands.recv()).await {
+                    Ok(command) => command,
+                    Err(_) => {
+                        send_pending(&mut rsmq, &qname, &mut pending).await;
+                        continue;
+                    }
+                }
+            }
+            _ => commands.recv().await,
+        };
+
+        match command {
+            Some(Command::Send(message, sender)) => {
+                if pending.is_empty() {
+                    deadline = Instant::now().checked_add(flush_interval);
+                }
+
+                pending.push((message, sender));
+
+                if pending.len() >= max_messages {
+                    send_pending(&mut rsmq, &qname, &mut pending).await;
+                }
+            }
+            Some(Command::Flush(done)) => {
+                send_pending(&mut rsmq, &qname, &mut pending).await;
+                let _ = done.send(());
+            }
+            // Every SendBuffer handle is gone, so nothing else can be pushed
+            None => {
+                send_pending(&mut rsmq, &qname, &mut pending).await;
+                return;
+            }
+        }
+    }
+}
+
+async fn send_pending<R: RsmqConnection + Send>(
+    rsmq: &mut R,
+    qname: &str,
+    pending: &mut Vec<(RedisBytes, oneshot::Sender<RsmqResult<String>>)>,
+) {
+    if pending.is_empty() {
+        return;
+    }
+
+    let (messages, senders): (Vec<_>, Vec<_>) = pending
+        .drain(..)
+        .map(|(message, sender)| ((message, None), sender))
+        .unzip();
+
+    match rsmq
+        .send_messages_with_mode(qname, messages, RsmqBatchMode::BestEffort)
+        .await
+    {
+        Ok(results) => {
+            for (sender, result) in senders.into_iter().zip(results) {
+                let _ = sender.send(result);
+            }
+        }
+        Err(error) => {
+            let error = error.to_string();
+
+            for sender in senders {
+                let _ = sender.send(Err(RsmqError::SendBufferFailed(error.clone())));
+            }
+        }
+    }
+}
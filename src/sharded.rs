@@ -0,0 +1,167 @@
+use crate::r#trait::RsmqConnection;
+use crate::types::{MaxSize, RedisBytes, RsmqMessage};
+use crate::{RsmqDecodeError, RsmqResult};
+use std::convert::TryFrom;
+use std::hash::{BuildHasher, BuildHasherDefault, Hasher};
+use std::time::Duration;
+
+type KeyExtractor = Box<dyn Fn(&[u8]) -> Vec<u8> + Send + Sync>;
+
+/// 64-bit FNV-1a, the default hasher of `ShardedRsmq`. Unlike the hasher of the standard library, its result is
+/// fixed, so the producers choose the same shards whatever their Rust version or platform
+#[derive(Debug, Clone, Copy)]
+pub struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> FnvHasher {
+        FnvHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Splits a logical queue into `shards` queues (`{qname}-0` to `{qname}-{shards - 1}`). Each message is sent to the
+/// shard given by the hash of its key, so the messages with the same key keep their order, and "receive" goes through
+/// the shards in turns.
+///
+/// ```rust,ignore
+/// // The key is everything before the first ':' of the message
+/// let mut sharded = ShardedRsmq::new(rsmq, 4, |message| {
+///     message.split(|byte| *byte == b':').next().unwrap_or_default().to_vec()
+/// });
+///
+/// sharded.create_queue("orders", None, None, None).await?;
+/// sharded.send_message("orders", "customer-1:order-7", None).await?;
+///
+/// if let Some((shard, message)) = sharded.receive::<String>("orders", None).await? {
+///     sharded.inner().delete_message(&shard, &message.id).await?;
+/// }
+/// ```
+pub struct ShardedRsmq<R, S = BuildHasherDefault<FnvHasher>> {
+    rsmq: R,
+    shards: u32,
+    key: KeyExtractor,
+    hasher: S,
+    next: u32,
+}
+
+impl<R: RsmqConnection + Send> ShardedRsmq<R> {
+    /// Creates the wrapper with the default hasher (`FnvHasher`). `key` returns the part of the message used to choose its shard.
+    /// A `shards` of 0 is taken as 1.
+    pub fn new<F>(rsmq: R, shards: u32, key: F) -> ShardedRsmq<R>
+    where
+        F: Fn(&[u8]) -> Vec<u8> + Send + Sync + 'static,
+    {
+        ShardedRsmq::with_hasher(rsmq, shards, key, BuildHasherDefault::default())
+    }
+}
+
+impl<R: RsmqConnection + Send, S: BuildHasher + Send> ShardedRsmq<R, S> {
+    /// Same as "new" but the keys are hashed with `hasher`. All the producers of a queue need to use the same
+    /// hasher, so it must not be randomly seeded (like `std::collections::hash_map::RandomState`).
+    pub fn with_hasher<F>(rsmq: R, shards: u32, key: F, hasher: S) -> ShardedRsmq<R, S>
+    where
+        F: Fn(&[u8]) -> Vec<u8> + Send + Sync + 'static,
+    {
+        ShardedRsmq {
+            rsmq,
+            shards: shards.max(1),
+            key: Box::new(key),
+            hasher,
+            next: 0,
+        }
+    }
+
+    /// The wrapped instance, for the operations that are done on a single shard (like deleting a message)
+    pub fn inner(&mut self) -> &mut R {
+        &mut self.rsmq
+    }
+
+    /// Name of the queue of the shard `shard` of `qname`
+    pub fn shard_name(&self, qname: &str, shard: u32) -> String {
+        format!("{}-{}", qname, shard)
+    }
+
+    /// Shard where a message is sent, given by the hash of its key
+    pub fn shard_of(&self, message: &[u8]) -> u32 {
+        // Only the bytes of the key are hashed. Hashing the `Vec` would add its length as a `usize`, which depends on
+        // the platform
+        let mut hasher = self.hasher.build_hasher();
+        hasher.write(&(self.key)(message));
+
+        (hasher.finish() % u64::from(self.shards)) as u32
+    }
+
+    /// Creates the queue of every shard, with the same settings as "create_queue"
+    pub async fn create_queue(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+        delay: Option<Duration>,
+        maxsize: Option<MaxSize>,
+    ) -> RsmqResult<()> {
+        for shard in 0..self.shards {
+            let shard_name = self.shard_name(qname, shard);
+
+            self.rsmq
+                .create_queue(&shard_name, hidden, delay, maxsize)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Same as "send_message" but the message is sent to the shard of its key
+    pub async fn send_message<E: Into<RedisBytes>>(
+        &mut self,
+        qname: &str,
+        message: E,
+        delay: Option<Duration>,
+    ) -> RsmqResult<String> {
+        let message = message.into().0;
+        let shard_name = self.shard_name(qname, self.shard_of(&message));
+
+        self.rsmq.send_message(&shard_name, message, delay).await
+    }
+
+    /// Receives a message from the shards, in turns: every call starts with the shard after the one of the previous
+    /// call and it tries each shard once. Returns the name of the shard queue with the message, needed to delete it.
+    pub async fn receive<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<(String, RsmqMessage<E>)>> {
+        for _ in 0..self.shards {
+            let shard = self.next;
+            self.next = (self.next + 1) % self.shards;
+
+            let shard_name = self.shard_name(qname, shard);
+
+            if let Some(message) = self.rsmq.receive_message::<E>(&shard_name, hidden).await? {
+                return Ok(Some((shard_name, message)));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+impl<R, S> std::fmt::Debug for ShardedRsmq<R, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("ShardedRsmq")
+            .field("shards", &self.shards)
+            .field("next", &self.next)
+            .finish()
+    }
+}
@@ -0,0 +1,73 @@
+use crate::r#trait::RsmqConnection;
+use crate::types::{RedisBytes, RsmqMessage, RsmqNewMessage, RsmqRealtimePayload};
+use crate::{RateLimiter, RsmqDecodeError, RsmqError, RsmqResult};
+use core::convert::TryFrom;
+use futures_util::future;
+use futures_util::stream::{self, Stream, StreamExt};
+use std::time::Duration;
+
+/// Stream returned by "RsmqConnection::message_stream" and "RsmqConnection::message_stream_limited"
+pub(crate) fn message_stream<'a, R, E>(
+    rsmq: &'a mut R,
+    qname: &'a str,
+    hidden: Option<Duration>,
+    poll_interval: Duration,
+    limiter: Option<RateLimiter>,
+) -> impl Stream<Item = RsmqResult<RsmqMessage<E>>> + 'a
+where
+    R: RsmqConnection + Send,
+    E: TryFrom<RedisBytes, Error: RsmqDecodeError> + 'a,
+{
+    stream::unfold(
+        (rsmq, false, limiter),
+        move |(rsmq, failed, mut limiter)| async move {
+            // After an error, wait before trying again so a Redis outage doesn't become a busy loop
+            if failed {
+                futures_timer::Delay::new(poll_interval).await;
+            }
+
+            loop {
+                if let Some(limiter) = &mut limiter {
+                    let wait = limiter.acquire();
+
+                    if !wait.is_zero() {
+                        futures_timer::Delay::new(wait).await;
+                    }
+                }
+
+                let result = rsmq.receive_message::<E>(qname, hidden).await;
+
+                // Only the received messages use a token
+                if let (Some(limiter), Ok(None) | Err(_)) = (&mut limiter, &result) {
+                    limiter.release();
+                }
+
+                match result {
+                    Ok(Some(message)) => return Some((Ok(message), (rsmq, false, limiter))),
+                    Ok(None) => futures_timer::Delay::new(poll_interval).await,
+                    Err(error) => return Some((Err(error), (rsmq, true, limiter))),
+                }
+            }
+        },
+    )
+}
+
+/// Stream returned by "subscribe_new_messages". The payloads are read as `payload` publishes them, and the ones that
+/// can't be read that way (like a count that is not a number) are skipped
+pub(crate) async fn new_messages(
+    client: Option<&redis::Client>,
+    channel: String,
+    payload: RsmqRealtimePayload,
+) -> RsmqResult<impl Stream<Item = RsmqNewMessage>> {
+    let client = client.ok_or(RsmqError::NoClient)?;
+
+    let mut pubsub = client.get_async_pubsub().await?;
+    pubsub.subscribe(channel).await?;
+
+    Ok(pubsub.into_on_message().filter_map(move |message| {
+        future::ready(match payload {
+            RsmqRealtimePayload::Count => message.get_payload().ok().map(RsmqNewMessage::Count),
+            RsmqRealtimePayload::MessageId => message.get_payload().ok().map(RsmqNewMessage::Id),
+        })
+    }))
+}
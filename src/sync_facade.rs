@@ -1,15 +1,20 @@
-use crate::functions::{CachedScript, RsmqFunctions};
+use crate::attributes_cache::AttributesCache;
+use crate::functions::{CachedScript, RsmqFunctions, TracedOperation};
+use crate::master_connection::MasterConnection;
+use crate::observer::ObservedConnection;
 use crate::r#trait::RsmqConnection;
-use crate::types::{RedisBytes, RsmqMessage, RsmqOptions, RsmqQueueAttributes};
-use crate::{RsmqError, RsmqResult};
+use crate::types::{
+    MaxSize, RedisBytes, RsmqAuditRecord, RsmqBatchMode, RsmqConfigSummary, RsmqConnectionInfo,
+    RsmqMessage, RsmqMessageMeta, RsmqOptions, RsmqQueueAttributes, RsmqQueueConfig,
+};
+use crate::{RsmqDecodeError, RsmqError, RsmqResult};
 use core::convert::TryFrom;
-use core::marker::PhantomData;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::runtime::Runtime;
 
 #[derive(Clone)]
-struct RedisConnection(redis::aio::MultiplexedConnection);
+struct RedisConnection(MasterConnection);
 
 impl std::fmt::Debug for RedisConnection {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -20,40 +25,36 @@ impl std::fmt::Debug for RedisConnection {
 #[derive(Debug, Clone)]
 pub struct RsmqSync {
     connection: RedisConnection,
-    functions: RsmqFunctions<redis::aio::MultiplexedConnection>,
+    functions: RsmqFunctions<ObservedConnection<MasterConnection>>,
     runner: Arc<Runtime>,
     scripts: CachedScript,
 }
 
 impl RsmqSync {
-    /// Creates a new RSMQ instance, including its connection
+    /// Creates a new RSMQ instance, including its connection. After a connection error or a READONLY reply the
+    /// connection is opened again, asking the sentinels for the current master when `RsmqOptions::sentinel` is set
     pub async fn new(options: RsmqOptions) -> RsmqResult<RsmqSync> {
         let runner = tokio::runtime::Builder::new_current_thread()
             .enable_all()
             .build()
             .map_err(|e| RsmqError::TokioStart(e.into()))?;
 
-        let conn_info = redis::ConnectionInfo {
-            addr: redis::ConnectionAddr::Tcp(options.host, options.port),
-            redis: redis::RedisConnectionInfo {
-                db: options.db.into(),
-                username: options.username,
-                password: options.password,
-                protocol: options.protocol,
-            },
-        };
+        let target = options.connection_target()?;
 
-        let client = redis::Client::open(conn_info)?;
+        let mut functions = RsmqFunctions::new(&options);
 
-        let functions = RsmqFunctions {
-            ns: options.ns,
-            realtime: options.realtime,
-            conn: PhantomData,
-        };
+        functions.attributes_cache = options.client_side_caching.then(AttributesCache::default);
 
         let (connection, scripts) = runner.block_on(async {
-            let mut conn = client.get_multiplexed_async_connection().await?;
-            let scripts = functions.load_scripts(&mut conn).await?;
+            let conn =
+                MasterConnection::connect(target, functions.attributes_cache.clone()).await?;
+            let scripts = functions
+                .load_scripts(&mut ObservedConnection::new(
+                    conn.clone(),
+                    "load_scripts",
+                    functions.observer.clone(),
+                ))
+                .await?;
             Result::<_, RsmqError>::Ok((conn, scripts))
         })?;
 
@@ -64,6 +65,15 @@ impl RsmqSync {
             scripts,
         })
     }
+
+    /// Connection for a single operation, reporting its round trips to the observer
+    fn observed(&self, operation: &'static str) -> ObservedConnection<MasterConnection> {
+        ObservedConnection::new(
+            self.connection.0.clone(),
+            operation,
+            self.functions.observer.clone(),
+        )
+    }
 }
 
 #[async_trait::async_trait]
@@ -77,12 +87,57 @@ impl RsmqConnection for RsmqSync {
         self.runner.block_on(async {
             self.functions
                 .change_message_visibility(
-                    &mut self.connection.0,
+                    &mut self.observed("change_message_visibility"),
                     qname,
                     message_id,
                     hidden,
                     &self.scripts,
                 )
+                .traced("change_message_visibility", qname, Some(message_id))
+                .await
+        })
+    }
+
+    async fn clone_queue_config(&mut self, src: &str, dest: &str) -> RsmqResult<()> {
+        self.runner.block_on(async {
+            self.functions
+                .clone_queue_config(&mut self.observed("clone_queue_config"), src, dest)
+                .traced("clone_queue_config", src, None)
+                .await
+        })
+    }
+
+    fn config_summary(&self) -> RsmqConfigSummary {
+        self.functions.config_summary()
+    }
+
+    fn connection_info(&self) -> RsmqConnectionInfo {
+        self.connection
+            .0
+            .target()
+            .connection_info()
+            .unwrap_or_else(|| self.functions.connection_info.clone())
+    }
+
+    async fn count_group(&mut self, qname: &str, group_id: &str) -> RsmqResult<u64> {
+        self.runner.block_on(async {
+            self.functions
+                .count_group(
+                    &mut self.observed("count_group"),
+                    qname,
+                    group_id,
+                    &self.scripts,
+                )
+                .traced("count_group", qname, None)
+                .await
+        })
+    }
+
+    async fn count_visible_messages(&mut self, qname: &str) -> RsmqResult<u64> {
+        self.runner.block_on(async {
+            self.functions
+                .count_visible_messages(&mut self.observed("count_visible_messages"), qname)
+                .traced("count_visible_messages", qname, None)
                 .await
         })
     }
@@ -92,11 +147,70 @@ impl RsmqConnection for RsmqSync {
         qname: &str,
         hidden: Option<Duration>,
         delay: Option<Duration>,
-        maxsize: Option<i32>,
+        maxsize: Option<MaxSize>,
+    ) -> RsmqResult<()> {
+        self.runner.block_on(async {
+            self.functions
+                .create_queue(
+                    &mut self.observed("create_queue"),
+                    qname,
+                    hidden,
+                    delay,
+                    maxsize,
+                )
+                .traced("create_queue", qname, None)
+                .await
+        })
+    }
+
+    async fn create_queue_if_not_exists(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+        delay: Option<Duration>,
+        maxsize: Option<MaxSize>,
+    ) -> RsmqResult<bool> {
+        self.runner.block_on(async {
+            self.functions
+                .create_queue_if_not_exists(
+                    &mut self.observed("create_queue_if_not_exists"),
+                    qname,
+                    hidden,
+                    delay,
+                    maxsize,
+                )
+                .traced("create_queue_if_not_exists", qname, None)
+                .await
+        })
+    }
+
+    async fn create_queue_with_config(
+        &mut self,
+        qname: &str,
+        config: &RsmqQueueConfig,
     ) -> RsmqResult<()> {
         self.runner.block_on(async {
             self.functions
-                .create_queue(&mut self.connection.0, qname, hidden, delay, maxsize)
+                .create_queue_with_config(
+                    &mut self.observed("create_queue_with_config"),
+                    qname,
+                    config,
+                )
+                .traced("create_queue_with_config", qname, None)
+                .await
+        })
+    }
+
+    async fn delete_by_handle(&mut self, qname: &str, handle: &str) -> RsmqResult<bool> {
+        self.runner.block_on(async {
+            self.functions
+                .delete_by_handle(
+                    &mut self.observed("delete_by_handle"),
+                    qname,
+                    handle,
+                    &self.scripts,
+                )
+                .traced("delete_by_handle", qname, None)
                 .await
         })
     }
@@ -104,76 +218,1063 @@ impl RsmqConnection for RsmqSync {
     async fn delete_message(&mut self, qname: &str, id: &str) -> RsmqResult<bool> {
         self.runner.block_on(async {
             self.functions
-                .delete_message(&mut self.connection.0, qname, id)
+                .delete_message(
+                    &mut self.observed("delete_message"),
+                    qname,
+                    id,
+                    &self.scripts,
+                )
+                .await
+        })
+    }
+    async fn delete_message_if_unchanged(
+        &mut self,
+        qname: &str,
+        id: &str,
+        expected: &[u8],
+    ) -> RsmqResult<bool> {
+        self.runner.block_on(async {
+            self.functions
+                .delete_message_if_unchanged(
+                    &mut self.observed("delete_message_if_unchanged"),
+                    qname,
+                    id,
+                    expected,
+                    &self.scripts,
+                )
+                .traced("delete_message_if_unchanged", qname, Some(id))
+                .await
+        })
+    }
+
+    async fn delete_message_strict(&mut self, qname: &str, id: &str) -> RsmqResult<bool> {
+        self.runner.block_on(async {
+            self.functions
+                .delete_message_strict(
+                    &mut self.observed("delete_message_strict"),
+                    qname,
+                    id,
+                    &self.scripts,
+                )
+                .traced("delete_message_strict", qname, Some(id))
+                .await
+        })
+    }
+
+    async fn delete_messages(&mut self, qname: &str, ids: &[&str]) -> RsmqResult<Vec<bool>> {
+        self.runner.block_on(async {
+            self.functions
+                .delete_messages(
+                    &mut self.observed("delete_messages"),
+                    qname,
+                    ids,
+                    &self.scripts,
+                )
                 .await
         })
     }
+
     async fn delete_queue(&mut self, qname: &str) -> RsmqResult<()> {
         self.runner.block_on(async {
             self.functions
-                .delete_queue(&mut self.connection.0, qname)
+                .delete_queue(&mut self.observed("delete_queue"), qname)
+                .traced("delete_queue", qname, None)
+                .await
+        })
+    }
+    async fn delete_queue_if_empty(&mut self, qname: &str) -> RsmqResult<bool> {
+        self.runner.block_on(async {
+            self.functions
+                .delete_queue_if_empty(
+                    &mut self.observed("delete_queue_if_empty"),
+                    qname,
+                    &self.scripts,
+                )
+                .traced("delete_queue_if_empty", qname, None)
+                .await
+        })
+    }
+
+    async fn delete_queue_returning_attributes(
+        &mut self,
+        qname: &str,
+    ) -> RsmqResult<RsmqQueueAttributes> {
+        self.runner.block_on(async {
+            self.functions
+                .delete_queue_returning_attributes(
+                    &mut self.observed("delete_queue_returning_attributes"),
+                    qname,
+                )
+                .traced("delete_queue_returning_attributes", qname, None)
+                .await
+        })
+    }
+
+    async fn eval_script(
+        &mut self,
+        script: &str,
+        qnames: &[&str],
+        args: &[&str],
+    ) -> RsmqResult<redis::Value> {
+        self.runner.block_on(async {
+            self.functions
+                .eval_script(&mut self.observed("eval_script"), script, qnames, args)
+                .traced("eval_script", "", None)
+                .await
+        })
+    }
+
+    async fn for_each_message<E, F>(
+        &mut self,
+        qname: &str,
+        include_hidden: bool,
+        f: F,
+    ) -> RsmqResult<bool>
+    where
+        E: TryFrom<RedisBytes, Error: RsmqDecodeError>,
+        F: FnMut(RsmqMessage<E>) + Send,
+    {
+        self.runner.block_on(async {
+            self.functions
+                .for_each_message(
+                    &mut self.observed("for_each_message"),
+                    qname,
+                    include_hidden,
+                    f,
+                )
+                .traced("for_each_message", qname, None)
+                .await
+        })
+    }
+
+    async fn get_max_depth(&mut self, qname: &str) -> RsmqResult<u64> {
+        self.runner.block_on(async {
+            self.functions
+                .get_max_depth(&mut self.observed("get_max_depth"), qname)
+                .traced("get_max_depth", qname, None)
+                .await
+        })
+    }
+
+    async fn get_message<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        message_id: &str,
+    ) -> RsmqResult<Option<RsmqMessage<E>>> {
+        self.runner.block_on(async {
+            self.functions
+                .get_message(&mut self.observed("get_message"), qname, message_id)
+                .traced("get_message", qname, Some(message_id))
                 .await
         })
     }
+
     async fn get_queue_attributes(&mut self, qname: &str) -> RsmqResult<RsmqQueueAttributes> {
         self.runner.block_on(async {
             self.functions
-                .get_queue_attributes(&mut self.connection.0, qname)
+                .get_queue_attributes(&mut self.observed("get_queue_attributes"), qname)
+                .traced("get_queue_attributes", qname, None)
+                .await
+        })
+    }
+
+    async fn get_queue_attributes_checked(
+        &mut self,
+        qname: &str,
+    ) -> RsmqResult<RsmqQueueAttributes> {
+        self.runner.block_on(async {
+            self.functions
+                .get_queue_attributes_checked(
+                    &mut self.observed("get_queue_attributes_checked"),
+                    qname,
+                )
+                .traced("get_queue_attributes_checked", qname, None)
+                .await
+        })
+    }
+
+    async fn get_queue_attributes_many(
+        &mut self,
+        qnames: &[&str],
+    ) -> RsmqResult<Vec<(String, RsmqResult<RsmqQueueAttributes>)>> {
+        self.runner.block_on(async {
+            self.functions
+                .get_queue_attributes_many(&mut self.observed("get_queue_attributes_many"), qnames)
+                .traced("get_queue_attributes_many", "", None)
+                .await
+        })
+    }
+
+    async fn list_active_queues(&mut self) -> RsmqResult<Vec<String>> {
+        self.runner.block_on(async {
+            self.functions
+                .list_active_queues(&mut self.observed("list_active_queues"))
+                .traced("list_active_queues", "", None)
                 .await
         })
     }
 
     async fn list_queues(&mut self) -> RsmqResult<Vec<String>> {
-        self.runner
-            .block_on(async { self.functions.list_queues(&mut self.connection.0).await })
+        self.runner.block_on(async {
+            self.functions
+                .list_queues(&mut self.observed("list_queues"))
+                .traced("list_queues", "", None)
+                .await
+        })
+    }
+
+    async fn list_queues_with_attributes(
+        &mut self,
+    ) -> RsmqResult<Vec<(String, RsmqQueueAttributes)>> {
+        self.runner.block_on(async {
+            self.functions
+                .list_queues_with_attributes(&mut self.observed("list_queues_with_attributes"))
+                .traced("list_queues_with_attributes", "", None)
+                .await
+        })
+    }
+
+    async fn message_time_remaining(
+        &mut self,
+        qname: &str,
+        id: &str,
+    ) -> RsmqResult<Option<Duration>> {
+        self.runner.block_on(async {
+            self.functions
+                .message_time_remaining(&mut self.observed("message_time_remaining"), qname, id)
+                .traced("message_time_remaining", qname, Some(id))
+                .await
+        })
+    }
+
+    async fn move_message(&mut self, from: &str, to: &str, message_id: &str) -> RsmqResult<bool> {
+        self.runner.block_on(async {
+            self.functions
+                .move_message(
+                    &mut self.observed("move_message"),
+                    from,
+                    to,
+                    message_id,
+                    &self.scripts,
+                )
+                .traced("move_message", from, Some(message_id))
+                .await
+        })
     }
 
-    async fn pop_message<E: TryFrom<RedisBytes, Error = Vec<u8>>>(
+    async fn peek_message<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
         &mut self,
         qname: &str,
     ) -> RsmqResult<Option<RsmqMessage<E>>> {
         self.runner.block_on(async {
             self.functions
-                .pop_message::<E>(&mut self.connection.0, qname, &self.scripts)
+                .peek_message::<E>(&mut self.observed("peek_message"), qname, &self.scripts)
+                .traced("peek_message", qname, None)
+                .await
+        })
+    }
+
+    async fn peek_message_meta(&mut self, qname: &str) -> RsmqResult<Option<RsmqMessageMeta>> {
+        self.runner.block_on(async {
+            self.functions
+                .peek_message_meta(
+                    &mut self.observed("peek_message_meta"),
+                    qname,
+                    &self.scripts,
+                )
+                .traced("peek_message_meta", qname, None)
                 .await
         })
     }
 
-    async fn receive_message<E: TryFrom<RedisBytes, Error = Vec<u8>>>(
+    async fn pop_message<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
         &mut self,
         qname: &str,
-        hidden: Option<Duration>,
     ) -> RsmqResult<Option<RsmqMessage<E>>> {
         self.runner.block_on(async {
             self.functions
-                .receive_message::<E>(&mut self.connection.0, qname, hidden, &self.scripts)
+                .pop_message::<E>(&mut self.observed("pop_message"), qname, &self.scripts)
+                .traced("pop_message", qname, None)
                 .await
         })
     }
 
-    async fn send_message<E: Into<RedisBytes> + Send>(
+    async fn pop_message_batch<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
         &mut self,
         qname: &str,
-        message: E,
-        delay: Option<Duration>,
-    ) -> RsmqResult<String> {
+        count: usize,
+    ) -> RsmqResult<Vec<RsmqMessage<E>>> {
         self.runner.block_on(async {
             self.functions
-                .send_message(&mut self.connection.0, qname, message, delay)
+                .pop_message_batch::<E>(
+                    &mut self.observed("pop_message_batch"),
+                    qname,
+                    count,
+                    &self.scripts,
+                )
+                .traced("pop_message_batch", qname, None)
                 .await
         })
     }
 
-    async fn set_queue_attributes(
+    async fn promote_matured_retries(&mut self, qname: &str) -> RsmqResult<u64> {
+        self.runner.block_on(async {
+            self.functions
+                .promote_matured_retries(
+                    &mut self.observed("promote_matured_retries"),
+                    qname,
+                    &self.scripts,
+                )
+                .traced("promote_matured_retries", qname, None)
+                .await
+        })
+    }
+
+    async fn purge_dlq(&mut self, qname: &str) -> RsmqResult<u64> {
+        self.runner.block_on(async {
+            self.functions
+                .purge_dlq(&mut self.observed("purge_dlq"), qname, &self.scripts)
+                .traced("purge_dlq", qname, None)
+                .await
+        })
+    }
+
+    async fn purge_queue(&mut self, qname: &str) -> RsmqResult<u64> {
+        self.runner.block_on(async {
+            self.functions
+                .purge_queue(&mut self.observed("purge_queue"), qname, &self.scripts)
+                .traced("purge_queue", qname, None)
+                .await
+        })
+    }
+
+    async fn queue_exists(&mut self, qname: &str) -> RsmqResult<bool> {
+        self.runner.block_on(async {
+            self.functions
+                .queue_exists(&mut self.observed("queue_exists"), qname)
+                .traced("queue_exists", qname, None)
+                .await
+        })
+    }
+
+    async fn read_audit(&mut self, qname: &str, since: u64) -> RsmqResult<Vec<RsmqAuditRecord>> {
+        self.runner.block_on(async {
+            self.functions
+                .read_audit(&mut self.observed("read_audit"), qname, since)
+                .traced("read_audit", qname, None)
+                .await
+        })
+    }
+
+    fn realtime_channel(&self, qname: &str) -> String {
+        self.functions.realtime_channel(qname)
+    }
+
+    async fn receive_from_dlq<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
         &mut self,
         qname: &str,
         hidden: Option<Duration>,
-        delay: Option<Duration>,
-        maxsize: Option<i64>,
-    ) -> RsmqResult<RsmqQueueAttributes> {
+    ) -> RsmqResult<Option<RsmqMessage<E>>> {
         self.runner.block_on(async {
             self.functions
-                .set_queue_attributes(&mut self.connection.0, qname, hidden, delay, maxsize)
+                .receive_from_dlq::<E>(
+                    &mut self.observed("receive_from_dlq"),
+                    qname,
+                    hidden,
+                    &self.scripts,
+                )
+                .traced("receive_from_dlq", qname, None)
+                .await
+        })
+    }
+
+    async fn receive_message<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<RsmqMessage<E>>> {
+        self.runner.block_on(async {
+            self.functions
+                .receive_message::<E>(
+                    &mut self.observed("receive_message"),
+                    qname,
+                    hidden,
+                    &self.scripts,
+                )
+                .await
+        })
+    }
+
+    async fn receive_message_blocking<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+        timeout: Duration,
+    ) -> RsmqResult<Option<RsmqMessage<E>>> {
+        self.runner.block_on(async {
+            self.functions
+                .receive_message_blocking::<E>(
+                    &mut self.observed("receive_message_blocking"),
+                    qname,
+                    hidden,
+                    timeout,
+                    &self.scripts,
+                )
+                .traced("receive_message_blocking", qname, None)
+                .await
+        })
+    }
+
+    async fn receive_message_ensuring<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        config: &RsmqQueueConfig,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<RsmqMessage<E>>> {
+        self.runner.block_on(async {
+            self.functions
+                .receive_message_ensuring::<E>(
+                    &mut self.observed("receive_message_ensuring"),
+                    qname,
+                    config,
+                    hidden,
+                    &self.scripts,
+                )
+                .traced("receive_message_ensuring", qname, None)
+                .await
+        })
+    }
+
+    #[cfg(feature = "serde")]
+    async fn receive_message_json<M: serde::de::DeserializeOwned>(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<RsmqMessage<M>>> {
+        self.runner.block_on(async {
+            self.functions
+                .receive_message_json::<M>(
+                    &mut self.observed("receive_message_json"),
+                    qname,
+                    hidden,
+                    &self.scripts,
+                )
+                .traced("receive_message_json", qname, None)
+                .await
+        })
+    }
+
+    #[cfg(feature = "otel")]
+    async fn receive_message_traced<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<(RsmqMessage<E>, opentelemetry::Context)>> {
+        self.runner.block_on(async {
+            self.functions
+                .receive_message_traced::<E>(
+                    &mut self.observed("receive_message_traced"),
+                    qname,
+                    hidden,
+                    &self.scripts,
+                )
+                .traced("receive_message_traced", qname, None)
+                .await
+        })
+    }
+
+    async fn receive_message_where<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        attribute: &str,
+        value: &str,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<RsmqMessage<E>>> {
+        self.runner.block_on(async {
+            self.functions
+                .receive_message_where::<E>(
+                    &mut self.observed("receive_message_where"),
+                    qname,
+                    attribute,
+                    value,
+                    hidden,
+                    &self.scripts,
+                )
+                .traced("receive_message_where", qname, None)
+                .await
+        })
+    }
+
+    async fn receive_message_with_attributes<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<(Option<RsmqMessage<E>>, RsmqQueueAttributes)> {
+        self.runner.block_on(async {
+            self.functions
+                .receive_message_with_attributes::<E>(
+                    &mut self.observed("receive_message_with_attributes"),
+                    qname,
+                    hidden,
+                    &self.scripts,
+                )
+                .traced("receive_message_with_attributes", qname, None)
+                .await
+        })
+    }
+
+    async fn receive_message_with_reply_to<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<(RsmqMessage<E>, Option<String>)>> {
+        self.runner.block_on(async {
+            self.functions
+                .receive_message_with_reply_to::<E>(
+                    &mut self.observed("receive_message_with_reply_to"),
+                    qname,
+                    hidden,
+                    &self.scripts,
+                )
+                .traced("receive_message_with_reply_to", qname, None)
+                .await
+        })
+    }
+
+    async fn receive_message_with_sequence<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<(RsmqMessage<E>, u64)>> {
+        self.runner.block_on(async {
+            self.functions
+                .receive_message_with_sequence::<E>(
+                    &mut self.observed("receive_message_with_sequence"),
+                    qname,
+                    hidden,
+                    &self.scripts,
+                )
+                .traced("receive_message_with_sequence", qname, None)
+                .await
+        })
+    }
+
+    async fn receive_messages<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+        count: usize,
+    ) -> RsmqResult<Vec<RsmqMessage<E>>> {
+        self.runner.block_on(async {
+            self.functions
+                .receive_messages::<E>(
+                    &mut self.observed("receive_messages"),
+                    qname,
+                    hidden,
+                    count,
+                    &self.scripts,
+                )
+                .await
+        })
+    }
+
+    async fn receive_multi<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qnames: &[&str],
+        max_total: usize,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Vec<(String, RsmqMessage<E>)>> {
+        self.runner.block_on(async {
+            self.functions
+                .receive_multi::<E>(
+                    &mut self.observed("receive_multi"),
+                    qnames,
+                    max_total,
+                    hidden,
+                    &self.scripts,
+                )
+                .traced("receive_multi", "", None)
+                .await
+        })
+    }
+
+    async fn record_external_send(&mut self, qname: &str, count: u64) -> RsmqResult<()> {
+        self.runner.block_on(async {
+            self.functions
+                .record_external_send(&mut self.observed("record_external_send"), qname, count)
+                .traced("record_external_send", qname, None)
+                .await
+        })
+    }
+
+    async fn rename_queue(&mut self, from: &str, to: &str) -> RsmqResult<()> {
+        self.runner.block_on(async {
+            self.functions
+                .rename_queue(&mut self.observed("rename_queue"), from, to, &self.scripts)
+                .traced("rename_queue", from, None)
+                .await
+        })
+    }
+
+    async fn replay_from_dlq(&mut self, qname: &str, id: &str) -> RsmqResult<bool> {
+        self.runner.block_on(async {
+            self.functions
+                .replay_from_dlq(
+                    &mut self.observed("replay_from_dlq"),
+                    qname,
+                    id,
+                    &self.scripts,
+                )
+                .traced("replay_from_dlq", qname, Some(id))
+                .await
+        })
+    }
+
+    async fn reply<E: Into<RedisBytes> + Send>(
+        &mut self,
+        reply_to: &str,
+        payload: E,
+    ) -> RsmqResult<String> {
+        self.runner.block_on(async {
+            self.functions
+                .reply(
+                    &mut self.observed("reply"),
+                    reply_to,
+                    payload,
+                    &self.scripts,
+                )
+                .traced("reply", reply_to, None)
+                .await
+        })
+    }
+
+    async fn requeue_message(&mut self, qname: &str, message_id: &str) -> RsmqResult<bool> {
+        self.runner.block_on(async {
+            self.functions
+                .requeue_message(
+                    &mut self.observed("requeue_message"),
+                    qname,
+                    message_id,
+                    &self.scripts,
+                )
+                .traced("requeue_message", qname, Some(message_id))
+                .await
+        })
+    }
+
+    async fn requeue_by_handle(&mut self, qname: &str, handle: &str) -> RsmqResult<bool> {
+        self.runner.block_on(async {
+            self.functions
+                .requeue_by_handle(
+                    &mut self.observed("requeue_by_handle"),
+                    qname,
+                    handle,
+                    &self.scripts,
+                )
+                .traced("requeue_by_handle", qname, None)
+                .await
+        })
+    }
+
+    async fn reset_max_depth(&mut self, qname: &str) -> RsmqResult<u64> {
+        self.runner.block_on(async {
+            self.functions
+                .reset_max_depth(&mut self.observed("reset_max_depth"), qname)
+                .traced("reset_max_depth", qname, None)
+                .await
+        })
+    }
+
+    async fn retry_message(
+        &mut self,
+        qname: &str,
+        id: &str,
+        attempt: u32,
+        base: Duration,
+    ) -> RsmqResult<bool> {
+        self.runner.block_on(async {
+            self.functions
+                .retry_message(
+                    &mut self.observed("retry_message"),
+                    qname,
+                    id,
+                    attempt,
+                    base,
+                    &self.scripts,
+                )
+                .traced("retry_message", qname, Some(id))
+                .await
+        })
+    }
+
+    async fn send_message<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        message: E,
+        delay: Option<Duration>,
+    ) -> RsmqResult<String> {
+        self.runner.block_on(async {
+            self.functions
+                .send_message(
+                    &mut self.observed("send_message"),
+                    qname,
+                    message,
+                    delay,
+                    &self.scripts,
+                )
+                .await
+        })
+    }
+
+    async fn send_message_deduplicated<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        deduplication_id: &str,
+        message: E,
+        delay: Option<Duration>,
+    ) -> RsmqResult<String> {
+        self.runner.block_on(async {
+            self.functions
+                .send_message_deduplicated(
+                    &mut self.observed("send_message_deduplicated"),
+                    qname,
+                    deduplication_id,
+                    message,
+                    delay,
+                    &self.scripts,
+                )
+                .traced("send_message_deduplicated", qname, None)
+                .await
+        })
+    }
+
+    async fn send_message_grouped<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        group_id: &str,
+        message: E,
+        delay: Option<Duration>,
+    ) -> RsmqResult<String> {
+        self.runner.block_on(async {
+            self.functions
+                .send_message_grouped(
+                    &mut self.observed("send_message_grouped"),
+                    qname,
+                    group_id,
+                    message,
+                    delay,
+                    &self.scripts,
+                )
+                .traced("send_message_grouped", qname, None)
+                .await
+        })
+    }
+
+    async fn send_message_if_below<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        message: E,
+        delay: Option<Duration>,
+        max_depth: u64,
+    ) -> RsmqResult<Option<String>> {
+        self.runner.block_on(async {
+            self.functions
+                .send_message_if_below(
+                    &mut self.observed("send_message_if_below"),
+                    qname,
+                    message,
+                    delay,
+                    max_depth,
+                    &self.scripts,
+                )
+                .traced("send_message_if_below", qname, None)
+                .await
+        })
+    }
+
+    #[cfg(feature = "serde")]
+    async fn send_message_json<M: serde::Serialize + Sync>(
+        &mut self,
+        qname: &str,
+        message: &M,
+        delay: Option<Duration>,
+    ) -> RsmqResult<String> {
+        self.runner.block_on(async {
+            self.functions
+                .send_message_json(
+                    &mut self.observed("send_message_json"),
+                    qname,
+                    message,
+                    delay,
+                    &self.scripts,
+                )
+                .traced("send_message_json", qname, None)
+                .await
+        })
+    }
+
+    async fn send_message_ordered<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        key: &str,
+        message: E,
+        delay: Option<Duration>,
+    ) -> RsmqResult<String> {
+        self.runner.block_on(async {
+            self.functions
+                .send_message_ordered(
+                    &mut self.observed("send_message_ordered"),
+                    qname,
+                    key,
+                    message,
+                    delay,
+                    &self.scripts,
+                )
+                .traced("send_message_ordered", qname, None)
+                .await
+        })
+    }
+
+    async fn send_message_ref(
+        &mut self,
+        qname: &str,
+        message: &[u8],
+        delay: Option<Duration>,
+    ) -> RsmqResult<String> {
+        self.runner.block_on(async {
+            self.functions
+                .send_message_ref(
+                    &mut self.observed("send_message_ref"),
+                    qname,
+                    message,
+                    delay,
+                    &self.scripts,
+                )
+                .traced("send_message_ref", qname, None)
+                .await
+        })
+    }
+
+    #[cfg(feature = "otel")]
+    async fn send_message_traced<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        message: E,
+        delay: Option<Duration>,
+    ) -> RsmqResult<String> {
+        self.runner.block_on(async {
+            self.functions
+                .send_message_traced(
+                    &mut self.observed("send_message_traced"),
+                    qname,
+                    message,
+                    delay,
+                    &self.scripts,
+                )
+                .traced("send_message_traced", qname, None)
+                .await
+        })
+    }
+
+    async fn send_message_with_attributes<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        message: E,
+        delay: Option<Duration>,
+        attributes: &[(&str, &str)],
+    ) -> RsmqResult<String> {
+        self.runner.block_on(async {
+            self.functions
+                .send_message_with_attributes(
+                    &mut self.observed("send_message_with_attributes"),
+                    qname,
+                    message,
+                    delay,
+                    attributes,
+                    &self.scripts,
+                )
+                .traced("send_message_with_attributes", qname, None)
+                .await
+        })
+    }
+
+    async fn send_message_with_deadline<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        message: E,
+        delay: Option<Duration>,
+        lifetime: Duration,
+    ) -> RsmqResult<String> {
+        self.runner.block_on(async {
+            self.functions
+                .send_message_with_deadline(
+                    &mut self.observed("send_message_with_deadline"),
+                    qname,
+                    message,
+                    delay,
+                    lifetime,
+                    &self.scripts,
+                )
+                .traced("send_message_with_deadline", qname, None)
+                .await
+        })
+    }
+
+    async fn send_message_with_reply_to<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        message: E,
+        delay: Option<Duration>,
+        reply_to: &str,
+    ) -> RsmqResult<String> {
+        self.runner.block_on(async {
+            self.functions
+                .send_message_with_reply_to(
+                    &mut self.observed("send_message_with_reply_to"),
+                    qname,
+                    message,
+                    delay,
+                    reply_to,
+                    &self.scripts,
+                )
+                .traced("send_message_with_reply_to", qname, None)
+                .await
+        })
+    }
+
+    async fn send_message_with_score<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        message: E,
+        score: u64,
+    ) -> RsmqResult<String> {
+        self.runner.block_on(async {
+            self.functions
+                .send_message_with_score(
+                    &mut self.observed("send_message_with_score"),
+                    qname,
+                    message,
+                    score,
+                    &self.scripts,
+                )
+                .traced("send_message_with_score", qname, None)
+                .await
+        })
+    }
+
+    async fn send_messages<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        messages: Vec<(E, Option<Duration>)>,
+    ) -> RsmqResult<Vec<String>> {
+        self.runner.block_on(async {
+            self.functions
+                .send_messages(
+                    &mut self.observed("send_messages"),
+                    qname,
+                    messages,
+                    &self.scripts,
+                )
+                .await
+        })
+    }
+
+    async fn send_messages_with_mode<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        messages: Vec<(E, Option<Duration>)>,
+        mode: RsmqBatchMode,
+    ) -> RsmqResult<Vec<RsmqResult<String>>> {
+        self.runner.block_on(async {
+            self.functions
+                .send_messages_with_mode(
+                    &mut self.observed("send_messages_with_mode"),
+                    qname,
+                    messages,
+                    mode,
+                    &self.scripts,
+                )
+                .traced("send_messages_with_mode", qname, None)
+                .await
+        })
+    }
+
+    async fn set_queue_attribute_if_absent(
+        &mut self,
+        qname: &str,
+        field: &str,
+        value: &str,
+    ) -> RsmqResult<bool> {
+        self.runner.block_on(async {
+            self.functions
+                .set_queue_attribute_if_absent(
+                    &mut self.observed("set_queue_attribute_if_absent"),
+                    qname,
+                    field,
+                    value,
+                )
+                .traced("set_queue_attribute_if_absent", qname, None)
+                .await
+        })
+    }
+
+    async fn set_queue_attributes(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+        delay: Option<Duration>,
+        maxsize: Option<MaxSize>,
+    ) -> RsmqResult<RsmqQueueAttributes> {
+        self.runner.block_on(async {
+            self.functions
+                .set_queue_attributes(
+                    &mut self.observed("set_queue_attributes"),
+                    qname,
+                    hidden,
+                    delay,
+                    maxsize,
+                )
+                .traced("set_queue_attributes", qname, None)
+                .await
+        })
+    }
+
+    async fn swap_message_order(
+        &mut self,
+        qname: &str,
+        id_a: &str,
+        id_b: &str,
+    ) -> RsmqResult<bool> {
+        self.runner.block_on(async {
+            self.functions
+                .swap_message_order(
+                    &mut self.observed("swap_message_order"),
+                    qname,
+                    id_a,
+                    id_b,
+                    &self.scripts,
+                )
+                .traced("swap_message_order", qname, None)
+                .await
+        })
+    }
+
+    async fn sweep_stale_to_dlq(
+        &mut self,
+        qname: &str,
+        older_than: Duration,
+        dlq: &str,
+    ) -> RsmqResult<u64> {
+        self.runner.block_on(async {
+            self.functions
+                .sweep_stale_to_dlq(
+                    &mut self.observed("sweep_stale_to_dlq"),
+                    qname,
+                    older_than,
+                    dlq,
+                    &self.scripts,
+                )
+                .traced("sweep_stale_to_dlq", qname, None)
                 .await
         })
     }
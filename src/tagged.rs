@@ -0,0 +1,96 @@
+use crate::r#trait::RsmqConnection;
+use crate::types::RsmqMessage;
+use crate::{RsmqError, RsmqResult};
+use std::collections::HashMap;
+use std::time::Duration;
+
+type Decoder<T> = Box<dyn Fn(&[u8]) -> Option<T> + Send + Sync>;
+
+/// Receives messages whose first byte tells their type and decodes them with the decoder registered for that tag,
+/// returning a single type (usually an enum with a variant per tag).
+///
+/// ```rust,ignore
+/// enum Event {
+///     Created(String),
+///     Deleted(u64),
+/// }
+///
+/// let mut receiver = TaggedReceiver::new();
+///
+/// receiver
+///     .register(1, |payload| String::from_utf8(payload.to_vec()).map(Event::Created))
+///     .register(2, |payload| payload.try_into().map(|id| Event::Deleted(u64::from_be_bytes(id))));
+///
+/// let message = receiver.receive_tagged(&mut rsmq, "events", None).await?;
+/// ```
+pub struct TaggedReceiver<T> {
+    decoders: HashMap<u8, Decoder<T>>,
+}
+
+impl<T> TaggedReceiver<T> {
+    pub fn new() -> TaggedReceiver<T> {
+        TaggedReceiver {
+            decoders: HashMap::new(),
+        }
+    }
+
+    /// Registers the decoder for the messages starting with `tag`. The decoder receives the message without the
+    /// tag byte. It replaces any decoder previously registered for the same tag.
+    pub fn register<E, F>(&mut self, tag: u8, decoder: F) -> &mut Self
+    where
+        F: Fn(&[u8]) -> Result<T, E> + Send + Sync + 'static,
+    {
+        self.decoders
+            .insert(tag, Box::new(move |payload| decoder(payload).ok()));
+        self
+    }
+
+    /// Decodes a tagged message. Fails with `RsmqError::CannotDecodeMessage` if the message is empty, there is no
+    /// decoder for its tag or the decoder fails.
+    pub fn decode(&self, bytes: Vec<u8>) -> RsmqResult<T> {
+        let decoded = bytes
+            .split_first()
+            .and_then(|(tag, payload)| self.decoders.get(tag).and_then(|decoder| decoder(payload)));
+
+        decoded.ok_or(RsmqError::CannotDecodeMessage(bytes))
+    }
+
+    /// Same as "receive_message" but the message is decoded by the decoder registered for its first byte
+    pub async fn receive_tagged<R: RsmqConnection + Send>(
+        &self,
+        rsmq: &mut R,
+        qname: &str,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<RsmqMessage<T>>> {
+        let message = match rsmq.receive_message::<Vec<u8>>(qname, hidden).await? {
+            Some(message) => message,
+            None => return Ok(None),
+        };
+
+        Ok(Some(RsmqMessage {
+            message: self.decode(message.message)?,
+            id: message.id,
+            rc: message.rc,
+            fr: message.fr,
+            sent: message.sent,
+            expired: message.expired,
+        }))
+    }
+}
+
+impl<T> Default for TaggedReceiver<T> {
+    fn default() -> TaggedReceiver<T> {
+        TaggedReceiver::new()
+    }
+}
+
+impl<T> std::fmt::Debug for TaggedReceiver<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut tags: Vec<&u8> = self.decoders.keys().collect();
+        tags.sort();
+
+        f.debug_struct("TaggedReceiver")
+            .field("tags", &tags)
+            .finish()
+    }
+}
@@ -0,0 +1,142 @@
+use crate::r#trait::RsmqConnection;
+use crate::time::{MockTime, TimeSource};
+use crate::types::RsmqMessage;
+use crate::RsmqResult;
+use std::time::Duration;
+
+/// Precision of the queue time, which is the current time rounded down to it. A message hidden until a score becomes
+/// visible when the queue time reaches it
+#[cfg(feature = "break-js-comp")]
+const QUEUE_TIME_PRECISION_MS: u64 = 1;
+#[cfg(not(feature = "break-js-comp"))]
+const QUEUE_TIME_PRECISION_MS: u64 = 1000;
+
+/// A message received by a consumer that crashed before deleting it. Used to check, with `MockTime`, that the
+/// message is delivered again once its visibility timeout expires, which is what "at least once delivery" relies on.
+/// The Rsmq instance has to use the same `MockTime` as `RsmqOptions::time_source` and the queue should only contain
+/// the messages of the test.
+///
+/// ```rust,ignore
+/// rsmq.send_message("myqueue", "job", None).await?;
+///
+/// let crash = ConsumerCrash::receive(&mut rsmq, "myqueue", None).await?.unwrap();
+///
+/// // Panics if the message is delivered before its visibility timeout or not delivered after it
+/// let message = crash.assert_redelivered(&mut rsmq, &time, "myqueue").await?;
+///
+/// // Process it with your consumer logic, which must delete it
+/// my_consumer.process(&mut rsmq, message).await?;
+///
+/// assert_eq!(rsmq.get_queue_attributes("myqueue").await?.msgs, 0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ConsumerCrash {
+    id: String,
+    rc: u64,
+    hidden: Duration,
+}
+
+impl ConsumerCrash {
+    /// Receives a message and drops it without deleting it, like a consumer crashing while processing it. `hidden`
+    /// defaults to the queue visibility timeout. Returns None if there was no message to receive.
+    pub async fn receive<R: RsmqConnection + Send>(
+        rsmq: &mut R,
+        qname: &str,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<ConsumerCrash>> {
+        let hidden = match hidden {
+            Some(hidden) => hidden,
+            None => rsmq.get_queue_attributes(qname).await?.vt,
+        };
+
+        let message = rsmq.receive_message::<Vec<u8>>(qname, Some(hidden)).await?;
+
+        Ok(message.map(|message| ConsumerCrash {
+            id: message.id,
+            rc: message.rc,
+            hidden,
+        }))
+    }
+
+    /// Id of the message that was not deleted
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Checks that the message is not delivered while it is hidden, moves `time` to the end of the visibility
+    /// timeout and checks that the message is delivered again, with its receive count increased. Returns the
+    /// redelivered message, which is hidden again for the same visibility timeout.
+    ///
+    /// The end of the visibility timeout is taken from the score stored in Redis, rounded up to the precision of the
+    /// queue time (whole seconds, or milliseconds with `break-js-comp`).
+    ///
+    /// Panics if any of the checks fails.
+    pub async fn assert_redelivered<R: RsmqConnection + Send>(
+        &self,
+        rsmq: &mut R,
+        time: &MockTime,
+        qname: &str,
+    ) -> RsmqResult<RsmqMessage<Vec<u8>>> {
+        let score = rsmq
+            .eval_script(
+                "return redis.call('ZSCORE', KEYS[1], ARGV[1])",
+                &[qname],
+                &[&self.id],
+            )
+            .await?;
+
+        let score = match redis::from_redis_value::<Option<f64>>(&score)? {
+            Some(score) => score as u64,
+            None => panic!("the message {} was deleted", self.id),
+        };
+
+        let visible_at = Duration::from_millis(
+            score.div_ceil(QUEUE_TIME_PRECISION_MS) * QUEUE_TIME_PRECISION_MS,
+        );
+        let almost = visible_at.saturating_sub(Duration::from_micros(1));
+
+        if almost > time.now() {
+            time.set(almost);
+
+            let message = rsmq
+                .receive_message::<Vec<u8>>(qname, Some(self.hidden))
+                .await?;
+
+            assert!(
+                message.is_none(),
+                "a message was delivered while the message {} was still hidden",
+                self.id
+            );
+        }
+
+        if visible_at > time.now() {
+            time.set(visible_at);
+        }
+
+        let message = rsmq
+            .receive_message::<Vec<u8>>(qname, Some(self.hidden))
+            .await?;
+
+        let message = match message {
+            Some(message) => message,
+            None => panic!(
+                "the message {} was not delivered again after its visibility timeout",
+                self.id
+            ),
+        };
+
+        assert_eq!(
+            message.id, self.id,
+            "another message was delivered instead of the message {}",
+            self.id
+        );
+        assert_eq!(
+            message.rc,
+            self.rc + 1,
+            "the receive count of the message {} didn't increase",
+            self.id
+        );
+
+        Ok(message)
+    }
+}
@@ -0,0 +1,54 @@
+use std::fmt::Debug;
+#[cfg(feature = "test-util")]
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Source of the current time used by the library instead of the Redis `TIME` command. Only used when set in
+/// `RsmqOptions::time_source` (`test-util` feature), so tests can control the time of delays and visibility timeouts.
+pub trait TimeSource: Debug + Send + Sync {
+    /// Current time as a duration since the unix epoch
+    fn now(&self) -> Duration;
+}
+
+/// Time source that only moves when `advance` or `set` are called.
+#[cfg(feature = "test-util")]
+#[derive(Debug, Clone)]
+pub struct MockTime(Arc<Mutex<Duration>>);
+
+#[cfg(feature = "test-util")]
+impl MockTime {
+    /// Creates a new mock time starting at `start` (duration since the unix epoch)
+    pub fn new(start: Duration) -> MockTime {
+        MockTime(Arc::new(Mutex::new(start)))
+    }
+
+    /// Moves the time forward
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        *now += duration;
+    }
+
+    /// Sets the current time (duration since the unix epoch)
+    pub fn set(&self, time: Duration) {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner()) = time;
+    }
+}
+
+#[cfg(feature = "test-util")]
+impl Default for MockTime {
+    /// Starts at the current system time
+    fn default() -> MockTime {
+        MockTime::new(
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default(),
+        )
+    }
+}
+
+#[cfg(feature = "test-util")]
+impl TimeSource for MockTime {
+    fn now(&self) -> Duration {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
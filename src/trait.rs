@@ -1,6 +1,13 @@
+#[cfg(feature = "guard")]
+use crate::guard::ReceivedMessage;
+#[cfg(feature = "stream")]
+use crate::rate_limit::RateLimiter;
 use crate::types::RedisBytes;
-use crate::types::{RsmqMessage, RsmqQueueAttributes};
-use crate::RsmqResult;
+use crate::types::{
+    MaxSize, RsmqAuditRecord, RsmqBatchMode, RsmqConfigSummary, RsmqConnectionInfo, RsmqMessage,
+    RsmqMessageMeta, RsmqQueueAttributes, RsmqQueueConfig,
+};
+use crate::{RsmqDecodeError, RsmqResult};
 use core::convert::TryFrom;
 use std::time::Duration;
 
@@ -8,7 +15,7 @@ use std::time::Duration;
 pub trait RsmqConnection {
     /// Change the hidden time of a already sent message.
     ///
-    /// `hidden` has a max time of 9_999_999 for compatibility reasons to this library JS version counterpart
+    /// `hidden` has a max time of 9_999_999 seconds for compatibility reasons to this library JS version counterpart
     async fn change_message_visibility(
         &mut self,
         qname: &str,
@@ -16,56 +23,489 @@ pub trait RsmqConnection {
         hidden: Duration,
     ) -> RsmqResult<()>;
 
+    /// Creates the queue `dest` with the same "vt", "delay" and "maxsize" as the queue `src`. The new queue starts
+    /// empty and with fresh statistics.
+    ///
+    /// Fails with `QueueNotFound` if `src` doesn't exist and with `QueueExists` if `dest` already exists.
+    async fn clone_queue_config(&mut self, src: &str, dest: &str) -> RsmqResult<()>;
+
+    /// Returns the settings this instance is effectively using (namespace, realtime, protocol, defaults, etc).
+    /// Useful to log them or to attach them to a bug report.
+    fn config_summary(&self) -> RsmqConfigSummary;
+
+    /// Returns the Redis server (host, port and db) this instance is connected to, as resolved when connecting. The
+    /// host and port are unknown when the connection was given to the library.
+    fn connection_info(&self) -> RsmqConnectionInfo;
+
+    /// Returns how many messages sent with "send_message_grouped" for this group are still in the queue (not
+    /// deleted yet). Once it reaches zero, the whole group has been processed.
+    async fn count_group(&mut self, qname: &str, group_id: &str) -> RsmqResult<u64>;
+
+    /// Returns the number of messages that can be received right now, the ones neither hidden nor delayed, counted
+    /// with a single ZCOUNT using the Redis time. Unlike subtracting `hiddenmsgs` from `msgs` of the queue
+    /// attributes, it can't mix two different moments, so it is the one to use for scaling workers.
+    async fn count_visible_messages(&mut self, qname: &str) -> RsmqResult<u64>;
+
     /// Creates a new queue. Attributes can be later modified with "set_queue_attributes" method
     ///
     /// hidden: Time the messages will be hidden when they are received with the "receive_message" method. It
-    /// has a max time of 9_999_999 for compatibility reasons to this library JS version counterpart
+    /// has a max time of 9_999_999 seconds for compatibility reasons to this library JS version counterpart
     ///
-    /// delay: Time the messages will be delayed before being delivered
+    /// delay: Time the messages will be delayed before being delivered. It has the same max time as "hidden"
     ///
-    /// maxsize: Maximum size in bytes of each message in the queue. Needs to be between 1024 or 65536 or -1 (unlimited
-    /// size)
+    /// maxsize: Maximum size of each message in the queue. `MaxSize::Bytes` needs to be between 1024 and
+    /// `RsmqOptions::maxsize_ceiling` (65536 by default)
     async fn create_queue(
         &mut self,
         qname: &str,
         hidden: Option<Duration>,
         delay: Option<Duration>,
-        maxsize: Option<i32>,
+        maxsize: Option<MaxSize>,
     ) -> RsmqResult<()>;
 
+    /// Same as "create_queue" but an existing queue is not an error. Returns true if the queue was created and false
+    /// if it already existed (its settings are left as they are). Useful when several services create the same
+    /// queues at startup.
+    async fn create_queue_if_not_exists(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+        delay: Option<Duration>,
+        maxsize: Option<MaxSize>,
+    ) -> RsmqResult<bool>;
+
+    /// Same as "create_queue" but with the settings of `config`. If `config.max_receives` is set, the messages
+    /// received that many times are moved by "receive_message" (and "receive_messages") to the dead-letter queue
+    /// (`config.dead_letter_queue`, "{qname}-dlq" by default) instead of being delivered again. The dead-letter queue
+    /// is created with the default settings if it doesn't exist
+    async fn create_queue_with_config(
+        &mut self,
+        qname: &str,
+        config: &RsmqQueueConfig,
+    ) -> RsmqResult<()>;
+
+    /// Deletes a message using the handle returned by "RsmqMessage::receipt_handle" instead of its id. The handle
+    /// is tied to that receive, so the message is only deleted if it wasn't received again since then. This way a
+    /// slow client doesn't delete a message that became visible again and was received by another client. Returns
+    /// true if the message was deleted. Fails with `RsmqError::InvalidFormat` if the handle is not valid.
+    async fn delete_by_handle(&mut self, qname: &str, handle: &str) -> RsmqResult<bool>;
+
     /// Deletes a message from the queue.
     ///
     /// Important to use when you are using receive_message.
     async fn delete_message(&mut self, qname: &str, id: &str) -> RsmqResult<bool>;
 
+    /// Deletes the message only if its body is still `expected` (usually, the body as it was received), so a
+    /// message replaced meanwhile by someone else is not deleted. Returns true if the message was deleted. The
+    /// comparison and the deletion are atomic.
+    async fn delete_message_if_unchanged(
+        &mut self,
+        qname: &str,
+        id: &str,
+        expected: &[u8],
+    ) -> RsmqResult<bool>;
+
+    /// Same as "delete_message" but, if the message was only partially stored (its id in the queue without its body
+    /// or the body without the id), it cleans what was left and returns `RsmqError::InconsistentState` describing it.
+    async fn delete_message_strict(&mut self, qname: &str, id: &str) -> RsmqResult<bool>;
+
+    /// Same as "delete_message" for several messages of the queue, in a single transaction instead of one round trip
+    /// per message. Useful for acknowledging the messages returned by "receive_messages". Returns, in the same order as
+    /// `ids`, if each message existed and was deleted. The number of ids is limited by `RsmqOptions::max_batch_size`.
+    async fn delete_messages(&mut self, qname: &str, ids: &[&str]) -> RsmqResult<Vec<bool>>;
+
     /// Deletes the queue and all the messages on it
     async fn delete_queue(&mut self, qname: &str) -> RsmqResult<()>;
 
+    /// Deletes the queue only if it has no messages (hidden or delayed messages count too). Returns false, without
+    /// deleting anything, if the queue still has messages. The check and the deletion happen atomically.
+    async fn delete_queue_if_empty(&mut self, qname: &str) -> RsmqResult<bool>;
+
+    /// Same as "delete_queue" but it returns the attributes (settings, stats and message counts) the queue had when it
+    /// was deleted. They are read in the same transaction that deletes the queue, so nothing can change them in
+    /// between.
+    async fn delete_queue_returning_attributes(
+        &mut self,
+        qname: &str,
+    ) -> RsmqResult<RsmqQueueAttributes>;
+
+    /// Runs your own Lua script atomically, like the scripts of the library. For each queue of `qnames`, in order, two
+    /// keys are passed: the sorted set of the messages (`KEYS[1]` for the first queue) and the hash with the queue
+    /// settings and the message bodies (`KEYS[2]` for the first queue). `args` are passed as `ARGV`. The script is
+    /// cached by Redis (EVALSHA) and the raw result is returned. The queues are not checked, so the script has to
+    /// handle the ones that don't exist.
+    async fn eval_script(
+        &mut self,
+        script: &str,
+        qnames: &[&str],
+        args: &[&str],
+    ) -> RsmqResult<redis::Value>;
+
+    /// Calls `f` with every message of the queue, in the order they would be delivered, without receiving them (their
+    /// visibility and receive count don't change). Messages are read from Redis in small pages, so huge queues can be
+    /// exported without loading them in memory. Hidden and delayed messages are only included if `include_hidden` is
+    /// true. Messages changed by other clients during the iteration may be skipped or passed twice.
+    ///
+    /// If `RsmqOptions::max_scan` is set, it stops after that number of messages and returns true (the result is
+    /// truncated). Otherwise it returns false.
+    async fn for_each_message<E, F>(
+        &mut self,
+        qname: &str,
+        include_hidden: bool,
+        f: F,
+    ) -> RsmqResult<bool>
+    where
+        E: TryFrom<RedisBytes, Error: RsmqDecodeError>,
+        F: FnMut(RsmqMessage<E>) + Send;
+
+    /// Returns the highest number of messages the queue had since the last "reset_max_depth" (or since it was
+    /// created), a cheap high-water mark for capacity reports. It is only recorded by the send methods when
+    /// `RsmqOptions::track_max_depth` is enabled; otherwise it is the current number of messages.
+    async fn get_max_depth(&mut self, qname: &str) -> RsmqResult<u64>;
+
+    /// Returns the message with the given id without receiving it, for debugging or for admin tools. Its visibility,
+    /// receive count and first received time don't change; the returned `rc` and `fr` are the values currently stored
+    /// (0 if the message was never received). Returns None if the message is not in the queue (for example, because
+    /// it was deleted).
+    async fn get_message<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        message_id: &str,
+    ) -> RsmqResult<Option<RsmqMessage<E>>>;
+
     /// Returns the queue attributes and statistics
     async fn get_queue_attributes(&mut self, qname: &str) -> RsmqResult<RsmqQueueAttributes>;
 
+    /// Same as "get_queue_attributes" but it fails with `RsmqError::CannotParseVT`, `RsmqError::CannotParseDelay` or
+    /// `RsmqError::CannotParseMaxsize` if the stored value (for example, written by a buggy external tool) is not
+    /// valid, instead of returning it as 0. It always reads the values from Redis, even with `client_side_caching`.
+    async fn get_queue_attributes_checked(
+        &mut self,
+        qname: &str,
+    ) -> RsmqResult<RsmqQueueAttributes>;
+
+    /// Same as "get_queue_attributes" for several queues, in a single transaction and with a single `TIME` call
+    /// instead of one round trip per queue. The result has each queue name with its own result, in the same order as
+    /// `qnames`, so a missing queue (`RsmqError::QueueNotFound`) or an invalid name doesn't fail the whole call. It
+    /// always reads the values from Redis, even with `client_side_caching`. The number of queues is limited by
+    /// `RsmqOptions::max_batch_size`.
+    async fn get_queue_attributes_many(
+        &mut self,
+        qnames: &[&str],
+    ) -> RsmqResult<Vec<(String, RsmqResult<RsmqQueueAttributes>)>>;
+
+    /// Returns the queues in the namespace that have at least one message ready to be received (not hidden nor
+    /// delayed). Useful to decide where to put consumers.
+    async fn list_active_queues(&mut self) -> RsmqResult<Vec<String>>;
+
     /// Returns a list of queues in the namespace
     async fn list_queues(&mut self) -> RsmqResult<Vec<String>>;
 
+    /// Returns every queue in the namespace with its attributes, like calling "get_queue_attributes" for each queue
+    /// returned by "list_queues" but in a single transaction instead of one per queue.
+    async fn list_queues_with_attributes(
+        &mut self,
+    ) -> RsmqResult<Vec<(String, RsmqQueueAttributes)>>;
+
+    /// Returns the messages of the queue as a `Stream`. It calls "receive_message" and, when the queue is empty, waits
+    /// `poll_interval` before trying again. Errors are returned as items and the stream continues (waiting
+    /// `poll_interval` first), so it only stops when dropped. Dropping it while a receive is in flight can leave that
+    /// message hidden until `hidden` passes, like any receive whose result is not processed.
+    #[cfg(feature = "stream")]
+    fn message_stream<'a, E: TryFrom<RedisBytes, Error: RsmqDecodeError> + 'a>(
+        &'a mut self,
+        qname: &'a str,
+        hidden: Option<Duration>,
+        poll_interval: Duration,
+    ) -> impl futures_util::Stream<Item = RsmqResult<RsmqMessage<E>>> + 'a
+    where
+        Self: Sized + Send,
+    {
+        crate::stream::message_stream(self, qname, hidden, poll_interval, None)
+    }
+
+    /// Same as "message_stream" but the messages are received at the rate allowed by `limiter`, whatever the depth of
+    /// the queue. The stream waits for a token before each receive, and the receives that return nothing give it back
+    #[cfg(feature = "stream")]
+    fn message_stream_limited<'a, E: TryFrom<RedisBytes, Error: RsmqDecodeError> + 'a>(
+        &'a mut self,
+        qname: &'a str,
+        hidden: Option<Duration>,
+        poll_interval: Duration,
+        limiter: RateLimiter,
+    ) -> impl futures_util::Stream<Item = RsmqResult<RsmqMessage<E>>> + 'a
+    where
+        Self: Sized + Send,
+    {
+        crate::stream::message_stream(self, qname, hidden, poll_interval, Some(limiter))
+    }
+
+    /// Returns how long the message will stay hidden before being delivered again, so a client processing it can
+    /// decide whether to extend its visibility with "change_message_visibility". Zero if the message is already
+    /// visible and None if it doesn't exist.
+    async fn message_time_remaining(
+        &mut self,
+        qname: &str,
+        id: &str,
+    ) -> RsmqResult<Option<Duration>>;
+
+    /// Moves a message from the queue `from` to the queue `to` atomically, so it is never lost nor in both queues. It
+    /// keeps its id, body, receive count and the rest of its data, and it is delivered after the delay of `to`.
+    /// Returns false if the message is not in `from` (for example, it was deleted). Fails with
+    /// `RsmqError::QueueNotFound` if any of the queues doesn't exist.
+    async fn move_message(&mut self, from: &str, to: &str, message_id: &str) -> RsmqResult<bool>;
+
+    /// Returns the next message that "receive_message" would return, without hiding it: it stays visible and its
+    /// receive count and first received time don't change. The returned `rc` and `fr` are the values currently
+    /// stored (0 if the message was never received). Useful for monitoring.
+    async fn peek_message<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+    ) -> RsmqResult<Option<RsmqMessage<E>>>;
+
+    /// Returns the metadata (id, receive count, timestamps and size) of the next message that "receive_message" would
+    /// return, without transferring its body. The message is not changed: it stays visible and its receive count
+    /// doesn't increase.
+    async fn peek_message_meta(&mut self, qname: &str) -> RsmqResult<Option<RsmqMessageMeta>>;
+
     /// Deletes and returns a message. Be aware that using this you may end with deleted & unprocessed messages.
-    async fn pop_message<E: TryFrom<RedisBytes, Error = Vec<u8>>>(
+    async fn pop_message<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+    ) -> RsmqResult<Option<RsmqMessage<E>>>;
+
+    /// Deletes and returns up to `count` messages in a single atomic operation. It returns fewer messages (or none)
+    /// when the queue doesn't have enough visible ones. Be aware that using this you may end with deleted &
+    /// unprocessed messages.
+    async fn pop_message_batch<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        count: usize,
+    ) -> RsmqResult<Vec<RsmqMessage<E>>>;
+
+    /// Moves the messages of the retry queue (see "retry_message") whose delay is over back to the queue, where they
+    /// are visible right away. It is meant to be called periodically by a single process. Returns how many messages
+    /// were moved.
+    async fn promote_matured_retries(&mut self, qname: &str) -> RsmqResult<u64>;
+
+    /// Deletes every message of the dead-letter queue of `qname` (the one set with `RsmqQueueConfig::dead_letter_queue`,
+    /// or `{qname}-dlq`), keeping the queue.
+    /// Returns how many messages were deleted. Fails with `RsmqError::QueueNotFound` if the dead-letter queue doesn't
+    /// exist.
+    async fn purge_dlq(&mut self, qname: &str) -> RsmqResult<u64>;
+
+    /// Deletes every message of the queue, keeping the queue with its settings (hidden, delay, maxsize, etc) and its
+    /// stats, unlike deleting and creating it again. Returns how many messages were deleted. Fails with
+    /// `RsmqError::QueueNotFound` if the queue doesn't exist.
+    async fn purge_queue(&mut self, qname: &str) -> RsmqResult<u64>;
+
+    /// Returns if the queue exists. Cheaper than "get_queue_attributes" when only the existence matters, and a missing
+    /// queue is `false` instead of `RsmqError::QueueNotFound`.
+    async fn queue_exists(&mut self, qname: &str) -> RsmqResult<bool>;
+
+    /// Returns the records of the audit log of the queue (see `RsmqOptions::audit_log_max_len`) added since the
+    /// `since` timestamp (epoch in milliseconds). Use 0 to read all the records that are still kept.
+    async fn read_audit(&mut self, qname: &str, since: u64) -> RsmqResult<Vec<RsmqAuditRecord>>;
+
+    /// Returns the Redis pubsub channel where, with `RsmqOptions::realtime` enabled, every send publishes the number of
+    /// messages of the queue (`{ns}:rt:{qname}`). Subscribe to it to be notified of new messages.
+    fn realtime_channel(&self, qname: &str) -> String;
+
+    /// Same as "receive_message" but it receives from the dead-letter queue of `qname` (the one set with
+    /// `RsmqQueueConfig::dead_letter_queue`, or `{qname}-dlq`), so the dead-lettered messages can be inspected. Delete
+    /// them with "delete_message" using the dead-letter queue name, or move them back with "replay_from_dlq".
+    async fn receive_from_dlq<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
         &mut self,
         qname: &str,
+        hidden: Option<Duration>,
     ) -> RsmqResult<Option<RsmqMessage<E>>>;
 
     /// Returns a message. The message stays hidden for some time (defined by "hidden" argument or the queue
     /// settings). After that time, the message will be redelivered. In order to avoid the redelivery, you need to use
     /// the "delete_message" after this function.
     ///
-    /// `hidden` has a max time of 9_999_999 for compatibility reasons to this library JS version counterpart.
-    async fn receive_message<E: TryFrom<RedisBytes, Error = Vec<u8>>>(
+    /// `hidden` has a max time of 9_999_999 seconds for compatibility reasons to this library JS version counterpart.
+    async fn receive_message<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<RsmqMessage<E>>>;
+
+    /// Same as "receive_message" but, if the queue has no visible message, it waits for one up to `timeout`. Returns
+    /// None if no message could be received in that time. While waiting it tries again and again, a few
+    /// milliseconds apart at first and up to half a second apart, so it doesn't need the realtime notifications.
+    /// When several clients wait on the same queue, each message is received by only one of them and the rest keep
+    /// waiting. The connection is held while waiting (for `PooledRsmq`, one connection of the pool).
+    async fn receive_message_blocking<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
         &mut self,
         qname: &str,
         hidden: Option<Duration>,
+        timeout: Duration,
     ) -> RsmqResult<Option<RsmqMessage<E>>>;
 
+    /// Same as "receive_message" but, if the queue doesn't exist, it is created with `config` first instead of
+    /// failing with `QueueNotFound`. It is safe to call it from several clients at the same time: only one of them
+    /// creates the queue and an existing queue is never modified.
+    async fn receive_message_ensuring<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        config: &RsmqQueueConfig,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<RsmqMessage<E>>>;
+
+    /// Same as "receive_message" but the message is returned inside a `ReceivedMessage`, which has to be settled
+    /// with "ack" (deleting it) or "nack" (making it visible again). Dropping it without settling it is logged (with
+    /// the `tracing` feature) and, depending on `RsmqOptions::unacked_policy`, the message is made visible again. The
+    /// guard keeps a clone of this instance, so it can be moved to another task.
+    #[cfg(feature = "guard")]
+    async fn receive_message_guarded<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<ReceivedMessage<E, Self>>>
+    where
+        Self: Clone + Send + Sized + 'static,
+    {
+        let policy = self.config_summary().unacked_policy;
+
+        let message = self.receive_message::<E>(qname, hidden).await?;
+
+        Ok(message.map(|message| ReceivedMessage::new(self.clone(), qname, message, policy)))
+    }
+
+    /// Same as "receive_message" but the message is deserialized from JSON (see "send_message_json"). If it cannot be
+    /// deserialized, it fails with `RsmqError::CannotDecodeJson`, which contains the received bytes and the
+    /// serde_json error. The message was received anyway, so it will be delivered again after the hidden time.
+    #[cfg(feature = "serde")]
+    async fn receive_message_json<M: serde::de::DeserializeOwned>(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<RsmqMessage<M>>>;
+
+    /// Same as "receive_message" but it also returns the OpenTelemetry context that "send_message_traced" injected
+    /// in the message, so the consumer spans can be linked to the producer ones. If the message has no trace context,
+    /// the returned context is the current one.
+    #[cfg(feature = "otel")]
+    async fn receive_message_traced<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<(RsmqMessage<E>, opentelemetry::Context)>>;
+
+    /// Same as "receive_message" but it receives the first visible message whose attribute `attribute` (see
+    /// "send_message_with_attributes") is `value`. The filter runs in Redis, so the messages that don't match are not
+    /// transferred nor hidden, and they stay available for other consumers.
+    async fn receive_message_where<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        attribute: &str,
+        value: &str,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<RsmqMessage<E>>>;
+
+    /// Same as "receive_message" but it also returns the queue attributes, fetched in the same round trip and
+    /// transaction. Useful for workers that adapt to the queue settings (like the hidden time) when they start. A
+    /// missing queue returns `QueueNotFound` even with `missing_queue_as_empty`, as there are no attributes to return.
+    async fn receive_message_with_attributes<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<(Option<RsmqMessage<E>>, RsmqQueueAttributes)>;
+
+    /// Same as "receive_message" but it also returns the queue where the reply to the message has to be sent, if it
+    /// was sent with "send_message_with_reply_to". Send the reply with "reply".
+    async fn receive_message_with_reply_to<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<(RsmqMessage<E>, Option<String>)>>;
+
+    /// Same as "receive_message" but it also returns the total received count of the queue (`totalrecv`) right after
+    /// this receive, taken atomically with it. It can be used as a delivery sequence number: each receive gets a
+    /// bigger one. The counter is shared by every receive and pop of the queue, so it is only gap-free if all of them
+    /// are done with this method.
+    async fn receive_message_with_sequence<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Option<(RsmqMessage<E>, u64)>>;
+
+    /// Same as "receive_message" but it receives up to `count` visible messages in a single round trip, hiding all of
+    /// them for `hidden` (or the queue visibility timeout). Returns fewer messages, or none, if the queue doesn't have
+    /// enough visible messages. The number of messages is limited by `RsmqOptions::max_batch_size`. If a message
+    /// cannot be decoded into `E`, it fails with the decoding error of that message.
+    async fn receive_messages<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qname: &str,
+        hidden: Option<Duration>,
+        count: usize,
+    ) -> RsmqResult<Vec<RsmqMessage<E>>>;
+
+    /// Receives up to `max_total` messages from several queues in a single call. The queues are visited in turns
+    /// (one message from each queue per round) so a busy queue doesn't starve the others. Each message is returned
+    /// with the name of its queue. If `hidden` is None, each queue uses its own "vt" setting. If a queue doesn't exist,
+    /// it fails with `RsmqError::MissingQueue` naming it, or the queue is skipped with
+    /// `RsmqOptions::missing_queue_as_empty`.
+    async fn receive_multi<E: TryFrom<RedisBytes, Error: RsmqDecodeError>>(
+        &mut self,
+        qnames: &[&str],
+        max_total: usize,
+        hidden: Option<Duration>,
+    ) -> RsmqResult<Vec<(String, RsmqMessage<E>)>>;
+
+    /// Adds `count` to the "totalsent" stat of the queue (and updates its "modified" time) without sending anything.
+    /// Useful to keep the stats right when some messages are enqueued by other means.
+    async fn record_external_send(&mut self, qname: &str, count: u64) -> RsmqResult<()>;
+
+    /// Renames the queue atomically, keeping its messages, their receive counts and the queue stats. Fails with
+    /// `RsmqError::QueueExists` if there is already a queue named `to`.
+    async fn rename_queue(&mut self, from: &str, to: &str) -> RsmqResult<()>;
+
+    /// Moves a message from the dead-letter queue of `qname` (the one set with `RsmqQueueConfig::dead_letter_queue`, or
+    /// `{qname}-dlq`) back to `qname`, where it is visible right away. The message keeps its id, but its receive count
+    /// starts again from 0, so it isn't moved back to the dead-letter queue on its next receive. Returns false if the
+    /// message is not in the dead-letter queue.
+    async fn replay_from_dlq(&mut self, qname: &str, id: &str) -> RsmqResult<bool>;
+
+    /// Sends `payload` to the `reply_to` queue returned by "receive_message_with_reply_to". The reply is a normal
+    /// message, so the requester receives it with any of the receive methods.
+    async fn reply<E: Into<RedisBytes> + Send>(
+        &mut self,
+        reply_to: &str,
+        payload: E,
+    ) -> RsmqResult<String>;
+
+    /// Makes a received message visible again right away, so it can be received by another client. Same as
+    /// "change_message_visibility" with a zero hidden time. The receive count of the message is kept. Returns false
+    /// if the message doesn't exist
+    async fn requeue_message(&mut self, qname: &str, message_id: &str) -> RsmqResult<bool>;
+
+    /// Same as "requeue_message" but using the handle returned by "RsmqMessage::receipt_handle". As with
+    /// "delete_by_handle", the message is only made visible if it wasn't received again since then. Returns false if
+    /// the message doesn't exist or was received again. Fails with `RsmqError::InvalidFormat` if the handle is not
+    /// valid.
+    async fn requeue_by_handle(&mut self, qname: &str, handle: &str) -> RsmqResult<bool>;
+
+    /// Returns the maximum depth of the queue (see "get_max_depth") and starts recording it again from the current
+    /// number of messages.
+    async fn reset_max_depth(&mut self, qname: &str) -> RsmqResult<u64>;
+
+    /// Moves a failed message to the retry queue `{qname}-retry` (created with the default settings if it doesn't
+    /// exist), where it stays delayed for `base * 2^attempt` (capped to the maximum delay of 9,999,999 seconds). Use
+    /// "promote_matured_retries" to move the messages back once their delay is over. The message keeps its id and
+    /// receive count. Returns false if the message wasn't found.
+    async fn retry_message(
+        &mut self,
+        qname: &str,
+        id: &str,
+        attempt: u32,
+        base: Duration,
+    ) -> RsmqResult<bool>;
+
     /// Sends a message to the queue. The message will be delayed some time (controlled by the "delayed" argument or
-    /// the queue settings) before being delivered to a client.
+    /// the queue settings) before being delivered to a client. The delay has a max time of 9_999_999 seconds, like in
+    /// the queue settings.
     async fn send_message<E: Into<RedisBytes> + Send>(
         &mut self,
         qname: &str,
@@ -73,21 +513,192 @@ pub trait RsmqConnection {
         delay: Option<Duration>,
     ) -> RsmqResult<String>;
 
+    /// Same as "send_message" but the message is rejected with `RsmqError::DuplicatedMessage` while another message
+    /// sent with the same deduplication id is still in the queue (until it's deleted or popped). Once that message
+    /// is gone, the deduplication id can be used again.
+    async fn send_message_deduplicated<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        deduplication_id: &str,
+        message: E,
+        delay: Option<Duration>,
+    ) -> RsmqResult<String>;
+
+    /// Same as "send_message" but the message is tracked as part of a group (for example, the sub tasks of a
+    /// bigger job). Use "count_group" to know how many messages of the group are still in the queue.
+    async fn send_message_grouped<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        group_id: &str,
+        message: E,
+        delay: Option<Duration>,
+    ) -> RsmqResult<String>;
+
+    /// Same as "send_message" but the message is only sent if the queue has less than `max_depth` messages (visible
+    /// or not), so producers can stop adding work to a queue that is already too deep. The check and the send are
+    /// done atomically. Returns None, without sending anything, if the queue is at or over `max_depth`.
+    async fn send_message_if_below<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        message: E,
+        delay: Option<Duration>,
+        max_depth: u64,
+    ) -> RsmqResult<Option<String>>;
+
+    /// Same as "send_message" but the message is any value serializable with serde, stored as JSON. Use
+    /// "receive_message_json" to deserialize it. Fails with `RsmqError::CannotEncodeJson` if serde_json cannot
+    /// serialize it.
+    #[cfg(feature = "serde")]
+    async fn send_message_json<M: serde::Serialize + Sync>(
+        &mut self,
+        qname: &str,
+        message: &M,
+        delay: Option<Duration>,
+    ) -> RsmqResult<String>;
+
+    /// Same as "send_message" but messages sent with the same ordering key are delivered one at a time and in the
+    /// order they were sent: a message is not delivered while an earlier message with the same key exists (it is
+    /// delayed or it was received and not deleted yet). Messages with different keys, or without key, are delivered
+    /// in parallel as usual. Be sure to delete the messages once processed, otherwise the key stays blocked until the
+    /// message is redelivered.
+    async fn send_message_ordered<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        key: &str,
+        message: E,
+        delay: Option<Duration>,
+    ) -> RsmqResult<String>;
+
+    /// Same as "send_message" but it takes the message by reference, so a big buffer that the caller still needs
+    /// doesn't have to be cloned. The bytes are written directly into the Redis command.
+    async fn send_message_ref(
+        &mut self,
+        qname: &str,
+        message: &[u8],
+        delay: Option<Duration>,
+    ) -> RsmqResult<String>;
+
+    /// Same as "send_message" but it injects the current OpenTelemetry context (the `traceparent` header) into the
+    /// message. Use "receive_message_traced" to get it back on the consumer side.
+    #[cfg(feature = "otel")]
+    async fn send_message_traced<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        message: E,
+        delay: Option<Duration>,
+    ) -> RsmqResult<String>;
+
+    /// Same as "send_message" but it stores `attributes` (name and value pairs) with the message. The consumers can
+    /// receive only the messages with a given attribute value with "receive_message_where".
+    async fn send_message_with_attributes<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        message: E,
+        delay: Option<Duration>,
+        attributes: &[(&str, &str)],
+    ) -> RsmqResult<String>;
+
+    /// Same as "send_message" but the message has a deadline, `lifetime` after it is sent. It is not deleted nor
+    /// hidden once the deadline passes: it is delivered as usual, but with `expired` set to true in the received
+    /// `RsmqMessage`, so the handler can decide what to do with late messages (for example, log and delete them).
+    async fn send_message_with_deadline<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        message: E,
+        delay: Option<Duration>,
+        lifetime: Duration,
+    ) -> RsmqResult<String>;
+
+    /// Same as "send_message" but it also stores `reply_to`, the name of the queue where the consumer has to send the
+    /// reply. The consumer gets it with "receive_message_with_reply_to". Usually each requester has its own queue for
+    /// the replies.
+    async fn send_message_with_reply_to<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        message: E,
+        delay: Option<Duration>,
+        reply_to: &str,
+    ) -> RsmqResult<String>;
+
+    /// Same as "send_message" but the message becomes visible at `score`, the exact score (milliseconds since epoch)
+    /// of the message in the queue sorted set, instead of after a delay. Useful to reproduce schedules computed by
+    /// another RSMQ compatible producer.
+    ///
+    /// This is an advanced method: the score is used as it is, without any check, and it is up to the caller to keep
+    /// it consistent with the Redis clock, which is the one used to decide when a message is visible.
+    async fn send_message_with_score<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        message: E,
+        score: u64,
+    ) -> RsmqResult<String>;
+
+    /// Sends several messages in a single transaction (one round trip instead of one per message), each one with its
+    /// own delay (None uses the queue delay). Returns the ids of the messages in the same order. Nothing is sent if
+    /// any of the messages is invalid, for example bigger than the queue maxsize (`RsmqError::MessageTooLong`). The
+    /// number of messages is limited by `RsmqOptions::max_batch_size`.
+    async fn send_messages<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        messages: Vec<(E, Option<Duration>)>,
+    ) -> RsmqResult<Vec<String>>;
+
+    /// Same as "send_messages" but with `RsmqBatchMode::BestEffort` the invalid messages (like the ones bigger than
+    /// the queue maxsize) are skipped instead of failing the whole batch. The valid ones are sent in a single
+    /// transaction. The result has the id of each sent message, or the error of each skipped one, in the same order as
+    /// `messages`. With `RsmqBatchMode::AllOrNothing` it behaves as "send_messages".
+    async fn send_messages_with_mode<E: Into<RedisBytes> + Send>(
+        &mut self,
+        qname: &str,
+        messages: Vec<(E, Option<Duration>)>,
+        mode: RsmqBatchMode,
+    ) -> RsmqResult<Vec<RsmqResult<String>>>;
+
+    /// Sets a field of the queue settings only if it isn't set yet, so a default can be rolled out without
+    /// overwriting the value chosen by an operator. Returns whether the value was applied. The settings used by the
+    /// library are validated: "vt" and "delay" (in milliseconds, up to 9_999_999 seconds), "maxsize", "maxrc" (the
+    /// receives before moving a message to the dead-letter queue) and "dlq". The fields maintained by the library
+    /// ("created", "modified", "totalrecv" and "totalsent") are rejected with `RsmqError::InvalidFormat`.
+    async fn set_queue_attribute_if_absent(
+        &mut self,
+        qname: &str,
+        field: &str,
+        value: &str,
+    ) -> RsmqResult<bool>;
+
     /// Modify the queue attributes. Keep in mind that "hidden" and "delay" can be overwritten when the message
     /// is sent. "hidden" can be changed by the method "change_message_visibility"
     ///
     /// hidden: Time the messages will be hidden when they are received with the "receive_message" method. It
-    /// has a max time of 9_999_999 for compatibility reasons to this library JS version counterpart
+    /// has a max time of 9_999_999 seconds for compatibility reasons to this library JS version counterpart
     ///
-    /// delay: Time the messages will be delayed before being delivered
+    /// delay: Time the messages will be delayed before being delivered. It has the same max time as "hidden"
     ///
-    /// maxsize: Maximum size in bytes of each message in the queue. Needs to be between 1024 or 65536 or -1 (unlimited
-    /// size)
+    /// maxsize: Maximum size of each message in the queue. `MaxSize::Bytes` needs to be between 1024 and
+    /// `RsmqOptions::maxsize_ceiling` (65536 by default)
     async fn set_queue_attributes(
         &mut self,
         qname: &str,
         hidden: Option<Duration>,
         delay: Option<Duration>,
-        maxsize: Option<i64>,
+        maxsize: Option<MaxSize>,
     ) -> RsmqResult<RsmqQueueAttributes>;
+
+    /// Swaps the delivery order of two messages of the queue, keeping their ids and receive counts. Returns false
+    /// (and changes nothing) if any of the messages doesn't exist anymore or is hidden because a client received it
+    /// and is still processing it.
+    async fn swap_message_order(&mut self, qname: &str, id_a: &str, id_b: &str)
+        -> RsmqResult<bool>;
+
+    /// Moves to the `dlq` queue the messages of `qname` that were sent more than `older_than` ago and are visible
+    /// (messages being processed by a client are left alone). Useful to get rid of messages that keep coming back
+    /// because no consumer manages to process them, based on their age instead of their receive count. The messages
+    /// keep their id and receive count and are visible right away in `dlq`, which must exist. Returns how many
+    /// messages were moved.
+    async fn sweep_stale_to_dlq(
+        &mut self,
+        qname: &str,
+        older_than: Duration,
+        dlq: &str,
+    ) -> RsmqResult<u64>;
 }
@@ -83,8 +83,28 @@ pub struct TestContext {
 
 impl TestContext {
     pub fn new() -> TestContext {
-        let server = RedisServer::new();
+        TestContext::with_server(RedisServer::new())
+    }
+
+    /// Same as "new" but the server only listens on a Unix socket
+    #[allow(dead_code)]
+    pub fn new_unix() -> TestContext {
+        let path = std::env::temp_dir().join(format!(
+            "rsmq-test-{}-{}.sock",
+            process::id(),
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+
+        TestContext::with_server(RedisServer::new_with_addr(
+            redis::ConnectionAddr::Unix(path),
+            |cmd| cmd.spawn().expect("Error executing redis-server"),
+        ))
+    }
 
+    fn with_server(server: RedisServer) -> TestContext {
         let client = redis::Client::open(redis::ConnectionInfo {
             addr: server.get_client_addr().clone(),
             redis: redis::RedisConnectionInfo {
@@ -113,7 +133,7 @@ impl TestContext {
                 }
             }
         }
-        redis::cmd("FLUSHDB").execute(&mut con);
+        redis::cmd("FLUSHDB").exec(&mut con).unwrap();
 
         TestContext { server, client }
     }